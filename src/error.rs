@@ -146,6 +146,50 @@ pub enum InstrumentError {
     #[error("authentication failure: {0}")]
     AuthenticationFailure(String),
 
+    /// There is no connection profile with the given name.
+    #[error("unknown connection profile \"{name}\"")]
+    UnknownProfile {
+        /// The name of the profile that was requested
+        name: String,
+    },
+
+    /// There is no managed instrument with the given name.
+    #[error("unknown instrument \"{name}\"")]
+    UnknownInstrument {
+        /// The name of the instrument that was requested
+        name: String,
+    },
+
+    /// None of the registered drivers support the instrument.
+    #[error("no driver supports the instrument model \"{model}\"")]
+    UnsupportedInstrument {
+        /// The model of the instrument
+        model: String,
+    },
+
+    /// A switch channel or channel list is not valid for the installed cards.
+    #[error("invalid channel \"{channel}\": {details}")]
+    InvalidChannel {
+        /// The channel, channel list or pattern name
+        channel: String,
+        /// Why the channel is not valid
+        details: String,
+    },
+
+    /// An IEEE 488.2 block could not be read or written.
+    #[error("block transfer error: {details}")]
+    BlockError {
+        /// What went wrong with the block
+        details: String,
+    },
+
+    /// An operation didn't finish before its timeout.
+    #[error("timed out waiting for {details}")]
+    Timeout {
+        /// What was being waited for
+        details: String,
+    },
+
     /// An uncategorized error.
     #[error("{0}")]
     Other(String),
@@ -1,24 +1,27 @@
 //! Trait that allows aborting current operation on the instrument.
 
-use std::io::Write;
+use std::io::{Read, Write};
 
-use crate::error::Result;
+use crate::{
+    error::Result,
+    instrument::{wait_complete, COMPLETE_TIMEOUT},
+};
 
 /// The current operation on the [`Instrument`] can be aborted.
 pub trait Abort
 where
-    Self: Write,
+    Self: Read + Write,
 {
     /// Abort current operation on the instrument.
     ///
     /// # Notes
-    /// - Abort current operation on the instrument using 'abort'.
+    /// - Abort current operation on the instrument using 'abort', then wait for it
+    ///   to finish with [`wait_complete`].
     /// # Errors
     /// Returns an [`InstrumentError`] if any errors occurred.
     fn abort(&mut self) -> Result<()> {
         self.write_all(b"abort\n")?;
         self.flush()?;
-        //Do we need to wait?
-        Ok(())
+        wait_complete(self, COMPLETE_TIMEOUT)
     }
 }
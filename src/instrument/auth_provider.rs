@@ -0,0 +1,320 @@
+//! A trait that allows the application to supply the credentials used by
+//! [`Login::login`](crate::instrument::Login::login), instead of prompting on the
+//! command line.
+//!
+//! [`Authentication`] is itself an [`AuthProvider`] and provides the built-in
+//! command line prompt ([`Authentication::Prompt`]) and keyring
+//! ([`Authentication::Keyring`]) credentials. [`EnvProvider`] reads environment
+//! variables and [`CallbackProvider`] calls back into the application. Any provider
+//! can be used to log in with [`Authentication::provider`]:
+//!
+//! ```no_run
+//! use tsp_toolkit_kic_lib::instrument::{
+//!     auth_provider::CallbackProvider, authenticate::Authentication,
+//! };
+//!
+//! let auth = Authentication::provider(
+//!     CallbackProvider::new(|| Ok(Some("secret".to_string())))
+//!         .on_rejected(|| eprintln!("the password was rejected")),
+//! );
+//! ```
+
+use std::{
+    fmt::Debug,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+use crate::{error::Result, instrument::info::InstrumentInfo, InstrumentError};
+
+/// Provides the credentials to log into an instrument and is told whether they were
+/// accepted.
+pub trait AuthProvider: Send {
+    /// The username to log in with, if any.
+    ///
+    /// # Errors
+    /// Any errors that occur while getting the username.
+    fn username(&mut self) -> Result<Option<String>>;
+
+    /// The password to log in with, if any.
+    ///
+    /// # Errors
+    /// Any errors that occur while getting the password.
+    fn password(&mut self) -> Result<Option<String>>;
+
+    /// Whether the provider needs to know which instrument is being logged into
+    /// (see [`AuthProvider::on_identified`]) before it is asked for credentials.
+    fn needs_identity(&self) -> bool {
+        false
+    }
+
+    /// Called with the instrument's information before the credentials are
+    /// requested, if [`AuthProvider::needs_identity`].
+    ///
+    /// # Errors
+    /// Any errors that occur while handling the information (e.g. looking up saved
+    /// credentials).
+    fn on_identified(&mut self, info: &InstrumentInfo) -> Result<()> {
+        let _ = info;
+        Ok(())
+    }
+
+    /// The instrument accepted the credentials.
+    ///
+    /// # Errors
+    /// Any errors that occur while handling the success (e.g. saving the
+    /// credentials).
+    fn on_success(&mut self, info: &InstrumentInfo) -> Result<()> {
+        let _ = info;
+        Ok(())
+    }
+
+    /// The instrument rejected the credentials.
+    ///
+    /// # Errors
+    /// Any errors that occur while handling the rejection.
+    fn on_rejected(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// An [`AuthProvider`] that can be cloned and stored in an [`Authentication`].
+/// Clones share the same provider, and are only equal to each other.
+#[derive(Clone)]
+pub struct SharedProvider(Arc<Mutex<dyn AuthProvider>>);
+
+impl SharedProvider {
+    /// Share the given provider.
+    pub fn new(provider: impl AuthProvider + 'static) -> Self {
+        Self(Arc::new(Mutex::new(provider)))
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut dyn AuthProvider) -> Result<T>) -> Result<T> {
+        let mut provider = self.0.lock().map_err(|_| {
+            InstrumentError::AuthenticationFailure("credential provider is poisoned".to_string())
+        })?;
+        let result = f(&mut *provider);
+        drop(provider);
+        result
+    }
+}
+
+impl AuthProvider for SharedProvider {
+    fn username(&mut self) -> Result<Option<String>> {
+        self.with(|p| p.username())
+    }
+
+    fn password(&mut self) -> Result<Option<String>> {
+        self.with(|p| p.password())
+    }
+
+    fn needs_identity(&self) -> bool {
+        self.0.lock().is_ok_and(|p| p.needs_identity())
+    }
+
+    fn on_identified(&mut self, info: &InstrumentInfo) -> Result<()> {
+        self.with(|p| p.on_identified(info))
+    }
+
+    fn on_success(&mut self, info: &InstrumentInfo) -> Result<()> {
+        self.with(|p| p.on_success(info))
+    }
+
+    fn on_rejected(&mut self) -> Result<()> {
+        self.with(|p| p.on_rejected())
+    }
+}
+
+impl Debug for SharedProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedProvider")
+    }
+}
+
+impl PartialEq for SharedProvider {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SharedProvider {}
+
+impl Hash for SharedProvider {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).cast::<()>().hash(state);
+    }
+}
+
+/// Reads the credentials from environment variables, for services without a user
+/// to prompt.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EnvProvider {
+    username_var: String,
+    password_var: String,
+}
+
+impl EnvProvider {
+    /// The default variable that holds the username.
+    pub const USERNAME_VAR: &'static str = "TSP_TOOLKIT_USERNAME";
+    /// The default variable that holds the password.
+    pub const PASSWORD_VAR: &'static str = "TSP_TOOLKIT_PASSWORD";
+
+    /// Read the credentials from the given variables.
+    pub fn new(username_var: impl Into<String>, password_var: impl Into<String>) -> Self {
+        Self {
+            username_var: username_var.into(),
+            password_var: password_var.into(),
+        }
+    }
+}
+
+impl Default for EnvProvider {
+    fn default() -> Self {
+        Self::new(Self::USERNAME_VAR, Self::PASSWORD_VAR)
+    }
+}
+
+impl AuthProvider for EnvProvider {
+    /// The username is optional since most instruments only use a password.
+    fn username(&mut self) -> Result<Option<String>> {
+        Ok(std::env::var(&self.username_var).ok())
+    }
+
+    fn password(&mut self) -> Result<Option<String>> {
+        std::env::var(&self.password_var).map(Some).map_err(|_| {
+            InstrumentError::AuthenticationFailure(format!("{} is not set", self.password_var))
+        })
+    }
+}
+
+type Callback<T> = Box<dyn FnMut() -> Result<Option<T>> + Send>;
+type SuccessCallback = Box<dyn FnMut(&InstrumentInfo) + Send>;
+
+/// Calls back into the application for the credentials, e.g. to show a dialog.
+pub struct CallbackProvider {
+    username: Option<Callback<String>>,
+    password: Callback<String>,
+    on_success: Option<SuccessCallback>,
+    on_rejected: Option<Box<dyn FnMut() + Send>>,
+}
+
+impl CallbackProvider {
+    /// Get the password from `password`.
+    pub fn new(password: impl FnMut() -> Result<Option<String>> + Send + 'static) -> Self {
+        Self {
+            username: None,
+            password: Box::new(password),
+            on_success: None,
+            on_rejected: None,
+        }
+    }
+
+    /// Get the username from `username`. Without it, no username is used.
+    #[must_use]
+    pub fn with_username(
+        mut self,
+        username: impl FnMut() -> Result<Option<String>> + Send + 'static,
+    ) -> Self {
+        self.username = Some(Box::new(username));
+        self
+    }
+
+    /// Call `f` when the instrument accepts the credentials.
+    #[must_use]
+    pub fn on_success(mut self, f: impl FnMut(&InstrumentInfo) + Send + 'static) -> Self {
+        self.on_success = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` when the instrument rejects the credentials.
+    #[must_use]
+    pub fn on_rejected(mut self, f: impl FnMut() + Send + 'static) -> Self {
+        self.on_rejected = Some(Box::new(f));
+        self
+    }
+}
+
+impl Debug for CallbackProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CallbackProvider")
+    }
+}
+
+impl AuthProvider for CallbackProvider {
+    fn username(&mut self) -> Result<Option<String>> {
+        self.username.as_mut().map_or(Ok(None), |f| f())
+    }
+
+    fn password(&mut self) -> Result<Option<String>> {
+        (self.password)()
+    }
+
+    fn on_success(&mut self, info: &InstrumentInfo) -> Result<()> {
+        if let Some(f) = self.on_success.as_mut() {
+            f(info);
+        }
+        Ok(())
+    }
+
+    fn on_rejected(&mut self) -> Result<()> {
+        if let Some(f) = self.on_rejected.as_mut() {
+            f();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::{
+        instrument::{authenticate::Authentication, info::InstrumentInfo},
+        InstrumentError,
+    };
+
+    use super::{AuthProvider, CallbackProvider, EnvProvider};
+
+    #[test]
+    fn callback_provider_through_authentication() {
+        let rejected = Arc::new(AtomicUsize::new(0));
+        let count = Arc::clone(&rejected);
+        let mut auth = Authentication::provider(
+            CallbackProvider::new(|| Ok(Some("secret".to_string())))
+                .with_username(|| Ok(Some("admin".to_string())))
+                .on_rejected(move || {
+                    count.fetch_add(1, Ordering::SeqCst);
+                }),
+        );
+
+        assert_eq!(auth.read_username().unwrap().as_deref(), Some("admin"));
+        assert_eq!(auth.read_password().unwrap().as_deref(), Some("secret"));
+        auth.on_rejected().unwrap();
+        auth.on_success(&InstrumentInfo::default()).unwrap();
+        assert_eq!(rejected.load(Ordering::SeqCst), 1);
+
+        assert_eq!(auth.clone(), auth);
+        assert_ne!(
+            auth,
+            Authentication::provider(CallbackProvider::new(|| Ok(None)))
+        );
+        assert!(serde_json::to_string(&auth).is_err());
+    }
+
+    #[test]
+    fn env_provider() {
+        std::env::set_var("KIC_LIB_TEST_ENV_PASSWORD", "from-env");
+        let mut provider =
+            EnvProvider::new("KIC_LIB_TEST_ENV_USERNAME", "KIC_LIB_TEST_ENV_PASSWORD");
+
+        assert_eq!(provider.username().unwrap(), None);
+        assert_eq!(provider.password().unwrap().as_deref(), Some("from-env"));
+        assert!(matches!(
+            EnvProvider::new("", "KIC_LIB_TEST_ENV_MISSING").password(),
+            Err(InstrumentError::AuthenticationFailure(_))
+        ));
+    }
+}
@@ -1,11 +1,16 @@
 // Authenticate functionality of the instrument.
 
-#[cfg(test)]
-use keyring::{mock, set_default_credential_builder};
-
-use crate::{model::Model, InstrumentError};
+use crate::{
+    instrument::{
+        auth_provider::{AuthProvider, SharedProvider},
+        credential_store::{CredentialStore, SavedCredential},
+        info::InstrumentInfo,
+    },
+    model::Model,
+    InstrumentError,
+};
 
-const SERVICE_NAME: &str = "tsp-toolkit";
+pub(crate) const SERVICE_NAME: &str = "tsp-toolkit";
 
 /// An enum that provides the expected functionality for authentication into an instrument.
 ///
@@ -30,15 +35,126 @@ pub enum Authentication {
     Keyring { id: String },
     /// No authentication is required, don't try to use any.
     NoAuth,
+    /// Get the credentials from an [`AuthProvider`] supplied by the application.
+    Provider(SharedProvider),
+    /// Look up the saved keyring credential for the instrument when logging in, and
+    /// prompt if there isn't one. Credentials that the instrument rejects are removed
+    /// from the keyring. Use [`Authentication::auto`] to create it.
+    Auto(AutoAuth),
+}
+
+/// The state of [`Authentication::Auto`] for the instrument being logged into.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct AutoAuth {
+    /// The id of the saved credential that was found for the instrument
+    saved: Option<String>,
+    /// [`Authentication::Keyring`] for the saved credential, otherwise
+    /// [`Authentication::Prompt`]
+    auth: Box<Authentication>,
+}
+
+impl Default for AutoAuth {
+    fn default() -> Self {
+        Self {
+            saved: None,
+            auth: Box::new(Authentication::Prompt),
+        }
+    }
+}
+
+impl AutoAuth {
+    fn identify(
+        &mut self,
+        store: &mut CredentialStore,
+        info: &InstrumentInfo,
+    ) -> Result<(), InstrumentError> {
+        *self = store.find(info)?.map_or_else(Self::default, |saved| Self {
+            auth: Box::new(saved.authentication()),
+            saved: Some(saved.id),
+        });
+        Ok(())
+    }
+
+    fn reject(&mut self, store: &mut CredentialStore) -> Result<(), InstrumentError> {
+        if let Some(id) = self.saved.take() {
+            store.delete(&id)?;
+        }
+        *self = Self::default();
+        Ok(())
+    }
+}
+
+/// The serialized form of [`Authentication`]. Only the variants that do not carry a
+/// plaintext credential can be represented.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SerializedAuthentication {
+    Prompt,
+    Keyring {
+        id: String,
+    },
+    #[serde(rename = "none")]
+    NoAuth,
+    Auto,
+}
+
+/// Only [`Authentication::Prompt`], [`Authentication::Keyring`],
+/// [`Authentication::NoAuth`] and [`Authentication::Auto`] can be serialized. Attempting to serialize a variant
+/// that holds a plaintext credential is an error so that passwords are never written
+/// to disk; save them to the keyring and serialize the resulting
+/// [`Authentication::Keyring`] instead.
+impl serde::Serialize for Authentication {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let auth = match self {
+            Self::Prompt => SerializedAuthentication::Prompt,
+            Self::Keyring { id } => SerializedAuthentication::Keyring { id: id.clone() },
+            Self::NoAuth => SerializedAuthentication::NoAuth,
+            Self::Auto(_) => SerializedAuthentication::Auto,
+            Self::PromptPartial { .. } | Self::Credential { .. } => {
+                return Err(serde::ser::Error::custom(
+                    "plaintext credentials cannot be serialized, use a keyring entry instead",
+                ));
+            }
+            Self::Provider(_) => {
+                return Err(serde::ser::Error::custom(
+                    "credential providers cannot be serialized",
+                ));
+            }
+        };
+        auth.serialize(serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Authentication {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match SerializedAuthentication::deserialize(deserializer)? {
+            SerializedAuthentication::Prompt => Self::Prompt,
+            SerializedAuthentication::Keyring { id } => Self::Keyring { id },
+            SerializedAuthentication::NoAuth => Self::NoAuth,
+            SerializedAuthentication::Auto => Self::auto(),
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
-struct SecretEntry {
-    username: String,
-    password: String,
+pub(crate) struct SecretEntry {
+    pub(crate) username: String,
+    pub(crate) password: String,
 }
 
 impl Authentication {
+    /// Get the credentials from the given [`AuthProvider`].
+    pub fn provider(provider: impl AuthProvider + 'static) -> Self {
+        Self::Provider(SharedProvider::new(provider))
+    }
+
+    /// Look up the saved keyring credential for the instrument when logging in (see
+    /// [`Authentication::Auto`]).
+    #[must_use]
+    pub fn auto() -> Self {
+        Self::Auto(AutoAuth::default())
+    }
+
     ///
     /// Retrieves the username
     ///
@@ -74,7 +190,7 @@ impl Authentication {
             Self::PromptPartial { .. } => {
                 unreachable!("All other partial prompt options are used for usernames")
             }
-            Self::Credential { username, .. } => Ok(Some((*username).to_string())),
+            Self::Credential { username, .. } => Ok(Some(username.clone())),
             Self::Keyring { id } => {
                 let entry = keyring::Entry::new(SERVICE_NAME, id)?;
                 let secret = &entry.get_secret()?;
@@ -84,6 +200,8 @@ impl Authentication {
                 Ok(Some(secret.username))
             }
             Self::NoAuth => Ok(None),
+            Self::Provider(p) => p.username(),
+            Self::Auto(auto) => auto.auth.read_username(),
         }
     }
 
@@ -118,7 +236,7 @@ impl Authentication {
             Self::PromptPartial { .. } => {
                 unreachable!("All other prompt options are used for passwords")
             }
-            Self::Credential { password, .. } => Ok(Some((*password).to_string())),
+            Self::Credential { password, .. } => Ok(Some(password.clone())),
             Self::Keyring { id } => {
                 let entry = keyring::Entry::new(SERVICE_NAME, id)?;
                 let secret = &entry.get_secret()?;
@@ -128,6 +246,8 @@ impl Authentication {
                 Ok(Some(secret.password))
             }
             Self::NoAuth => Ok(None),
+            Self::Provider(p) => p.password(),
+            Self::Auto(auto) => auto.auth.read_password(),
         }
     }
 
@@ -152,16 +272,14 @@ impl Authentication {
     }
 
     /// Saves this credential to the system keyring. This will overwrite an existing
-    /// entry or create a new one if it doesn't already exist.
+    /// entry or create a new one if it doesn't already exist, and adds it to the
+    /// [`CredentialStore`] index.
     ///
     /// # Errors
     /// Errors may occur from the interactions with the [`keyring`] crate.
     pub fn save_credential(&self, model: &Model, serial: &str) -> Result<(), InstrumentError> {
-        #[cfg(test)] // Don't use the system credential manager for unit tests.
-        set_default_credential_builder(mock::default_credential_builder());
-
-        let name = format!("{model}#{serial}");
         let (username, password) = match self {
+            Self::Auto(auto) => return auto.auth.save_credential(model, serial),
             Self::Prompt => {
                 return Err(InstrumentError::AuthenticationFailure(
                     "no credentials provided".to_string(),
@@ -171,7 +289,7 @@ impl Authentication {
                 username.clone().unwrap_or_default(),
                 password.clone().unwrap_or_default(),
             ),
-            Self::Credential { username, password } => (username.to_string(), password.to_string()),
+            Self::Credential { username, password } => (username.clone(), password.clone()),
             Self::Keyring { id } => {
                 let entry = keyring::Entry::new(SERVICE_NAME, id)?;
                 let secret = &entry.get_secret()?;
@@ -179,14 +297,116 @@ impl Authentication {
                     serde_json::from_str(String::from_utf8_lossy(secret).as_ref())?;
                 (secret.username, secret.password)
             }
-            Self::NoAuth => return Ok(()),
+            Self::NoAuth | Self::Provider(_) => return Ok(()),
         };
-        let secret = SecretEntry { username, password };
+        CredentialStore::open()?.save(
+            &SavedCredential::id_for(model, serial),
+            &username,
+            &password,
+        )
+    }
+}
 
-        let entry = keyring::Entry::new(SERVICE_NAME, &name)?;
+/// The built-in providers: [`Authentication::Prompt`] prompts on the command line,
+/// [`Authentication::Keyring`] reads the system keyring and
+/// [`Authentication::Credential`] uses the given credentials. Credentials that are
+/// accepted are saved to the keyring (see [`Authentication::save_credential`]).
+impl AuthProvider for Authentication {
+    fn username(&mut self) -> Result<Option<String>, InstrumentError> {
+        self.read_username()
+    }
 
-        entry.set_secret(serde_json::to_string(&secret)?.as_bytes())?;
+    fn password(&mut self) -> Result<Option<String>, InstrumentError> {
+        self.read_password()
+    }
 
-        Ok(())
+    fn needs_identity(&self) -> bool {
+        match self {
+            Self::Auto(_) => true,
+            Self::Provider(p) => p.needs_identity(),
+            _ => false,
+        }
+    }
+
+    /// [`Authentication::Auto`] looks up the saved credential for the instrument.
+    fn on_identified(&mut self, info: &InstrumentInfo) -> Result<(), InstrumentError> {
+        match self {
+            Self::Auto(auto) => auto.identify(&mut CredentialStore::open()?, info),
+            Self::Provider(p) => p.on_identified(info),
+            _ => Ok(()),
+        }
+    }
+
+    fn on_success(&mut self, info: &InstrumentInfo) -> Result<(), InstrumentError> {
+        match self {
+            Self::Provider(p) => p.on_success(info),
+            _ => self.save_credential(&info.model, &info.serial_number),
+        }
+    }
+
+    /// Providers are told about the rejection, prompts are reset so the next login
+    /// prompts again, and [`Authentication::Auto`] removes the rejected credential
+    /// from the keyring.
+    fn on_rejected(&mut self) -> Result<(), InstrumentError> {
+        match self {
+            Self::Provider(p) => p.on_rejected(),
+            Self::Auto(auto) => auto.reject(&mut CredentialStore::open()?),
+            Self::PromptPartial { .. } => {
+                *self = Self::Prompt;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use crate::{
+        instrument::{
+            auth_provider::AuthProvider, credential_store::CredentialStore, info::InstrumentInfo,
+        },
+        model::Model,
+    };
+
+    use super::{Authentication, AutoAuth};
+
+    #[test]
+    fn auto_uses_saved_credential_and_forgets_rejected() {
+        let info = InstrumentInfo {
+            model: Model::_2450,
+            serial_number: "1".to_string(),
+            ..InstrumentInfo::default()
+        };
+        let mut store = CredentialStore::open().unwrap();
+        let mut auto = AutoAuth::default();
+
+        auto.identify(&mut store, &info).unwrap();
+        assert_eq!(auto, AutoAuth::default());
+
+        store.save("2450#1", "admin", "secret").unwrap();
+        auto.identify(&mut store, &info).unwrap();
+        assert_eq!(
+            *auto.auth,
+            Authentication::Keyring {
+                id: "2450#1".to_string()
+            }
+        );
+
+        auto.reject(&mut store).unwrap();
+        assert_eq!(auto, AutoAuth::default());
+        assert_eq!(store.find(&info).unwrap(), None);
+        assert_eq!(store.list().unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn auto_needs_identity_and_serializes() {
+        let auth = Authentication::auto();
+        assert!(auth.needs_identity());
+        assert!(!Authentication::Prompt.needs_identity());
+
+        let json = serde_json::to_string(&auth).unwrap();
+        assert_eq!(json, r#""auto""#);
+        assert_eq!(serde_json::from_str::<Authentication>(&json).unwrap(), auth);
     }
 }
@@ -0,0 +1,416 @@
+//! A trait that allows for reading the reading buffers of an instrument.
+//!
+//! Buffer contents are transferred in binary (`format.REAL64` or `format.REAL32`,
+//! also known as `SREAL`) rather than ASCII, which is much faster for large buffers.
+
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
+
+use crate::{error::Result, instrument::query_tsp, protocol::block::BlockReader, InstrumentError};
+
+/// The number of attempts to make when reading the size of a buffer.
+const READ_ATTEMPTS: usize = 500;
+
+/// The delay between attempts when reading a response.
+const READ_DELAY: Duration = Duration::from_millis(10);
+
+/// The naming used for reading buffers by an instrument family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BufferDialect {
+    /// The 2600 series, where each of the given SMU channels has `nvbuffer1` and
+    /// `nvbuffer2` (e.g. `smua.nvbuffer1`).
+    Smu(&'static [&'static str]),
+    /// TTI instruments, which have `defbuffer1` and `defbuffer2`.
+    Tti,
+}
+
+impl BufferDialect {
+    /// The names of the reading buffers that always exist.
+    #[must_use]
+    pub fn default_buffers(self) -> Vec<String> {
+        match self {
+            Self::Smu(channels) => channels
+                .iter()
+                .flat_map(|c| [format!("{c}.nvbuffer1"), format!("{c}.nvbuffer2")])
+                .collect(),
+            Self::Tti => vec!["defbuffer1".to_string(), "defbuffer2".to_string()],
+        }
+    }
+
+    /// The attribute of a buffer that holds the given column.
+    #[must_use]
+    pub const fn attribute(self, column: Column) -> &'static str {
+        match (self, column) {
+            (_, Column::Readings) => "readings",
+            (Self::Smu(_), Column::Timestamps) => "timestamps",
+            (Self::Tti, Column::Timestamps) => "relativetimestamps",
+            (_, Column::SourceValues) => "sourcevalues",
+            (_, Column::Statuses) => "statuses",
+        }
+    }
+}
+
+/// A column of a reading buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Column {
+    /// The readings.
+    Readings,
+    /// The time of each reading in seconds, relative to the first reading.
+    Timestamps,
+    /// The value that was being sourced for each reading.
+    SourceValues,
+    /// The status bits of each reading.
+    Statuses,
+}
+
+/// The binary format used to transfer buffer contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DataFormat {
+    /// 64-bit IEEE-754 floating point numbers.
+    #[default]
+    Real64,
+    /// 32-bit IEEE-754 floating point numbers (`SREAL`). Half the size of
+    /// [`DataFormat::Real64`], at the cost of precision.
+    Real32,
+}
+
+impl DataFormat {
+    const fn tsp(self) -> &'static str {
+        match self {
+            Self::Real64 => "format.REAL64",
+            Self::Real32 => "format.REAL32",
+        }
+    }
+
+    /// The number of bytes used for each value.
+    #[must_use]
+    pub const fn width(self) -> usize {
+        match self {
+            Self::Real64 => 8,
+            Self::Real32 => 4,
+        }
+    }
+}
+
+/// The byte order used to transfer buffer contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ByteOrder {
+    /// Least significant byte first.
+    #[default]
+    Little,
+    /// Most significant byte first.
+    Big,
+}
+
+impl ByteOrder {
+    const fn tsp(self) -> &'static str {
+        match self {
+            Self::Little => "format.LITTLEENDIAN",
+            Self::Big => "format.BIGENDIAN",
+        }
+    }
+}
+
+/// The size of a reading buffer.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BufferInfo {
+    /// The name of the buffer (e.g. `defbuffer1`).
+    pub name: String,
+    /// The number of readings in the buffer.
+    pub size: usize,
+    /// The maximum number of readings the buffer can hold.
+    pub capacity: usize,
+}
+
+/// The contents of a reading buffer. Columns that weren't requested are empty.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BufferData {
+    /// See [`Column::Readings`].
+    pub readings: Vec<f64>,
+    /// See [`Column::Timestamps`].
+    pub timestamps: Vec<f64>,
+    /// See [`Column::SourceValues`].
+    pub source_values: Vec<f64>,
+    /// See [`Column::Statuses`].
+    pub statuses: Vec<u32>,
+}
+
+/// The [`Instrument`](crate::instrument::Instrument) has reading buffers that can be
+/// downloaded.
+///
+/// # Default
+/// The default implementation doesn't support any buffers. Drivers override
+/// [`Buffers::buffer_dialect`] to enable the other methods.
+pub trait Buffers: Read + Write {
+    /// The naming used for the reading buffers of this instrument, or [`None`] if
+    /// reading buffers aren't supported.
+    fn buffer_dialect(&self) -> Option<BufferDialect> {
+        None
+    }
+
+    /// The names of the reading buffers that always exist on this instrument.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if reading buffers aren't supported.
+    fn list_buffers(&mut self) -> Result<Vec<String>> {
+        Ok(dialect(self)?.default_buffers())
+    }
+
+    /// Get the number of readings in and the capacity of the given buffer.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if reading buffers aren't supported or the name is
+    /// invalid, any IO errors, or [`InstrumentError::InformationRetrievalError`] if
+    /// the response couldn't be parsed.
+    fn buffer_info(&mut self, name: &str) -> Result<BufferInfo> {
+        dialect(self)?;
+        check_name(name)?;
+        let command = format!("print({name}.n, {name}.capacity)");
+        let output = query_tsp(self, &command, READ_ATTEMPTS, READ_DELAY)?;
+        let line = output.last().map(String::as_str).unwrap_or_default();
+        let counts: Vec<usize> = line.split('\t').filter_map(parse_count).collect();
+        let [size, capacity] = counts[..] else {
+            return Err(InstrumentError::InformationRetrievalError {
+                details: format!("unable to parse the size of {name} from '{line}'"),
+            });
+        };
+        Ok(BufferInfo {
+            name: name.to_string(),
+            size,
+            capacity,
+        })
+    }
+
+    /// Download the given columns of the given buffer.
+    ///
+    /// # Errors
+    /// See [`Buffers::buffer_info`]. [`InstrumentError::InformationRetrievalError`]
+    /// is returned if the values couldn't be decoded, and
+    /// [`InstrumentError::BlockError`] if a binary block couldn't be read.
+    fn download_buffer(
+        &mut self,
+        name: &str,
+        columns: &[Column],
+        format: DataFormat,
+        order: ByteOrder,
+    ) -> Result<BufferData> {
+        let dialect = dialect(self)?;
+        let info = self.buffer_info(name)?;
+        let mut data = BufferData::default();
+        if info.size == 0 {
+            return Ok(data);
+        }
+        let expected = info.size.checked_mul(format.width()).ok_or_else(|| {
+            InstrumentError::InformationRetrievalError {
+                details: format!("{name} is too large to download"),
+            }
+        })?;
+        for column in columns {
+            let command = format!(
+                "format.data = {} format.byteorder = {} printbuffer(1, {}, {name}.{}) format.data = format.ASCII\n",
+                format.tsp(),
+                order.tsp(),
+                info.size,
+                dialect.attribute(*column),
+            );
+            self.write_all(command.as_bytes())?;
+            self.flush()?;
+            let block = BlockReader::new(&mut *self)
+                .with_max_size(expected)
+                .with_indefinite_len(expected)
+                .read_to_vec()?;
+            let values = decode(&block, format, order)?;
+            match column {
+                Column::Readings => data.readings = values,
+                Column::Timestamps => data.timestamps = values,
+                Column::SourceValues => data.source_values = values,
+                Column::Statuses => {
+                    data.statuses = values
+                        .iter()
+                        .map(|v| parse_status(*v))
+                        .collect::<Result<_>>()?;
+                }
+            }
+        }
+        Ok(data)
+    }
+}
+
+fn dialect<T: Buffers + ?Sized>(rw: &T) -> Result<BufferDialect> {
+    rw.buffer_dialect().ok_or_else(|| {
+        InstrumentError::Other("reading buffers are not supported by this instrument".to_string())
+    })
+}
+
+/// Buffer names are interpolated into TSP, so only allow dotted identifiers.
+fn check_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.split('.').all(|part| {
+            part.chars().next().is_some_and(|c| !c.is_ascii_digit())
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(InstrumentError::Other(format!(
+            "'{name}' is not a valid buffer name"
+        )))
+    }
+}
+
+fn parse_count(s: &str) -> Option<usize> {
+    s.trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|c| format!("{c:.0}").parse::<usize>().ok())
+}
+
+fn parse_status(value: f64) -> Result<u32> {
+    format!("{value:.0}")
+        .parse::<u32>()
+        .map_err(|_| InstrumentError::InformationRetrievalError {
+            details: format!("'{value}' is not a valid reading status"),
+        })
+}
+
+/// Decode the values in a binary block.
+pub(crate) fn decode(data: &[u8], format: DataFormat, order: ByteOrder) -> Result<Vec<f64>> {
+    let width = format.width();
+    if !data.len().is_multiple_of(width) {
+        return Err(InstrumentError::InformationRetrievalError {
+            details: format!(
+                "a block of {} bytes is not a whole number of {width}-byte values",
+                data.len()
+            ),
+        });
+    }
+    Ok(data
+        .chunks_exact(width)
+        .map(|c| match (format, order) {
+            (DataFormat::Real64, ByteOrder::Little) => {
+                f64::from_le_bytes(c.try_into().unwrap_or_default())
+            }
+            (DataFormat::Real64, ByteOrder::Big) => {
+                f64::from_be_bytes(c.try_into().unwrap_or_default())
+            }
+            (DataFormat::Real32, ByteOrder::Little) => {
+                f64::from(f32::from_le_bytes(c.try_into().unwrap_or_default()))
+            }
+            (DataFormat::Real32, ByteOrder::Big) => {
+                f64::from(f32::from_be_bytes(c.try_into().unwrap_or_default()))
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod unit {
+    use std::collections::VecDeque;
+
+    use crate::{
+        instrument::{authenticate::Authentication, info::InstrumentInfo},
+        model::{ki2600, tti, Model},
+        protocol::Protocol,
+        test_util::FakeInstrument,
+    };
+
+    use super::{Buffers, ByteOrder, Column, DataFormat};
+
+    /// Answers each `printbuffer` with the next block, a few bytes at a time, and
+    /// other queries with `lines`.
+    fn fake(lines: &[&str], blocks: Vec<Vec<u8>>) -> FakeInstrument {
+        let mut blocks = VecDeque::from(blocks);
+        FakeInstrument::new()
+            .with_lines(lines)
+            .with_read_size(5)
+            .with_responder(move |command| {
+                command
+                    .contains("printbuffer")
+                    .then(|| blocks.pop_front().expect("a block should be queued"))
+            })
+    }
+
+    fn info(model: Model) -> InstrumentInfo {
+        InstrumentInfo {
+            model,
+            ..InstrumentInfo::default()
+        }
+    }
+
+    fn block(header: &str, values: &[f64], little: bool) -> Vec<u8> {
+        let mut block = header.as_bytes().to_vec();
+        for v in values {
+            if little {
+                block.extend_from_slice(&v.to_le_bytes());
+            } else {
+                block.extend_from_slice(&v.to_be_bytes());
+            }
+        }
+        block.push(b'\n');
+        block
+    }
+
+    #[test]
+    fn smu_indefinite_blocks() {
+        let fake = fake(
+            &["3.00000e+00\t1.00000e+05"],
+            vec![
+                block("#0", &[1.5, -2.0, 1e-9], true),
+                block("#0", &[0.0, 0.1, 0.2], true),
+            ],
+        );
+        let mut smu = ki2600::Instrument::new(Protocol::new(fake.clone()), Authentication::NoAuth);
+        smu.add_info(info(Model::_2602B));
+
+        assert_eq!(
+            smu.list_buffers().unwrap(),
+            [
+                "smua.nvbuffer1",
+                "smua.nvbuffer2",
+                "smub.nvbuffer1",
+                "smub.nvbuffer2"
+            ]
+        );
+        let data = smu
+            .download_buffer(
+                "smua.nvbuffer1",
+                &[Column::Readings, Column::Timestamps],
+                DataFormat::Real64,
+                ByteOrder::Little,
+            )
+            .expect("buffer should download");
+
+        assert_eq!(data.readings, [1.5, -2.0, 1e-9]);
+        assert_eq!(data.timestamps, [0.0, 0.1, 0.2]);
+        assert!(data.statuses.is_empty());
+        assert!(fake.written().contains(
+            "format.data = format.REAL64 format.byteorder = format.LITTLEENDIAN \
+             printbuffer(1, 3, smua.nvbuffer1.timestamps) format.data = format.ASCII"
+        ));
+    }
+
+    #[test]
+    fn tti_definite_blocks() {
+        let fake = fake(&["2\t100000"], vec![block("#216", &[0.25, 4.0], false)]);
+        let mut tti = tti::Instrument::new(Protocol::new(fake.clone()), Authentication::NoAuth);
+        tti.add_info(info(Model::_2450));
+
+        let info = tti.buffer_info("defbuffer1").unwrap();
+        assert_eq!((info.size, info.capacity), (2, 100_000));
+
+        let data = tti
+            .download_buffer(
+                "defbuffer1",
+                &[Column::Timestamps],
+                DataFormat::Real64,
+                ByteOrder::Big,
+            )
+            .unwrap();
+        assert_eq!(data.timestamps, [0.25, 4.0]);
+        assert!(fake.written().contains("defbuffer1.relativetimestamps"));
+        assert!(fake.written().contains("format.BIGENDIAN"));
+        assert!(tti.buffer_info("defbuffer1; reset()").is_err());
+    }
+}
@@ -0,0 +1,350 @@
+//! Manage the credentials that [`Authentication::save_credential`] saves in the
+//! system keyring.
+//!
+//! Each instrument's credentials are saved in an entry named `{model}#{serial}`
+//! under the `tsp-toolkit` service. System keyrings can't be searched, so the store
+//! also keeps the ids it has saved in an index entry named [`INDEX_ID`]. Entries
+//! saved before the index existed can be added to it with
+//! [`CredentialStore::migrate`].
+//!
+//! [`Authentication::save_credential`]: crate::instrument::authenticate::Authentication::save_credential
+
+use std::collections::{hash_map, BTreeSet, HashMap};
+
+use keyring::Entry;
+#[cfg(test)]
+use keyring::{mock, set_default_credential_builder};
+
+use crate::{
+    error::Result,
+    instrument::{
+        authenticate::{Authentication, SecretEntry, SERVICE_NAME},
+        info::InstrumentInfo,
+    },
+    model::Model,
+};
+
+/// The name of the keyring entry that holds the index of saved ids. It can't clash
+/// with a `{model}#{serial}` id since model names are never empty.
+pub const INDEX_ID: &str = "#index";
+
+/// A saved credential for an instrument.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SavedCredential {
+    /// The name of the keyring entry
+    pub id: String,
+    /// The model of the instrument
+    pub model: Model,
+    /// The serial number of the instrument
+    pub serial_number: String,
+}
+
+impl SavedCredential {
+    /// The keyring entry name for the given instrument.
+    #[must_use]
+    pub fn id_for(model: &Model, serial_number: &str) -> String {
+        format!("{model}#{serial_number}")
+    }
+
+    fn parse(id: &str) -> Option<Self> {
+        let (model, serial_number) = id.split_once('#')?;
+        if model.is_empty() {
+            return None;
+        }
+        Some(Self {
+            id: id.to_string(),
+            model: model.parse().ok()?,
+            serial_number: serial_number.to_string(),
+        })
+    }
+
+    /// An [`Authentication`] that logs in with this credential.
+    #[must_use]
+    pub fn authentication(&self) -> Authentication {
+        Authentication::Keyring {
+            id: self.id.clone(),
+        }
+    }
+}
+
+/// The saved instrument credentials in the system keyring.
+#[derive(Debug)]
+pub struct CredentialStore {
+    index: Entry,
+    entries: HashMap<String, Entry>,
+}
+
+impl CredentialStore {
+    /// Open the credential store in the system keyring.
+    ///
+    /// # Errors
+    /// Errors may occur from the interactions with the [`keyring`] crate.
+    pub fn open() -> Result<Self> {
+        #[cfg(test)] // Don't use the system credential manager for unit tests.
+        set_default_credential_builder(mock::default_credential_builder());
+
+        Ok(Self {
+            index: Entry::new(SERVICE_NAME, INDEX_ID)?,
+            entries: HashMap::new(),
+        })
+    }
+
+    fn entry(&mut self, id: &str) -> Result<&Entry> {
+        Ok(match self.entries.entry(id.to_string()) {
+            hash_map::Entry::Occupied(e) => e.into_mut(),
+            hash_map::Entry::Vacant(e) => e.insert(Entry::new(SERVICE_NAME, id)?),
+        })
+    }
+
+    fn ids(&self) -> Result<BTreeSet<String>> {
+        match self.index.get_secret() {
+            Ok(ids) => Ok(serde_json::from_slice(&ids)?),
+            Err(keyring::Error::NoEntry) => Ok(BTreeSet::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set_ids(&self, ids: &BTreeSet<String>) -> Result<()> {
+        if !ids.is_empty() {
+            self.index
+                .set_secret(serde_json::to_string(ids)?.as_bytes())?;
+            return Ok(());
+        }
+        match self.index.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn index(&self, id: &str) -> Result<()> {
+        let mut ids = self.ids()?;
+        if ids.insert(id.to_string()) {
+            self.set_ids(&ids)?;
+        }
+        Ok(())
+    }
+
+    fn unindex(&self, id: &str) -> Result<()> {
+        let mut ids = self.ids()?;
+        if ids.remove(id) {
+            self.set_ids(&ids)?;
+        }
+        Ok(())
+    }
+
+    /// Read an entry. Entries that only hold a password (rather than a
+    /// [`SecretEntry`]) are returned with an empty username.
+    fn read(&mut self, id: &str) -> Result<Option<(SecretEntry, bool)>> {
+        let secret = match self.entry(id)?.get_secret() {
+            Ok(secret) => secret,
+            Err(keyring::Error::NoEntry) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(
+            serde_json::from_slice::<SecretEntry>(&secret).map_or_else(
+                |_| {
+                    let password = String::from_utf8_lossy(&secret).into_owned();
+                    (
+                        SecretEntry {
+                            username: String::new(),
+                            password,
+                        },
+                        true,
+                    )
+                },
+                |s| (s, false),
+            ),
+        ))
+    }
+
+    fn write(&mut self, id: &str, secret: &SecretEntry) -> Result<()> {
+        let secret = serde_json::to_string(secret)?;
+        self.entry(id)?.set_secret(secret.as_bytes())?;
+        Ok(())
+    }
+
+    /// The instruments with saved credentials, sorted by id.
+    ///
+    /// # Errors
+    /// Errors may occur from the interactions with the [`keyring`] crate or if the
+    /// index is corrupt.
+    pub fn list(&self) -> Result<Vec<SavedCredential>> {
+        Ok(self
+            .ids()?
+            .iter()
+            .filter_map(|id| SavedCredential::parse(id))
+            .collect())
+    }
+
+    /// Find the saved credential for the given instrument. A credential that was
+    /// saved before the index existed is added to it.
+    ///
+    /// # Errors
+    /// Errors may occur from the interactions with the [`keyring`] crate.
+    pub fn find(&mut self, info: &InstrumentInfo) -> Result<Option<SavedCredential>> {
+        let id = SavedCredential::id_for(&info.model, &info.serial_number);
+        if self.read(&id)?.is_none() {
+            return Ok(None);
+        }
+        self.index(&id)?;
+        Ok(SavedCredential::parse(&id))
+    }
+
+    /// Save the credential for the given id, overwriting an existing one.
+    ///
+    /// # Errors
+    /// Errors may occur from the interactions with the [`keyring`] crate.
+    pub fn save(&mut self, id: &str, username: &str, password: &str) -> Result<()> {
+        self.write(
+            id,
+            &SecretEntry {
+                username: username.to_string(),
+                password: password.to_string(),
+            },
+        )?;
+        self.index(id)
+    }
+
+    /// Delete the credential with the given id. Returns `false` if there wasn't one.
+    ///
+    /// # Errors
+    /// Errors may occur from the interactions with the [`keyring`] crate.
+    pub fn delete(&mut self, id: &str) -> Result<bool> {
+        let deleted = match self.entry(id)?.delete_credential() {
+            Ok(()) => true,
+            Err(keyring::Error::NoEntry) => false,
+            Err(e) => return Err(e.into()),
+        };
+        self.unindex(id)?;
+        Ok(deleted)
+    }
+
+    /// Move the credential saved as `from` to `to` (e.g. after the instrument's model
+    /// string changed), overwriting an existing credential at `to`.
+    ///
+    /// # Errors
+    /// [`keyring::Error::NoEntry`] if there is no credential at `from`, or other
+    /// errors from the interactions with the [`keyring`] crate.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        let Some((secret, _)) = self.read(from)? else {
+            return Err(keyring::Error::NoEntry.into());
+        };
+        if from == to {
+            return Ok(());
+        }
+        self.write(to, &secret)?;
+        self.index(to)?;
+        self.delete(from)?;
+        Ok(())
+    }
+
+    /// Migrate the credentials with the given ids: entries that aren't in the index
+    /// are added to it, and entries that only hold a password are rewritten with an
+    /// empty username. Ids without an entry are skipped. Returns the ids that were
+    /// changed.
+    ///
+    /// # Errors
+    /// Errors may occur from the interactions with the [`keyring`] crate.
+    pub fn migrate<I, S>(&mut self, ids: I) -> Result<Vec<String>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut indexed = self.ids()?;
+        let mut migrated = Vec::new();
+        for id in ids {
+            let id = id.as_ref();
+            let Some((secret, legacy)) = self.read(id)? else {
+                continue;
+            };
+            if legacy {
+                self.write(id, &secret)?;
+            }
+            if indexed.insert(id.to_string()) || legacy {
+                migrated.push(id.to_string());
+            }
+        }
+        self.set_ids(&indexed)?;
+        Ok(migrated)
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::assert_matches::assert_matches;
+
+    use crate::{
+        instrument::{authenticate::Authentication, info::InstrumentInfo},
+        model::Model,
+        InstrumentError,
+    };
+
+    use super::{CredentialStore, SavedCredential};
+
+    fn info(serial_number: &str) -> InstrumentInfo {
+        InstrumentInfo {
+            model: Model::_2450,
+            serial_number: serial_number.to_string(),
+            ..InstrumentInfo::default()
+        }
+    }
+
+    #[test]
+    fn save_find_rename_delete() {
+        let mut store = CredentialStore::open().unwrap();
+        assert_eq!(store.list().unwrap(), Vec::new());
+
+        store.save("2450#1", "admin", "secret").unwrap();
+        store.save("2450#2", "", "other").unwrap();
+        let ids: Vec<_> = store.list().unwrap().into_iter().map(|c| c.id).collect();
+        assert_eq!(ids, ["2450#1", "2450#2"]);
+
+        let found = store.find(&info("1")).unwrap().unwrap();
+        assert_eq!(found.model, Model::_2450);
+        assert_eq!(found.serial_number, "1");
+        assert_eq!(
+            found.authentication(),
+            Authentication::Keyring {
+                id: "2450#1".to_string()
+            }
+        );
+        assert_eq!(store.find(&info("3")).unwrap(), None);
+
+        store.rename("2450#2", "2450#3").unwrap();
+        assert_eq!(store.find(&info("2")).unwrap(), None);
+        assert_eq!(
+            store.find(&info("3")).unwrap().map(|c| c.id),
+            Some("2450#3".to_string())
+        );
+        assert_matches!(
+            store.rename("2450#2", "2450#4"),
+            Err(InstrumentError::KeyringError(keyring::Error::NoEntry))
+        );
+
+        assert!(store.delete("2450#1").unwrap());
+        assert!(!store.delete("2450#1").unwrap());
+        let ids: Vec<_> = store.list().unwrap().into_iter().map(|c| c.id).collect();
+        assert_eq!(ids, ["2450#3"]);
+    }
+
+    #[test]
+    fn migrate_legacy_entries() {
+        let mut store = CredentialStore::open().unwrap();
+        store.entry("2450#1").unwrap().set_password("bare").unwrap();
+        store.save("2450#2", "admin", "secret").unwrap();
+
+        let migrated = store.migrate(["2450#1", "2450#2", "2450#3"]).unwrap();
+        assert_eq!(migrated, ["2450#1"]);
+        assert_eq!(
+            store.list().unwrap(),
+            [
+                SavedCredential::parse("2450#1").unwrap(),
+                SavedCredential::parse("2450#2").unwrap()
+            ]
+        );
+        let (secret, legacy) = store.read("2450#1").unwrap().unwrap();
+        assert!(!legacy);
+        assert_eq!(secret.username, "");
+        assert_eq!(secret.password, "bare");
+    }
+}
@@ -3,18 +3,22 @@ use tracing::{debug, instrument};
 
 use crate::{
     error::Result,
+    instrument::read_response,
     model::{Model, Vendor},
     InstrumentError,
 };
 use std::{
     fmt::Display,
-    io::{ErrorKind, Read, Write},
+    io::{Read, Write},
     time::Duration,
 };
 
+/// How long to wait for the response to `*IDN?`.
+const IDN_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// The information about an instrument.
 #[allow(clippy::module_name_repetitions)]
-#[derive(serde::Serialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
 pub struct InstrumentInfo {
     /// The human-readable name of the vendor that makes the instrument
     pub vendor: Vendor,
@@ -36,31 +40,19 @@ pub struct InstrumentInfo {
 #[allow(clippy::module_name_repetitions)]
 #[instrument(skip(rw))]
 pub fn get_info<T: Read + Write + ?Sized>(rw: &mut T) -> Result<InstrumentInfo> {
+    // The instrument handles commands in order, so the response to `*IDN?` is only
+    // sent once `abort` and `*CLS` are done.
     debug!("Sending abort");
     rw.write_all(b"abort\n")?;
-    std::thread::sleep(Duration::from_millis(100));
     debug!("Sending *CLS");
     rw.write_all(b"*CLS\n")?;
-    std::thread::sleep(Duration::from_millis(100));
     debug!("Sending *IDN?");
     rw.write_all(b"*IDN?\n")?;
-    let mut info: Option<InstrumentInfo> = None;
-    for _ in 0..100 {
-        std::thread::sleep(Duration::from_millis(100));
-
-        let mut buf = vec![0u8; 100];
-        let read_bytes = match rw.read(&mut buf) {
-            Ok(b) => b,
-            Err(e) if e.kind() == ErrorKind::WouldBlock => continue,
-            Err(e) => return Err(e.into()),
-        };
-        let buf = &buf[..read_bytes];
-        debug!("Buffer after *IDN?: {}", String::from_utf8_lossy(buf));
-        if let Ok(i) = buf.try_into() {
-            info = Some(i);
-            break;
-        }
-    }
+    let info = read_response(rw, IDN_TIMEOUT, |response| {
+        debug!("Buffer after *IDN?: {response}");
+        let (lines, _) = response.rsplit_once('\n')?;
+        InstrumentInfo::try_from(lines.as_bytes()).ok()
+    })?;
     info.ok_or_else(|| InstrumentError::InformationRetrievalError {
         details: "unable to read instrument info".to_string(),
     })
@@ -197,7 +189,7 @@ impl Display for InstrumentInfo {
 
         let model: String = self.model.to_string();
 
-        let sn: String = self.serial_number.to_string();
+        let sn: String = self.serial_number.clone();
 
         let fw_rev = self
             .firmware_rev
@@ -233,4 +225,22 @@ TSP>";
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn instrument_info_serde_round_trip() {
+        for model in [Model::_2636B, Model::Other("9999".to_string())] {
+            let expected = InstrumentInfo {
+                vendor: Vendor::Tektronix,
+                model,
+                serial_number: "04331961".to_string(),
+                firmware_rev: None,
+            };
+
+            let json = serde_json::to_string(&expected).expect("info should serialize");
+            let actual: InstrumentInfo =
+                serde_json::from_str(&json).expect("info should deserialize");
+
+            assert_eq!(actual, expected);
+        }
+    }
 }
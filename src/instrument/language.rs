@@ -6,7 +6,7 @@ use crate::InstrumentError;
 
 /// The languages that could be on an instrument.
 #[allow(clippy::module_name_repetitions)]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CmdLanguage {
     /// The SCPI language
     Scpi,
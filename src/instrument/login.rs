@@ -1,7 +1,13 @@
 //! The login functionality for an instrument.
 
+use std::time::Duration;
+
 use crate::error::Result;
 
+/// How long [`Login::check_login`] waits for the instrument to respond before
+/// deciding that it is locked.
+pub const CHECK_TIMEOUT: Duration = Duration::from_millis(500);
+
 /// The log-in state of an instrument.
 #[derive(Debug, PartialEq, PartialOrd, Eq)]
 pub enum State {
@@ -1,40 +1,72 @@
 //! Trait definitions that need to be satisfied for any instrument.
 
 pub mod abort;
+pub mod auth_provider;
 pub mod authenticate;
+pub mod buffer;
+pub mod credential_store;
 pub mod firmware;
 pub mod info;
 pub mod language;
 pub mod login;
+pub mod password;
 pub mod reset;
 pub mod script;
+pub mod srq;
+pub mod status;
+pub mod tsplink;
 
 use std::{
-    io::{Read, Write},
-    time::Duration,
+    io::{ErrorKind, Read, Write},
+    time::{Duration, Instant},
 };
 
 use crate::interface::NonBlock;
+use crate::protocol::Clear;
 use crate::{error::Result, InstrumentError};
 pub use abort::Abort;
+pub use auth_provider::AuthProvider;
+pub use buffer::Buffers;
 pub use firmware::Flash;
 pub use info::Info;
 pub use language::{CmdLanguage, Language};
 pub use login::{Login, State};
+pub use password::PasswordAdmin;
 pub use reset::Reset;
 pub use script::Script;
+pub use srq::ServiceRequest;
+pub use status::Status;
 use tracing::debug;
+pub use tsplink::TspLink;
 
 /// A marker trait that defines the traits any [`Instrument`] needs to have.
 pub trait Instrument:
-    Flash + Info + Language + Login + Script + Read + Write + NonBlock + Reset + Abort
+    Flash
+    + Info
+    + Language
+    + Login
+    + Script
+    + Read
+    + Write
+    + NonBlock
+    + Reset
+    + Abort
+    + TspLink
+    + Buffers
+    + Status
+    + ServiceRequest
+    + PasswordAdmin
+    + Clear<Error = InstrumentError>
 {
 }
 
-/// Read the output until one of the strings in `one_of` is found
+/// Read the output until one of the strings in `one_of` is found. Each attempt
+/// reads whatever output is available, and only waits `delay_between_attempts`
+/// before the next attempt if there was none.
 ///
 /// # Errors
-/// This function may result in IO errors from trying to read from `rw`.
+/// This function may result in IO errors from trying to read from `rw`, or
+/// [`InstrumentError::Other`] if none of the strings was found in `max_attempts`.
 #[tracing::instrument(skip(rw))]
 pub fn read_until<T: Read + Write + ?Sized>(
     rw: &mut T,
@@ -44,21 +76,20 @@ pub fn read_until<T: Read + Write + ?Sized>(
 ) -> Result<String> {
     let mut accumulate = String::new();
     for _ in 0..max_attempts {
-        std::thread::sleep(delay_between_attempts);
         let mut buf: Vec<u8> = vec![0u8; 512];
-        match rw.read(&mut buf) {
-            Ok(_) => Ok(()),
-            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                std::thread::sleep(delay_between_attempts);
-                continue;
-            }
-            Err(e) => Err(e),
-        }?;
+        let read = match rw.read(&mut buf) {
+            Ok(read) => read,
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => 0,
+            Err(e) => return Err(e.into()),
+        };
+        let buf = &buf[..read];
         let first_null = buf.iter().position(|&x| x == b'\0').unwrap_or(buf.len());
         let buf = &buf[..first_null];
-        if !buf.is_empty() {
-            accumulate = format!("{accumulate}{}", String::from_utf8_lossy(buf));
+        if buf.is_empty() {
+            std::thread::sleep(delay_between_attempts);
+            continue;
         }
+        accumulate = format!("{accumulate}{}", String::from_utf8_lossy(buf));
         for s in one_of {
             if accumulate.contains(s) {
                 return Ok(accumulate.trim().to_string());
@@ -72,7 +103,9 @@ pub fn read_until<T: Read + Write + ?Sized>(
 ///
 /// # Warning
 /// This functions calls a TSP command and therefore should not be used before
-/// we know whether the instrument is in TSP mode (only applicable for TTI)
+/// we know whether the instrument is in TSP mode (only applicable for TTI). Use
+/// [`Scpi::clear_output_queue`](crate::model::tti::scpi::Scpi::clear_output_queue)
+/// for TTI instruments in SCPI mode.
 ///
 /// # Errors
 /// Whatever can errors can occur with [`std::io::Read`], [`std::io::Write`] or
@@ -96,3 +129,230 @@ pub fn clear_output_queue<T: Read + Write + ?Sized>(
         Err(e) => Err(e),
     }
 }
+
+/// How long [`Reset`], [`Abort`] and [`Script`] wait for the instrument to finish.
+pub const COMPLETE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The delay between reads while waiting for an operation to complete.
+const COMPLETE_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Wait for all pending operations to complete with `waitcomplete()`, then for a
+/// unique marker to be printed. Anything else that is printed before the marker is
+/// discarded.
+///
+/// This returns as soon as the instrument has finished, instead of after a fixed
+/// delay.
+///
+/// # Warning
+/// This function calls a TSP command and therefore should not be used before
+/// we know whether the instrument is in TSP mode (only applicable for TTI). Use
+/// [`Scpi::wait_complete`](crate::model::tti::scpi::Scpi::wait_complete) for TTI
+/// instruments in SCPI mode.
+///
+/// # Errors
+/// Any IO errors, or [`InstrumentError::Timeout`] if the marker wasn't printed
+/// within `timeout`.
+#[tracing::instrument(skip(rw))]
+pub fn wait_complete<T: Read + Write + ?Sized>(rw: &mut T, timeout: Duration) -> Result<()> {
+    let marker = format!(
+        "complete {}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap_or(0)
+    );
+
+    debug!("Sending waitcomplete()");
+    rw.write_all(format!("waitcomplete() print(\"{marker}\")\n").as_bytes())?;
+    rw.flush()?;
+
+    let deadline = Instant::now().checked_add(timeout);
+    let mut accumulate = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        match rw.read(&mut buf) {
+            Ok(n) => accumulate.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+        if String::from_utf8_lossy(&accumulate).contains(&marker) {
+            return Ok(());
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return Err(InstrumentError::Timeout {
+                details: "pending operations to complete".to_string(),
+            });
+        }
+        std::thread::sleep(COMPLETE_POLL_INTERVAL);
+    }
+}
+
+/// Read the response to a command until `parse` recognizes it.
+///
+/// `parse` is given everything read so far, and reads are only delayed while no
+/// output is available, so this returns as soon as the response arrives. Returns
+/// [`None`] if the response wasn't recognized within `timeout`, such as when a
+/// locked instrument doesn't respond.
+///
+/// # Errors
+/// Any IO errors from reading `rw`.
+pub fn read_response<T: Read + ?Sized, R>(
+    rw: &mut T,
+    timeout: Duration,
+    parse: impl Fn(&str) -> Option<R>,
+) -> Result<Option<R>> {
+    let deadline = Instant::now().checked_add(timeout);
+    let mut accumulate = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let read = match rw.read(&mut buf) {
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => 0,
+            Err(e) => return Err(e.into()),
+        };
+        if read > 0 {
+            accumulate.extend_from_slice(&buf[..read]);
+            if let Some(r) = parse(&String::from_utf8_lossy(&accumulate)) {
+                return Ok(Some(r));
+            }
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return Ok(None);
+        }
+        if read == 0 {
+            std::thread::sleep(COMPLETE_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Run the given TSP code and return each non-empty line that it prints.
+///
+/// # Warning
+/// This function calls a TSP command and therefore should not be used before
+/// we know whether the instrument is in TSP mode (only applicable for TTI)
+///
+/// # Errors
+/// Any errors that occur with [`std::io::Read`] or [`std::io::Write`], or
+/// [`InstrumentError::Other`] if the output of `tsp` was not read in time.
+#[tracing::instrument(skip(rw))]
+pub fn query_tsp<T: Read + Write + ?Sized>(
+    rw: &mut T,
+    tsp: &str,
+    max_attempts: usize,
+    delay_between_attempts: Duration,
+) -> Result<Vec<String>> {
+    debug!("Sending {tsp}");
+    run_tsp(rw, tsp, tsp, max_attempts, delay_between_attempts)
+}
+
+/// Like [`query_tsp`], but for TSP that contains a secret such as a password, so
+/// `tsp` is never logged or included in errors.
+///
+/// # Errors
+/// See [`query_tsp`].
+#[tracing::instrument(skip(rw, tsp))]
+pub(crate) fn query_tsp_secret<T: Read + Write + ?Sized>(
+    rw: &mut T,
+    tsp: &str,
+    max_attempts: usize,
+    delay_between_attempts: Duration,
+) -> Result<Vec<String>> {
+    debug!("Sending TSP that contains a secret");
+    run_tsp(rw, tsp, "<redacted>", max_attempts, delay_between_attempts)
+}
+
+/// Run `tsp` for [`query_tsp`], referring to it as `shown` in errors.
+fn run_tsp<T: Read + Write + ?Sized>(
+    rw: &mut T,
+    tsp: &str,
+    shown: &str,
+    max_attempts: usize,
+    delay_between_attempts: Duration,
+) -> Result<Vec<String>> {
+    let marker = format!("end of output {}", chrono::Utc::now());
+
+    rw.write_all(format!("{tsp} print(\"{marker}\")\n").as_bytes())?;
+
+    let output = match read_until(
+        rw,
+        std::slice::from_ref(&marker),
+        max_attempts,
+        delay_between_attempts,
+    ) {
+        Ok(o) => o,
+        Err(InstrumentError::Other(_)) => {
+            return Err(InstrumentError::Other(format!(
+                "unable to read the output of '{shown}'"
+            )))
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(output
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && *l != marker && *l != "TSP>")
+        .map(ToString::to_string)
+        .collect())
+}
+
+/// Run the given TSP code and return each non-empty line that it prints, waiting up
+/// to `timeout` for it to finish. Use this instead of [`query_tsp`] for code that
+/// takes a known amount of time, such as measurements.
+///
+/// # Warning
+/// This function calls a TSP command and therefore should not be used before
+/// we know whether the instrument is in TSP mode (only applicable for TTI)
+///
+/// # Errors
+/// Any errors that occur with [`std::io::Read`] or [`std::io::Write`], or
+/// [`InstrumentError::Other`] if the output of `tsp` was not read within `timeout`.
+#[tracing::instrument(skip(rw))]
+pub fn query_tsp_timeout<T: Read + Write + ?Sized>(
+    rw: &mut T,
+    tsp: &str,
+    timeout: Duration,
+) -> Result<Vec<String>> {
+    let marker = format!("end of output {}", chrono::Utc::now());
+
+    debug!("Sending {tsp}");
+    rw.write_all(format!("{tsp} print(\"{marker}\")\n").as_bytes())?;
+
+    let Some(output) = read_response(rw, timeout, |output| {
+        output.contains(&marker).then(|| output.to_string())
+    })?
+    else {
+        return Err(InstrumentError::Other(format!(
+            "unable to read the output of '{tsp}' within {timeout:?}"
+        )));
+    };
+
+    Ok(output
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && *l != marker && *l != "TSP>")
+        .map(ToString::to_string)
+        .collect())
+}
+
+/// Read and clear all the errors in the instrument error queue.
+///
+/// Each error is returned as printed by `errorqueue.next()`: the tab-separated
+/// error code, message, severity and node.
+///
+/// # Warning
+/// This function calls a TSP command and therefore should not be used before
+/// we know whether the instrument is in TSP mode (only applicable for TTI)
+///
+/// # Errors
+/// See [`query_tsp`].
+#[tracing::instrument(skip(rw))]
+pub fn read_error_queue<T: Read + Write + ?Sized>(
+    rw: &mut T,
+    max_attempts: usize,
+    delay_between_attempts: Duration,
+) -> Result<Vec<String>> {
+    query_tsp(
+        rw,
+        "for _ = 1, errorqueue.count do print(errorqueue.next()) end",
+        max_attempts,
+        delay_between_attempts,
+    )
+}
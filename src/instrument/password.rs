@@ -0,0 +1,374 @@
+//! A trait that allows for setting or clearing the password of an instrument and
+//! choosing which interfaces it protects, keeping the saved keyring credential (see
+//! [`CredentialStore`]) in sync.
+//!
+//! For example, to rotate the password of every instrument in a fleet:
+//!
+//! ```no_run
+//! # use tsp_toolkit_kic_lib::instrument::{password::PasswordMode, PasswordAdmin};
+//! # fn f(fleet: &mut [Box<dyn tsp_toolkit_kic_lib::instrument::Instrument>])
+//! # -> Result<(), tsp_toolkit_kic_lib::InstrumentError> {
+//! for instrument in fleet {
+//!     instrument.set_password("new password")?;
+//!     instrument.set_password_mode(PasswordMode::All)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use crate::{
+    error::Result,
+    instrument::{
+        credential_store::{CredentialStore, SavedCredential},
+        info::{Info, InstrumentInfo},
+        query_tsp, query_tsp_secret, wait_complete, COMPLETE_TIMEOUT,
+    },
+    InstrumentError,
+};
+
+/// The number of attempts to make when reading the password mode.
+const READ_ATTEMPTS: usize = 100;
+
+/// The delay between attempts when reading the password mode.
+const READ_DELAY: Duration = Duration::from_millis(10);
+
+/// The TSP commands used to manage the password of an instrument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PasswordDialect {
+    /// `localnode.password` and `localnode.passwordmode`
+    PasswordMode,
+    /// `localnode.password` only. These instruments control which interfaces can
+    /// change settings with `localnode.access` rather than a password mode.
+    PasswordOnly,
+}
+
+/// Which interfaces are protected by the password (`localnode.passwordmode`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PasswordMode {
+    /// The password is not used
+    None,
+    /// Only the web interface is protected
+    Web,
+    /// Only the remote command interfaces are protected
+    Remote,
+    /// The web and remote command interfaces are protected
+    All,
+}
+
+impl PasswordMode {
+    /// The TSP constant for this mode.
+    #[must_use]
+    pub const fn tsp(self) -> &'static str {
+        match self {
+            Self::None => "localnode.PASSWORD_NONE",
+            Self::Web => "localnode.PASSWORD_WEB",
+            Self::Remote => "localnode.PASSWORD_LAN",
+            Self::All => "localnode.PASSWORD_ALL",
+        }
+    }
+
+    const fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::None),
+            1 => Some(Self::Web),
+            2 => Some(Self::Remote),
+            3 => Some(Self::All),
+            _ => None,
+        }
+    }
+}
+
+impl Display for PasswordMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Web => "web",
+            Self::Remote => "remote",
+            Self::All => "all",
+        })
+    }
+}
+
+impl FromStr for PasswordMode {
+    type Err = InstrumentError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "web" => Ok(Self::Web),
+            "remote" | "lan" => Ok(Self::Remote),
+            "all" => Ok(Self::All),
+            _ => Err(InstrumentError::Other(format!(
+                "'{s}' is not a password mode, expected none, web, remote or all"
+            ))),
+        }
+    }
+}
+
+/// Manage the password of an instrument. The instrument must already be logged
+/// into if it has a password.
+pub trait PasswordAdmin: Info {
+    /// How the password of this instrument is managed, or [`None`] if it can't be
+    /// managed.
+    fn password_dialect(&self) -> Option<PasswordDialect> {
+        None
+    }
+
+    /// Set the password of the instrument and, once the instrument has accepted it,
+    /// save it to the keyring for the instrument (replacing the saved credential, if
+    /// any).
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the password can't be managed, contains a line
+    /// break or was rejected by the instrument, any IO errors, or errors from the
+    /// interactions with the [`keyring`] crate.
+    fn set_password(&mut self, password: &str) -> Result<()> {
+        dialect(self)?;
+        if password.contains(['\n', '\r']) {
+            return Err(InstrumentError::Other(
+                "passwords cannot contain line breaks".to_string(),
+            ));
+        }
+        // `pcall` reports whether the assignment was rejected without adding to the
+        // error queue.
+        let output = query_tsp_secret(
+            self,
+            &format!(
+                "print(pcall(function() localnode.password = {} end))",
+                lua_string(password)
+            ),
+            READ_ATTEMPTS,
+            READ_DELAY,
+        )?;
+        let result = output.last().map(String::as_str).unwrap_or_default();
+        if result.trim() != "true" {
+            let reason = result.trim().strip_prefix("false").unwrap_or(result).trim();
+            return Err(InstrumentError::Other(format!(
+                "the instrument did not accept the new password: {reason}"
+            )));
+        }
+        let info = self.info()?;
+        update_keyring(&mut CredentialStore::open()?, &info, password)
+    }
+
+    /// Clear the password of the instrument and remove the saved credential for the
+    /// instrument from the keyring.
+    ///
+    /// # Errors
+    /// See [`PasswordAdmin::set_password`].
+    fn clear_password(&mut self) -> Result<()> {
+        self.set_password("")
+    }
+
+    /// Get which interfaces are protected by the password.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the instrument doesn't have a password mode, any
+    /// IO errors, or [`InstrumentError::InformationRetrievalError`] if the mode could
+    /// not be read.
+    fn password_mode(&mut self) -> Result<PasswordMode> {
+        mode_dialect(self)?;
+        let output = query_tsp(
+            self,
+            "print(localnode.passwordmode)",
+            READ_ATTEMPTS,
+            READ_DELAY,
+        )?;
+        let value = output.last().map(String::as_str).unwrap_or_default();
+        value
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(|v| format!("{v:.0}").parse::<u8>().ok())
+            .and_then(PasswordMode::from_value)
+            .ok_or_else(|| InstrumentError::InformationRetrievalError {
+                details: format!("unable to read localnode.passwordmode from '{value}'"),
+            })
+    }
+
+    /// Set which interfaces are protected by the password.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the instrument doesn't have a password mode, or
+    /// any IO errors.
+    fn set_password_mode(&mut self, mode: PasswordMode) -> Result<()> {
+        mode_dialect(self)?;
+        self.write_all(format!("localnode.passwordmode = {}\n", mode.tsp()).as_bytes())?;
+        wait_complete(self, COMPLETE_TIMEOUT)
+    }
+}
+
+fn dialect<T: PasswordAdmin + ?Sized>(rw: &T) -> Result<PasswordDialect> {
+    rw.password_dialect().ok_or_else(|| {
+        InstrumentError::Other(
+            "the password of this instrument cannot be managed remotely".to_string(),
+        )
+    })
+}
+
+fn mode_dialect<T: PasswordAdmin + ?Sized>(rw: &T) -> Result<()> {
+    match dialect(rw)? {
+        PasswordDialect::PasswordMode => Ok(()),
+        PasswordDialect::PasswordOnly => Err(InstrumentError::Other(
+            "this instrument does not have a password mode".to_string(),
+        )),
+    }
+}
+
+/// Quote `s` as a Lua string literal.
+fn lua_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len().saturating_add(2));
+    quoted.push('"');
+    for c in s.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Save the new password for the instrument, or remove its credential if the
+/// password was cleared.
+fn update_keyring(
+    store: &mut CredentialStore,
+    info: &InstrumentInfo,
+    password: &str,
+) -> Result<()> {
+    let id = SavedCredential::id_for(&info.model, &info.serial_number);
+    if password.is_empty() {
+        store.delete(&id)?;
+        Ok(())
+    } else {
+        store.save(&id, "", password)
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::assert_matches::assert_matches;
+
+    use crate::{
+        instrument::{
+            authenticate::Authentication, credential_store::CredentialStore, info::InstrumentInfo,
+        },
+        model::{ki2600, tti, Model},
+        protocol::Protocol,
+        test_util::{capture_logs, FakeInstrument},
+        InstrumentError,
+    };
+
+    use super::{update_keyring, PasswordAdmin, PasswordDialect, PasswordMode};
+
+    #[test]
+    fn set_password_and_mode() {
+        let fake = FakeInstrument::new().with_replies(&[
+            b"true\n",
+            b"KEITHLEY INSTRUMENTS INC.,MODEL 2636B,1,1.0.0\n",
+            b"3.00000e+00\n",
+        ]);
+        let mut instrument =
+            ki2600::Instrument::new(Protocol::new(fake.clone()), Authentication::NoAuth);
+        assert_eq!(
+            instrument.password_dialect(),
+            Some(PasswordDialect::PasswordMode)
+        );
+        instrument.set_password(r#"a"b\c"#).unwrap();
+        instrument.set_password_mode(PasswordMode::Remote).unwrap();
+        assert_eq!(instrument.password_mode().unwrap(), PasswordMode::All);
+
+        let commands: Vec<_> = fake
+            .commands()
+            .into_iter()
+            .filter(|w| w.contains("localnode."))
+            .collect();
+        assert_eq!(commands.len(), 3);
+        assert!(commands[0]
+            .starts_with(r#"print(pcall(function() localnode.password = "a\"b\\c" end))"#));
+        assert_eq!(
+            commands[1],
+            "localnode.passwordmode = localnode.PASSWORD_LAN"
+        );
+        assert!(commands[2].starts_with("print(localnode.passwordmode)"));
+    }
+
+    #[test]
+    fn password_is_not_logged() {
+        let fake = FakeInstrument::new().with_replies(&[
+            b"true\n",
+            b"KEITHLEY INSTRUMENTS INC.,MODEL 2636B,1,1.0.0\n",
+        ]);
+        let mut instrument =
+            ki2600::Instrument::new(Protocol::new(fake.clone()), Authentication::NoAuth);
+
+        let logs = capture_logs(|| instrument.set_password("s3cr3t-rotation").unwrap());
+
+        assert!(fake.written().contains("s3cr3t-rotation"));
+        assert!(logs.contains("credentials redacted"), "{logs}");
+        assert!(!logs.contains("s3cr3t-rotation"), "{logs}");
+    }
+
+    #[test]
+    fn rejected_password_is_not_saved() {
+        let fake = FakeInstrument::new().with_replies(&[
+            b"false\t1401\tpassword is too long\n",
+            b"KEITHLEY INSTRUMENTS INC.,MODEL 2636B,1,1.0.0\n",
+        ]);
+        let mut instrument =
+            ki2600::Instrument::new(Protocol::new(fake.clone()), Authentication::NoAuth);
+
+        assert_matches!(
+            instrument.set_password("a very long password"),
+            Err(InstrumentError::Other(e)) if e.contains("password is too long")
+        );
+        assert!(!fake.written().contains("*IDN?"));
+    }
+
+    #[test]
+    fn password_only_has_no_mode() {
+        let fake = FakeInstrument::new()
+            .with_replies(&[b"true\n", b"KEITHLEY INSTRUMENTS,MODEL 2450,1,1.0.0\n"]);
+        let mut instrument =
+            tti::Instrument::new(Protocol::new(fake.clone()), Authentication::NoAuth);
+        assert_eq!(
+            instrument.password_dialect(),
+            Some(PasswordDialect::PasswordOnly)
+        );
+        instrument.clear_password().unwrap();
+        assert!(fake.commands()[0]
+            .starts_with(r#"print(pcall(function() localnode.password = "" end))"#));
+        assert_matches!(instrument.password_mode(), Err(InstrumentError::Other(_)));
+        assert_matches!(
+            instrument.set_password("line\nbreak"),
+            Err(InstrumentError::Other(_))
+        );
+    }
+
+    #[test]
+    fn keyring_follows_password() {
+        let info = InstrumentInfo {
+            model: Model::_2636B,
+            serial_number: "1".to_string(),
+            ..InstrumentInfo::default()
+        };
+        let mut store = CredentialStore::open().unwrap();
+        update_keyring(&mut store, &info, "new").unwrap();
+        assert!(store.find(&info).unwrap().is_some());
+        update_keyring(&mut store, &info, "").unwrap();
+        assert_eq!(store.find(&info).unwrap(), None);
+    }
+
+    #[test]
+    fn parse_mode() {
+        assert_eq!(
+            "Remote".parse::<PasswordMode>().unwrap(),
+            PasswordMode::Remote
+        );
+        assert_eq!("lan".parse::<PasswordMode>().unwrap(), PasswordMode::Remote);
+        assert!("everything".parse::<PasswordMode>().is_err());
+        assert_eq!(PasswordMode::Web.to_string(), "web");
+    }
+}
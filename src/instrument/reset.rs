@@ -1,24 +1,27 @@
 //! A trait that allows for resetting the instrument.
 
-use std::io::Write;
+use std::io::{Read, Write};
 
-use crate::error::Result;
+use crate::{
+    error::Result,
+    instrument::{wait_complete, COMPLETE_TIMEOUT},
+};
 
 /// The [`Instrument`] can be reset.
 pub trait Reset
 where
-    Self: Write,
+    Self: Read + Write,
 {
     /// Reset the instrument.
     ///
     /// # Notes
-    /// - Reset the instrument using *RST.
+    /// - Reset the instrument using *RST, then wait for it to finish with
+    ///   [`wait_complete`].
     /// # Errors
     /// Returns an [`InstrumentError`] if any errors occurred.
     fn reset(&mut self) -> Result<()> {
         self.write_all(b"*RST\n")?;
         self.flush()?;
-        //Do we need to wait?
-        Ok(())
+        wait_complete(self, COMPLETE_TIMEOUT)
     }
 }
@@ -1,16 +1,25 @@
 //! A trait that allows for the writing of a TSP script file to the instrument.
 
-use std::io::{BufRead, Write};
+use std::io::{Read, Write};
 
 use bytes::Buf;
 
-use crate::error::Result;
+use crate::{
+    error::Result,
+    instrument::{wait_complete, COMPLETE_TIMEOUT},
+    model::capabilities::Capabilities,
+};
 
 /// The [`Instrument`] can write a script to be executed.
 pub trait Script
 where
-    Self: Write,
+    Self: Read + Write,
 {
+    /// The maximum length of a script name. Longer names are truncated.
+    fn max_script_name_len(&self) -> usize {
+        Capabilities::DEFAULT.max_script_name_len
+    }
+
     /// Write the given script to the instrument with the given name.
     ///
     /// # Parameters
@@ -24,6 +33,8 @@ where
     ///   scripting environment.
     /// - The given script content will only be validated by the instrument, but not
     ///   the [`write_script`] function.
+    /// - The script is loaded (and saved) before it is run, which is confirmed with
+    ///   [`wait_complete`].
     ///
     /// # Errors
     /// Returns an [`InstrumentError`] if any errors occurred.
@@ -34,32 +45,49 @@ where
         save_script: bool,
         run_script: bool,
     ) -> Result<()> {
-        // Truncate name otherwise we risk a Fatal Error (NS-2201)
-        let name = String::from_utf8_lossy(name.take(31).chunk()).to_string();
-        let mut script = script.reader(); //String::from_utf8_lossy(script.as_ref()).to_string();
-        self.write_all(b"_orig_prompts = localnode.prompts localnode.prompts = 0\n")?;
-        self.flush()?;
-        self.write_all(format!("{name}=nil\n").as_bytes())?;
-        self.flush()?;
-        self.write_all(format!("loadscript {name}\n").as_bytes())?;
+        write_script(self, name, script, save_script, run_script)
+    }
+}
 
-        self.write_all(script.fill_buf().unwrap())?;
-        self.write_all(b"\nendscript\n")?;
-        self.flush()?;
+/// Write the given script to `rw` with `loadscript`. This is the default
+/// implementation of [`Script::write_script`], for drivers that override it.
+///
+/// # Errors
+/// Returns an [`InstrumentError`](crate::InstrumentError) if any errors occurred.
+pub fn write_script<T: Script + ?Sized>(
+    rw: &mut T,
+    name: &[u8],
+    script: &[u8],
+    save_script: bool,
+    run_script: bool,
+) -> Result<()> {
+    // Truncate name otherwise we risk a Fatal Error (NS-2201)
+    let name =
+        String::from_utf8_lossy(Buf::take(name, rw.max_script_name_len()).chunk()).to_string();
+    rw.write_all(b"_orig_prompts = localnode.prompts localnode.prompts = 0\n")?;
+    rw.flush()?;
+    rw.write_all(format!("{name}=nil\n").as_bytes())?;
+    rw.flush()?;
+    rw.write_all(format!("loadscript {name}\n").as_bytes())?;
 
-        if save_script {
-            self.write_all(format!("{name}.save()\n").as_bytes())?;
-            self.flush()?;
-        }
+    rw.write_all(script)?;
+    rw.write_all(b"\nendscript\n")?;
+    rw.flush()?;
 
-        if run_script {
-            self.write_all(format!("{name}.run()\n").as_bytes())?;
-            self.flush()?;
-        }
+    if save_script {
+        rw.write_all(format!("{name}.save()\n").as_bytes())?;
+        rw.flush()?;
+    }
 
-        self.write_all(b"localnode.prompts = _orig_prompts _orig_prompts = nil\n")?;
-        self.flush()?;
+    wait_complete(rw, COMPLETE_TIMEOUT)?;
 
-        Ok(())
+    if run_script {
+        rw.write_all(format!("{name}.run()\n").as_bytes())?;
+        rw.flush()?;
     }
+
+    rw.write_all(b"localnode.prompts = _orig_prompts _orig_prompts = nil\n")?;
+    rw.flush()?;
+
+    Ok(())
 }
@@ -0,0 +1,127 @@
+//! A trait that allows for waiting for an instrument to request service instead of
+//! polling and sleeping.
+//!
+//! Configure what requests service with [`Status`](super::Status) (e.g.
+//! [`Status::enable_srq`](super::Status::enable_srq)), then wait for it:
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use tsp_toolkit_kic_lib::instrument::srq::SrqEvents;
+//! # fn f(instrument: &mut dyn tsp_toolkit_kic_lib::instrument::Instrument)
+//! # -> Result<(), tsp_toolkit_kic_lib::InstrumentError> {
+//! for stb in SrqEvents::new(instrument, Duration::from_secs(5)) {
+//!     println!("service requested: {}", stb?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use crate::{error::Result, instrument::status::StatusByte, InstrumentError};
+
+/// The [`Instrument`](crate::instrument::Instrument) can notify when it requests
+/// service.
+pub trait ServiceRequest {
+    /// Wait up to `timeout` for the instrument to request service and return the
+    /// status byte that was read when it did.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Timeout`] if the instrument didn't request service in
+    /// time, or any errors from reading the status byte.
+    fn wait_for_srq(&mut self, timeout: Duration) -> Result<StatusByte> {
+        let _ = timeout;
+        Err(InstrumentError::Other(
+            "service requests are not supported".to_string(),
+        ))
+    }
+
+    /// An iterator over each service request, which ends once no service request
+    /// arrives within `timeout` of the previous one. Use [`SrqEvents::new`] for
+    /// trait objects.
+    fn srq_events(&mut self, timeout: Duration) -> SrqEvents<'_, Self>
+    where
+        Self: Sized,
+    {
+        SrqEvents::new(self, timeout)
+    }
+}
+
+/// The service requests of an instrument. See [`ServiceRequest::srq_events`].
+///
+/// Each item is the status byte read when service was requested. The iterator ends
+/// after a timeout or after the first error.
+pub struct SrqEvents<'a, T: ServiceRequest + ?Sized> {
+    rw: &'a mut T,
+    timeout: Duration,
+    done: bool,
+}
+
+impl<'a, T: ServiceRequest + ?Sized> SrqEvents<'a, T> {
+    /// Iterate over the service requests of `rw`, until none arrives within
+    /// `timeout` of the previous one.
+    pub const fn new(rw: &'a mut T, timeout: Duration) -> Self {
+        Self {
+            rw,
+            timeout,
+            done: false,
+        }
+    }
+}
+
+impl<T: ServiceRequest + ?Sized> Iterator for SrqEvents<'_, T> {
+    type Item = Result<StatusByte>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.rw.wait_for_srq(self.timeout) {
+            Ok(stb) => Some(Ok(stb)),
+            Err(InstrumentError::Timeout { .. }) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::{collections::VecDeque, time::Duration};
+
+    use crate::{error::Result, instrument::status::StatusByte, InstrumentError};
+
+    use super::ServiceRequest;
+
+    /// Requests service once for each queued status byte, then times out.
+    struct Fake(VecDeque<u8>);
+
+    impl ServiceRequest for Fake {
+        fn wait_for_srq(&mut self, _: Duration) -> Result<StatusByte> {
+            self.0
+                .pop_front()
+                .map(StatusByte)
+                .ok_or_else(|| InstrumentError::Timeout {
+                    details: "a service request".to_string(),
+                })
+        }
+    }
+
+    #[test]
+    fn srq_events_end_at_timeout() {
+        let mut fake = Fake(VecDeque::from([0x41, 0xC0]));
+
+        let events = fake
+            .srq_events(Duration::from_millis(10))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(events, [StatusByte(0x41), StatusByte(0xC0)]);
+        assert_eq!(fake.srq_events(Duration::ZERO).count(), 0);
+    }
+}
@@ -0,0 +1,630 @@
+//! A trait that allows for configuring and decoding the IEEE 488.2 status model of
+//! an instrument and its TSP `status.*` register tree.
+//!
+//! Which registers an instrument has, and the names of their bits, depend on its
+//! family (see [`StatusModel`]).
+//!
+//! For example, to request service when the reading buffer of `smua` of a 2600
+//! series instrument has readings available:
+//!
+//! ```no_run
+//! # use tsp_toolkit_kic_lib::instrument::{status::Register, Status};
+//! # fn f(instrument: &mut dyn tsp_toolkit_kic_lib::instrument::Instrument)
+//! # -> Result<(), tsp_toolkit_kic_lib::InstrumentError> {
+//! let bit = instrument
+//!     .status_bit(Register::BufferAvailable, "SMUA")
+//!     .expect("2600 series instruments have this bit");
+//! // Enables SMUA in status.measurement.buffer_available, BUFFER_AVAILABLE in
+//! // status.measurement and MSB in status.request_enable.
+//! instrument.enable_srq(bit)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+    ops::BitOr,
+    time::Duration,
+};
+
+use crate::{error::Result, instrument::query_tsp, InstrumentError};
+
+/// The number of attempts to make when reading a register.
+const READ_ATTEMPTS: usize = 100;
+
+/// The delay between attempts when reading a register.
+const READ_DELAY: Duration = Duration::from_millis(20);
+
+/// The names of the set bits of `value`, given the mask and name of each bit.
+fn bit_names(value: u16, bits: &[(u16, &'static str)]) -> Vec<&'static str> {
+    bits.iter()
+        .filter(|(mask, _)| value & mask != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// The bits of the standard event status register (`*ESR?`) and its enable
+/// register (`*ESE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct StandardEvents(pub u8);
+
+impl StandardEvents {
+    /// OPC: all pending operations completed after `*OPC`.
+    pub const OPERATION_COMPLETE: Self = Self(0x01);
+    /// RQC: the instrument requested control of the bus.
+    pub const REQUEST_CONTROL: Self = Self(0x02);
+    /// QYE: output was requested but not available, or was lost.
+    pub const QUERY_ERROR: Self = Self(0x04);
+    /// DDE: an instrument-specific error occurred.
+    pub const DEVICE_ERROR: Self = Self(0x08);
+    /// EXE: a command couldn't be executed.
+    pub const EXECUTION_ERROR: Self = Self(0x10);
+    /// CME: a command couldn't be parsed.
+    pub const COMMAND_ERROR: Self = Self(0x20);
+    /// URQ: a front panel key was pressed.
+    pub const USER_REQUEST: Self = Self(0x40);
+    /// PON: the instrument was powered on.
+    pub const POWER_ON: Self = Self(0x80);
+
+    const NAMES: [(u16, &'static str); 8] = [
+        (0x01, "OPC"),
+        (0x02, "RQC"),
+        (0x04, "QYE"),
+        (0x08, "DDE"),
+        (0x10, "EXE"),
+        (0x20, "CME"),
+        (0x40, "URQ"),
+        (0x80, "PON"),
+    ];
+
+    /// Whether all the bits of `other` are set.
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The 488.2 mnemonics of the set bits (e.g. `EXE`).
+    #[must_use]
+    pub fn names(self) -> Vec<&'static str> {
+        bit_names(self.0.into(), &Self::NAMES)
+    }
+}
+
+impl BitOr for StandardEvents {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl Display for StandardEvents {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.names().join(" | "))
+    }
+}
+
+/// The bits of the status byte (`*STB?`) and the service request enable register
+/// (`*SRE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct StatusByte(pub u8);
+
+impl StatusByte {
+    /// MSB: an enabled bit of the measurement register is set.
+    pub const MEASUREMENT_SUMMARY: Self = Self(0x01);
+    /// SSB: an enabled bit of the system summary register is set.
+    pub const SYSTEM_SUMMARY: Self = Self(0x02);
+    /// EAV: the error queue isn't empty.
+    pub const ERROR_AVAILABLE: Self = Self(0x04);
+    /// QSB: an enabled bit of the questionable register is set.
+    pub const QUESTIONABLE_SUMMARY: Self = Self(0x08);
+    /// MAV: the output queue isn't empty.
+    pub const MESSAGE_AVAILABLE: Self = Self(0x10);
+    /// ESB: an enabled bit of the standard event register is set.
+    pub const EVENT_SUMMARY: Self = Self(0x20);
+    /// MSS: the instrument is requesting service.
+    pub const MASTER_SUMMARY: Self = Self(0x40);
+    /// OSB: an enabled bit of the operation register is set.
+    pub const OPERATION_SUMMARY: Self = Self(0x80);
+
+    const NAMES: [(u16, &'static str); 8] = [
+        (0x01, "MSB"),
+        (0x02, "SSB"),
+        (0x04, "EAV"),
+        (0x08, "QSB"),
+        (0x10, "MAV"),
+        (0x20, "ESB"),
+        (0x40, "MSS"),
+        (0x80, "OSB"),
+    ];
+
+    /// Whether all the bits of `other` are set.
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The mnemonics of the set bits (e.g. `MAV`).
+    #[must_use]
+    pub fn names(self) -> Vec<&'static str> {
+        bit_names(self.0.into(), &Self::NAMES)
+    }
+}
+
+impl BitOr for StatusByte {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl Display for StatusByte {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.names().join(" | "))
+    }
+}
+
+/// A register of the TSP `status.*` tree that summarizes into the status byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Register {
+    /// `status.measurement`
+    Measurement,
+    /// `status.operation`
+    Operation,
+    /// `status.questionable`
+    Questionable,
+    /// `status.measurement.buffer_available` of the 2600 series, which is summarized
+    /// by the `BUFFER_AVAILABLE` bit of [`Register::Measurement`].
+    BufferAvailable,
+}
+
+impl Register {
+    const fn tsp(self) -> &'static str {
+        match self {
+            Self::Measurement => "status.measurement",
+            Self::Operation => "status.operation",
+            Self::Questionable => "status.questionable",
+            Self::BufferAvailable => "status.measurement.buffer_available",
+        }
+    }
+
+    /// The bit of the status byte that summarizes this register, through its parent
+    /// register if it has one.
+    #[must_use]
+    pub const fn summary(self) -> StatusByte {
+        match self {
+            Self::Measurement | Self::BufferAvailable => StatusByte::MEASUREMENT_SUMMARY,
+            Self::Operation => StatusByte::OPERATION_SUMMARY,
+            Self::Questionable => StatusByte::QUESTIONABLE_SUMMARY,
+        }
+    }
+
+    /// The register and mask of the bit that summarizes this register, if it isn't
+    /// summarized directly into the status byte.
+    const fn parent(self) -> Option<(Self, u16)> {
+        match self {
+            Self::BufferAvailable => Some((Self::Measurement, 0x0100)),
+            Self::Measurement | Self::Operation | Self::Questionable => None,
+        }
+    }
+}
+
+/// One of the values that make up a [`Register`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegisterPart {
+    /// The current state of each bit. Read-only.
+    Condition,
+    /// The bits that were latched since the register was last read. Reading it
+    /// clears it. Read-only.
+    Event,
+    /// The event bits that are summarized into the status byte.
+    Enable,
+    /// The condition bits that set the event bit when they change from 0 to 1.
+    PositiveTransition,
+    /// The condition bits that set the event bit when they change from 1 to 0.
+    NegativeTransition,
+}
+
+impl RegisterPart {
+    const fn tsp(self) -> &'static str {
+        match self {
+            Self::Condition => "condition",
+            Self::Event => "event",
+            Self::Enable => "enable",
+            Self::PositiveTransition => "ptr",
+            Self::NegativeTransition => "ntr",
+        }
+    }
+
+    const fn is_writable(self) -> bool {
+        !matches!(self, Self::Condition | Self::Event)
+    }
+}
+
+/// A named bit of a [`Register`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegisterBit {
+    /// The register the bit is in.
+    pub register: Register,
+    /// The mask of the bit.
+    pub mask: u16,
+    /// The name of the bit, as used by the TSP constant (e.g.
+    /// `status.measurement.BUFFER_AVAILABLE`).
+    pub name: &'static str,
+}
+
+const fn bit(register: Register, mask: u16, name: &'static str) -> RegisterBit {
+    RegisterBit {
+        register,
+        mask,
+        name,
+    }
+}
+
+/// The named register bits of the 2600 series.
+pub const KI2600_STATUS_BITS: &[RegisterBit] = &[
+    bit(Register::Measurement, 0x0001, "VOLTAGE_LIMIT"),
+    bit(Register::Measurement, 0x0002, "CURRENT_LIMIT"),
+    bit(Register::Measurement, 0x0080, "READING_OVERFLOW"),
+    bit(Register::Measurement, 0x0100, "BUFFER_AVAILABLE"),
+    bit(Register::BufferAvailable, 0x0002, "SMUA"),
+    bit(Register::BufferAvailable, 0x0004, "SMUB"),
+    bit(Register::Operation, 0x0001, "CALIBRATING"),
+    bit(Register::Operation, 0x0008, "SWEEPING"),
+    bit(Register::Operation, 0x0010, "MEASURING"),
+    bit(Register::Operation, 0x0400, "TRIGGER_OVERRUN"),
+    bit(Register::Operation, 0x0800, "USER"),
+    bit(Register::Operation, 0x2000, "INSTRUMENT_SUMMARY"),
+    bit(Register::Operation, 0x4000, "PROGRAM_RUNNING"),
+    bit(Register::Questionable, 0x0100, "CALIBRATION"),
+    bit(Register::Questionable, 0x0200, "UNSTABLE_OUTPUT"),
+    bit(Register::Questionable, 0x1000, "OVER_TEMPERATURE"),
+    bit(Register::Questionable, 0x2000, "INSTRUMENT_SUMMARY"),
+];
+
+/// The named register bits of the 3700 series.
+pub const KI3700_STATUS_BITS: &[RegisterBit] = &[
+    bit(Register::Operation, 0x0800, "USER"),
+    bit(Register::Operation, 0x2000, "INSTRUMENT_SUMMARY"),
+    bit(Register::Operation, 0x4000, "PROGRAM_RUNNING"),
+    bit(Register::Questionable, 0x2000, "INSTRUMENT_SUMMARY"),
+];
+
+/// The registers of the `status.*` tree that an instrument has, and the names of
+/// their bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StatusModel {
+    /// The registers the instrument has.
+    pub registers: &'static [Register],
+    /// Whether the registers have the [`RegisterPart::PositiveTransition`] and
+    /// [`RegisterPart::NegativeTransition`] filters.
+    pub transition_filters: bool,
+    /// The named bits of the registers.
+    pub bits: &'static [RegisterBit],
+}
+
+impl StatusModel {
+    /// The registers that every TSP instrument has, without named bits.
+    pub const DEFAULT: Self = Self {
+        registers: &[Register::Operation, Register::Questionable],
+        transition_filters: false,
+        bits: &[],
+    };
+
+    /// The 2600 series.
+    pub const KI2600: Self = Self {
+        registers: &[
+            Register::Measurement,
+            Register::Operation,
+            Register::Questionable,
+            Register::BufferAvailable,
+        ],
+        transition_filters: true,
+        bits: KI2600_STATUS_BITS,
+    };
+
+    /// The 3700 series.
+    pub const KI3700: Self = Self {
+        registers: &[
+            Register::Measurement,
+            Register::Operation,
+            Register::Questionable,
+        ],
+        transition_filters: true,
+        bits: KI3700_STATUS_BITS,
+    };
+
+    /// TTI instruments. Their bits are assigned to events with
+    /// `status.operation.setmap()` and `status.questionable.setmap()`, so none are
+    /// named, and there is no `status.measurement`.
+    pub const TTI: Self = Self::DEFAULT;
+
+    /// Check that the instrument has the given part of the given register.
+    fn check(self, register: Register, part: RegisterPart) -> Result<()> {
+        let transition = matches!(
+            part,
+            RegisterPart::PositiveTransition | RegisterPart::NegativeTransition
+        );
+        if !self.registers.contains(&register) || (transition && !self.transition_filters) {
+            return Err(InstrumentError::Other(format!(
+                "{}.{} isn't available on this instrument",
+                register.tsp(),
+                part.tsp()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for StatusModel {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The [`Instrument`](crate::instrument::Instrument) has an IEEE 488.2 status model
+/// that can be configured and read.
+///
+/// # Default
+/// The default implementation uses the TSP `status.*` attributes and only the
+/// registers that all TSP instruments have. Drivers override
+/// [`Status::status_model`] with the registers and named bits of their family,
+/// based on the [`Capabilities`](crate::model::capabilities::Capabilities) of the
+/// instrument.
+pub trait Status: Read + Write {
+    /// The registers of this instrument and the names of their bits.
+    fn status_model(&self) -> StatusModel {
+        StatusModel::DEFAULT
+    }
+
+    /// The named bits of the registers of this instrument.
+    fn status_bits(&self) -> &'static [RegisterBit] {
+        self.status_model().bits
+    }
+
+    /// Find the named bit of the given register.
+    fn status_bit(&self, register: Register, name: &str) -> Option<RegisterBit> {
+        self.status_bits()
+            .iter()
+            .find(|b| b.register == register && b.name == name)
+            .copied()
+    }
+
+    /// The names of the bits of `register` that are set in `value`.
+    fn decode_status(&self, register: Register, value: u16) -> Vec<&'static str> {
+        self.status_bits()
+            .iter()
+            .filter(|b| b.register == register && value & b.mask != 0)
+            .map(|b| b.name)
+            .collect()
+    }
+
+    /// Read and clear the standard event status register (`*ESR?`).
+    ///
+    /// # Errors
+    /// Any IO errors, or [`InstrumentError::InformationRetrievalError`] if the
+    /// register couldn't be read.
+    fn standard_events(&mut self) -> Result<StandardEvents> {
+        read_u8(self, "status.standard.event").map(StandardEvents)
+    }
+
+    /// Read the standard event status enable register (`*ESE?`).
+    ///
+    /// # Errors
+    /// See [`Status::standard_events`].
+    fn standard_event_enable(&mut self) -> Result<StandardEvents> {
+        read_u8(self, "status.standard.enable").map(StandardEvents)
+    }
+
+    /// Set the standard event status enable register (`*ESE`).
+    ///
+    /// # Errors
+    /// Any IO errors.
+    fn set_standard_event_enable(&mut self, enable: StandardEvents) -> Result<()> {
+        write_value(self, "status.standard.enable", enable.0.into())
+    }
+
+    /// Read the service request enable register (`*SRE?`).
+    ///
+    /// # Errors
+    /// See [`Status::standard_events`].
+    fn service_request_enable(&mut self) -> Result<StatusByte> {
+        read_u8(self, "status.request_enable").map(StatusByte)
+    }
+
+    /// Set the service request enable register (`*SRE`).
+    ///
+    /// # Errors
+    /// Any IO errors.
+    fn set_service_request_enable(&mut self, enable: StatusByte) -> Result<()> {
+        write_value(self, "status.request_enable", enable.0.into())
+    }
+
+    /// Read part of a register of the `status.*` tree.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the instrument doesn't have the register part
+    /// (see [`Status::status_model`]), or see [`Status::standard_events`].
+    fn status_register(&mut self, register: Register, part: RegisterPart) -> Result<u16> {
+        self.status_model().check(register, part)?;
+        read_value(self, &format!("{}.{}", register.tsp(), part.tsp()))
+    }
+
+    /// Set part of a register of the `status.*` tree.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the instrument doesn't have the register part
+    /// or the part is read-only, or any IO errors.
+    fn set_status_register(
+        &mut self,
+        register: Register,
+        part: RegisterPart,
+        value: u16,
+    ) -> Result<()> {
+        self.status_model().check(register, part)?;
+        if !part.is_writable() {
+            return Err(InstrumentError::Other(format!(
+                "{}.{} is read-only",
+                register.tsp(),
+                part.tsp()
+            )));
+        }
+        write_value(self, &format!("{}.{}", register.tsp(), part.tsp()), value)
+    }
+
+    /// Request service when the given bit is set, by enabling it in its register,
+    /// enabling the bit that summarizes a subregister in its parent register, and
+    /// enabling the register summary in the service request enable register.
+    ///
+    /// # Errors
+    /// See [`Status::status_register`] and [`Status::set_status_register`].
+    fn enable_srq(&mut self, bit: RegisterBit) -> Result<()> {
+        let mut next = Some((bit.register, bit.mask));
+        while let Some((register, mask)) = next {
+            let enable = self.status_register(register, RegisterPart::Enable)?;
+            self.set_status_register(register, RegisterPart::Enable, enable | mask)?;
+            next = register.parent();
+        }
+        let sre = self.service_request_enable()?;
+        self.set_service_request_enable(sre | bit.register.summary())
+    }
+}
+
+fn read_value<T: Read + Write + ?Sized>(rw: &mut T, attribute: &str) -> Result<u16> {
+    let output = query_tsp(
+        rw,
+        &format!("print({attribute})"),
+        READ_ATTEMPTS,
+        READ_DELAY,
+    )?;
+    let value = output.last().map(String::as_str).unwrap_or_default();
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|v| format!("{v:.0}").parse::<u16>().ok())
+        .ok_or_else(|| InstrumentError::InformationRetrievalError {
+            details: format!("unable to read {attribute} from '{value}'"),
+        })
+}
+
+fn read_u8<T: Read + Write + ?Sized>(rw: &mut T, attribute: &str) -> Result<u8> {
+    let value = read_value(rw, attribute)?;
+    u8::try_from(value).map_err(|_| InstrumentError::InformationRetrievalError {
+        details: format!("{attribute} is out of range: {value}"),
+    })
+}
+
+fn write_value<T: Write + ?Sized>(rw: &mut T, attribute: &str, value: u16) -> Result<()> {
+    rw.write_all(format!("{attribute} = {value}\n").as_bytes())?;
+    rw.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::test_util::FakeInstrument;
+
+    use super::{Register, RegisterPart, StandardEvents, Status, StatusByte, StatusModel};
+
+    type Values = Arc<Mutex<HashMap<String, u16>>>;
+
+    /// Holds the value of each status attribute, which can be printed and assigned.
+    fn fake(values: &[(&str, u16)]) -> (FakeInstrument, Values) {
+        let values: Values = Arc::new(Mutex::new(
+            values.iter().map(|(a, v)| ((*a).to_string(), *v)).collect(),
+        ));
+        let state = Arc::clone(&values);
+        let fake = FakeInstrument::new().with_responder(move |command| {
+            if let Some((attribute, value)) = command.split_once(" = ") {
+                state
+                    .lock()
+                    .unwrap()
+                    .insert(attribute.to_string(), value.parse().unwrap());
+                return None;
+            }
+            let attribute = command.strip_prefix("print(")?.strip_suffix(')')?;
+            let value = state.lock().unwrap().get(attribute).copied();
+            let value = value.unwrap_or_default();
+            Some(format!("{value}.00000e+00\n").into_bytes())
+        });
+        (fake, values)
+    }
+
+    impl Status for FakeInstrument {
+        fn status_model(&self) -> StatusModel {
+            StatusModel::KI2600
+        }
+    }
+
+    #[test]
+    fn enable_srq_on_buffer_available() {
+        let (mut fake, values) = fake(&[
+            ("status.measurement.enable", 0x0001),
+            ("status.request_enable", 0x20),
+        ]);
+
+        let bit = fake.status_bit(Register::BufferAvailable, "SMUA").unwrap();
+        fake.enable_srq(bit).unwrap();
+
+        assert_eq!(
+            values.lock().unwrap()["status.measurement.buffer_available.enable"],
+            0x0002
+        );
+        assert_eq!(values.lock().unwrap()["status.measurement.enable"], 0x0101);
+        assert_eq!(values.lock().unwrap()["status.request_enable"], 0x21);
+        assert_eq!(
+            fake.service_request_enable().unwrap(),
+            StatusByte::EVENT_SUMMARY | StatusByte::MEASUREMENT_SUMMARY
+        );
+        assert!(fake
+            .set_status_register(Register::Measurement, RegisterPart::Event, 0)
+            .is_err());
+    }
+
+    #[test]
+    fn decode_registers() {
+        let (mut fake, _) = fake(&[
+            ("status.standard.event", 0x31),
+            ("status.operation.condition", 0x4010),
+        ]);
+
+        let events = fake.standard_events().unwrap();
+        assert!(events.contains(StandardEvents::OPERATION_COMPLETE | StandardEvents::COMMAND_ERROR));
+        assert_eq!(events.names(), ["OPC", "EXE", "CME"]);
+
+        let condition = fake
+            .status_register(Register::Operation, RegisterPart::Condition)
+            .unwrap();
+        assert_eq!(
+            fake.decode_status(Register::Operation, condition),
+            ["MEASURING", "PROGRAM_RUNNING"]
+        );
+        assert_eq!(StatusByte(0xC4).to_string(), "EAV | MSS | OSB");
+    }
+
+    #[test]
+    fn registers_depend_on_family() {
+        let tti = StatusModel::TTI;
+        assert!(tti
+            .check(Register::Measurement, RegisterPart::Enable)
+            .is_err());
+        assert!(tti
+            .check(Register::Operation, RegisterPart::PositiveTransition)
+            .is_err());
+        assert!(tti.check(Register::Operation, RegisterPart::Enable).is_ok());
+        assert!(StatusModel::KI3700
+            .check(Register::BufferAvailable, RegisterPart::Event)
+            .is_err());
+        assert!(StatusModel::KI2600
+            .check(Register::Questionable, RegisterPart::NegativeTransition)
+            .is_ok());
+    }
+}
@@ -0,0 +1,330 @@
+//! A trait that allows for managing a TSP-Link network and working with its remote
+//! nodes.
+
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{
+    error::Result,
+    instrument::{info::InstrumentInfo, query_tsp},
+    model::{Model, Vendor},
+    InstrumentError,
+};
+
+/// The highest node number allowed on a TSP-Link network.
+pub const MAX_NODE: u16 = 64;
+
+/// The number of attempts to make when reading a response over TSP-Link.
+const READ_ATTEMPTS: usize = 100;
+
+/// The delay between attempts when reading a response over TSP-Link.
+const READ_DELAY: Duration = Duration::from_millis(50);
+
+/// The state of the TSP-Link network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LinkState {
+    /// The network has been initialized and the nodes can be reached.
+    Online,
+    /// The network has not been initialized or was disconnected.
+    Offline,
+}
+
+impl FromStr for LinkState {
+    type Err = InstrumentError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim() {
+            "online" => Ok(Self::Online),
+            "offline" => Ok(Self::Offline),
+            s => Err(InstrumentError::InformationRetrievalError {
+                details: format!("unknown TSP-Link state '{s}'"),
+            }),
+        }
+    }
+}
+
+impl Display for LinkState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Online => write!(f, "online"),
+            Self::Offline => write!(f, "offline"),
+        }
+    }
+}
+
+/// A node on the TSP-Link network.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NodeInfo {
+    /// The node number.
+    pub node: u16,
+    /// The information about the instrument at this node.
+    pub info: InstrumentInfo,
+}
+
+/// The [`Instrument`](crate::instrument::Instrument) can manage a TSP-Link network.
+///
+/// # Default
+/// The default implementation uses `tsplink.initialize()` and `node[N].version`.
+/// Drivers override [`TspLink::tsplink_initialize_command`] and
+/// [`TspLink::node_revision_attribute`] based on the
+/// [`Capabilities`](crate::model::capabilities::Capabilities) of the instrument.
+pub trait TspLink: Read + Write {
+    /// The TSP expression that initializes the TSP-Link network and evaluates to the
+    /// number of nodes that were found, or [`None`] if TSP-Link isn't supported.
+    fn tsplink_initialize_command(&self) -> Option<&'static str> {
+        Some("tsplink.initialize()")
+    }
+
+    /// The attribute of `node[N]` that holds the firmware revision of the node.
+    fn node_revision_attribute(&self) -> &'static str {
+        "version"
+    }
+
+    /// Initialize (or re-initialize) the TSP-Link network.
+    ///
+    /// # Returns
+    /// The number of nodes that were found, including this one.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if TSP-Link isn't supported, any IO errors, or
+    /// [`InstrumentError::InformationRetrievalError`] if the number of nodes could
+    /// not be read.
+    fn initialize_tsplink(&mut self) -> Result<usize> {
+        let Some(initialize) = self.tsplink_initialize_command() else {
+            return Err(InstrumentError::Other(
+                "TSP-Link is not supported by this instrument".to_string(),
+            ));
+        };
+        let command = format!("print({initialize})");
+        let output = query_tsp(self, &command, READ_ATTEMPTS, READ_DELAY)?;
+        let count = output.last().map(String::as_str).unwrap_or_default();
+        count
+            .parse::<f64>()
+            .ok()
+            .and_then(|c| format!("{c:.0}").parse::<usize>().ok())
+            .ok_or_else(|| InstrumentError::InformationRetrievalError {
+                details: format!("unable to read the number of TSP-Link nodes from '{count}'"),
+            })
+    }
+
+    /// Get the current state of the TSP-Link network.
+    ///
+    /// # Errors
+    /// Any IO errors, or [`InstrumentError::InformationRetrievalError`] if the state
+    /// could not be read.
+    fn tsplink_state(&mut self) -> Result<LinkState> {
+        let output = query_tsp(self, "print(tsplink.state)", READ_ATTEMPTS, READ_DELAY)?;
+        output
+            .last()
+            .map(String::as_str)
+            .unwrap_or_default()
+            .parse()
+    }
+
+    /// Get the information of every node on the TSP-Link network, including this
+    /// one, in node order.
+    ///
+    /// # Errors
+    /// Any IO errors, or [`InstrumentError::InformationRetrievalError`] if the node
+    /// information could not be read.
+    fn tsplink_nodes(&mut self) -> Result<Vec<NodeInfo>> {
+        let revision = self.node_revision_attribute();
+        let command = format!(
+            "for i = 1, {MAX_NODE} do if node[i] ~= nil then \
+             print(i, node[i].model, node[i].serialno, node[i].{revision}) end end"
+        );
+        query_tsp(self, &command, READ_ATTEMPTS, READ_DELAY)?
+            .iter()
+            .map(|line| parse_node_line(line))
+            .collect()
+    }
+
+    /// Execute the given TSP command on the given remote node.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the node number is out of range or any IO
+    /// errors.
+    fn execute_on_node(&mut self, node: u16, command: &str) -> Result<()> {
+        check_node(node)?;
+        self.write_all(format!("node[{node}].execute({})\n", long_string(command)).as_bytes())?;
+        self.flush()?;
+        Ok(())
+    }
+
+    /// Evaluate the given TSP expression on the given remote node and return what it
+    /// prints. For example, `query_node(2, "smua.measure.v()")` will measure the
+    /// voltage of SMU A on node 2.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the node number is out of range or the output
+    /// could not be read, or any IO errors.
+    fn query_node(&mut self, node: u16, expression: &str) -> Result<String> {
+        check_node(node)?;
+        let output = query_tsp(
+            self,
+            &format!("print(node[{node}].{expression})"),
+            READ_ATTEMPTS,
+            READ_DELAY,
+        )?;
+        Ok(output.join("\n"))
+    }
+
+    /// Write the given script to the given remote node with the given name.
+    ///
+    /// # Parameters
+    /// - `node` - is the node number of the remote node
+    /// - `name` - is the environment-compatible name of the script
+    /// - `script` - is the contents of the script
+    /// - `save_script` - `true` if the script should be saved to non-volatile memory
+    /// - `run_script` - `true` if the script should be run after load
+    ///
+    /// # Errors
+    /// See [`TspLink::execute_on_node`].
+    fn write_script_to_node(
+        &mut self,
+        node: u16,
+        name: &str,
+        script: &[u8],
+        save_script: bool,
+        run_script: bool,
+    ) -> Result<()> {
+        let script = String::from_utf8_lossy(script);
+        let save = if save_script {
+            format!(" {name}.save()")
+        } else {
+            String::new()
+        };
+        let run = if run_script {
+            format!(" {name}.run()")
+        } else {
+            String::new()
+        };
+        let command = format!(
+            "{name} = script.new({}, \"{name}\"){save}{run}",
+            long_string(&script)
+        );
+        self.execute_on_node(node, &command)
+    }
+}
+
+fn check_node(node: u16) -> Result<()> {
+    if (1..=MAX_NODE).contains(&node) {
+        Ok(())
+    } else {
+        Err(InstrumentError::Other(format!(
+            "TSP-Link node {node} is out of range (1-{MAX_NODE})"
+        )))
+    }
+}
+
+/// Parse a line of `node number, model, serial number, revision` separated by tabs.
+fn parse_node_line(line: &str) -> Result<NodeInfo> {
+    let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+    let [node, model, serial_number, revision] = fields[..] else {
+        return Err(InstrumentError::InformationRetrievalError {
+            details: format!("unable to parse TSP-Link node information from '{line}'"),
+        });
+    };
+    let Some(node) = node
+        .parse::<f64>()
+        .ok()
+        .and_then(|n| format!("{n:.0}").parse::<u16>().ok())
+    else {
+        return Err(InstrumentError::InformationRetrievalError {
+            details: format!("unable to parse TSP-Link node number from '{line}'"),
+        });
+    };
+    let model: Model = model.parse()?;
+    let vendor = if model.is_mp() {
+        Vendor::Tektronix
+    } else {
+        Vendor::Keithley
+    };
+    Ok(NodeInfo {
+        node,
+        info: InstrumentInfo {
+            vendor,
+            model,
+            serial_number: serial_number.to_string(),
+            firmware_rev: Some(revision.to_string()),
+        },
+    })
+}
+
+/// Quote the given string as a Lua long string (`[==[...]==]`) that doesn't need any
+/// escaping.
+fn long_string(s: &str) -> String {
+    let mut level = String::new();
+    let with_close = format!("{s}]");
+    while with_close.contains(&format!("]{level}]")) {
+        level.push('=');
+    }
+    format!("[{level}[{s}]{level}]")
+}
+
+#[cfg(test)]
+mod unit {
+    use crate::{
+        model::{Model, Vendor},
+        test_util::FakeInstrument,
+    };
+
+    use super::{long_string, LinkState, TspLink};
+
+    impl TspLink for FakeInstrument {}
+
+    #[test]
+    fn initialize_and_state() {
+        let mut fake = FakeInstrument::new().with_lines(&["3.00000e+00"]);
+        assert_eq!(fake.initialize_tsplink().unwrap(), 3);
+        assert!(fake.written().starts_with("print(tsplink.initialize())"));
+
+        let mut fake = FakeInstrument::new().with_lines(&["online"]);
+        assert_eq!(fake.tsplink_state().unwrap(), LinkState::Online);
+    }
+
+    #[test]
+    fn enumerate_nodes() {
+        let mut fake = FakeInstrument::new().with_lines(&[
+            "1\t2450\t04331961\t1.7.12b",
+            "2.00000e+00\tMP5103\t12345\t0.9.0",
+        ]);
+
+        let nodes = fake.tsplink_nodes().expect("nodes should be read");
+
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].node, 1);
+        assert_eq!(nodes[0].info.model, Model::_2450);
+        assert_eq!(nodes[0].info.vendor, Vendor::Keithley);
+        assert_eq!(nodes[0].info.serial_number, "04331961");
+        assert_eq!(nodes[0].info.firmware_rev.as_deref(), Some("1.7.12b"));
+        assert_eq!(nodes[1].node, 2);
+        assert_eq!(nodes[1].info.vendor, Vendor::Tektronix);
+    }
+
+    #[test]
+    fn write_script_to_remote_node() {
+        let mut fake = FakeInstrument::new();
+
+        fake.write_script_to_node(2, "test", b"print([[hi]])", true, false)
+            .expect("script should be written");
+
+        assert_eq!(
+            fake.written(),
+            "node[2].execute([==[test = script.new([=[print([[hi]])]=], \"test\") test.save()]==])\n"
+        );
+        assert!(fake.execute_on_node(65, "beeper.beep(1, 440)").is_err());
+    }
+
+    #[test]
+    fn long_string_levels() {
+        assert_eq!(long_string("abc"), "[[abc]]");
+        assert_eq!(long_string("a]]b"), "[=[a]]b]=]");
+        assert_eq!(long_string("a]"), "[=[a]]=]");
+        assert_eq!(long_string("a]="), "[[a]=]]");
+    }
+}
@@ -0,0 +1,277 @@
+//! Options that control how a connection to an instrument is established.
+
+use std::{
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+use socket2::{SockRef, TcpKeepalive};
+
+use tracing::trace;
+
+use crate::{error::Result, interface::host::HostAddr, InstrumentError};
+
+/// How the instrument resource should be locked when it is opened.
+///
+/// Locking only applies to VISA connections. Raw socket connections ignore this
+/// setting.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum LockMode {
+    /// Do not lock the resource.
+    #[default]
+    None,
+    /// Obtain an exclusive lock on the resource so that no other session can use it.
+    Exclusive,
+    /// Obtain a shared lock on the resource.
+    Shared,
+}
+
+/// The options used when connecting to an instrument.
+///
+/// The [`Default`] options match the behavior of the library before these options
+/// were configurable, except that [`ConnectOptions::http_timeout`] now applies to
+/// every LXI identification fetch (VXI-11, HiSLIP and VISA socket fetches used to
+/// time out after 100 ms). Only the fields that need to change should be set:
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use tsp_toolkit_kic_lib::interface::connect_options::ConnectOptions;
+///
+/// let options = ConnectOptions {
+///     connect_timeout: Some(Duration::from_secs(10)),
+///     read_timeout: Some(Duration::from_secs(5)),
+///     ..ConnectOptions::default()
+/// };
+/// ```
+///
+/// When serialized, all durations are represented as a whole number of milliseconds
+/// and any missing fields take their default value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ConnectOptions {
+    /// The maximum amount of time to wait for a TCP connection to be established.
+    /// [`None`] uses the operating system default.
+    #[serde(with = "millis::option")]
+    pub connect_timeout: Option<Duration>,
+    /// The read timeout for raw socket connections. [`None`] blocks indefinitely.
+    #[serde(with = "millis::option")]
+    pub read_timeout: Option<Duration>,
+    /// The write timeout for raw socket connections. [`None`] blocks indefinitely.
+    #[serde(with = "millis::option")]
+    pub write_timeout: Option<Duration>,
+    /// The total amount of time allowed when fetching the LXI identification page,
+    /// for every kind of LAN connection.
+    #[serde(with = "millis")]
+    pub http_timeout: Duration,
+    /// The idle time before TCP keepalive probes are sent. [`None`] disables
+    /// keepalive.
+    #[serde(with = "millis::option")]
+    pub tcp_keepalive: Option<Duration>,
+    /// Disable Nagle's algorithm on raw socket connections.
+    pub tcp_nodelay: bool,
+    /// The maximum amount of time VISA may take to open the resource. [`None`] waits
+    /// indefinitely.
+    #[serde(with = "millis::option")]
+    pub visa_open_timeout: Option<Duration>,
+    /// The I/O timeout for VISA connections. [`None`] leaves the VISA default.
+    #[serde(with = "millis::option")]
+    pub visa_timeout: Option<Duration>,
+    /// How the resource should be locked when it is opened.
+    pub lock_mode: LockMode,
+    /// A second port to open for raw socket connections, on which the status byte is
+    /// read with `*STB?` without disturbing the instrument output. TSP instruments
+    /// only accept one LAN session at a time, so this is only useful for a second
+    /// port that doesn't count as a session. Without it, raw socket connections
+    /// read the status byte in-band while idle and can't wait for service requests.
+    pub status_port: Option<u16>,
+    /// The dead socket termination port of raw socket connections. Connecting to it
+    /// closes every LAN session of the instrument, which is how a device clear is
+    /// done without VISA.
+    pub device_clear_port: u16,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: None,
+            read_timeout: Some(Duration::from_millis(1000)),
+            write_timeout: Some(Duration::from_millis(1000)),
+            http_timeout: Duration::from_secs(2),
+            tcp_keepalive: None,
+            tcp_nodelay: false,
+            visa_open_timeout: None,
+            visa_timeout: None,
+            lock_mode: LockMode::None,
+            status_port: None,
+            device_clear_port: 5030,
+        }
+    }
+}
+
+impl ConnectOptions {
+    /// Open a [`TcpStream`] to the given address and apply the socket options.
+    ///
+    /// # Errors
+    /// Any [`std::io::Error`] that occurs while connecting or configuring the socket.
+    pub fn connect_tcp(&self, addr: &SocketAddr) -> Result<TcpStream> {
+        let stream = match self.connect_timeout {
+            Some(timeout) => TcpStream::connect_timeout(addr, timeout)?,
+            None => TcpStream::connect(addr)?,
+        };
+        stream.set_read_timeout(self.read_timeout)?;
+        stream.set_write_timeout(self.write_timeout)?;
+        stream.set_nodelay(self.tcp_nodelay)?;
+        if let Some(time) = self.tcp_keepalive {
+            SockRef::from(&stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+        }
+        Ok(stream)
+    }
+
+    /// Resolve the given address and open a [`TcpStream`] to the first of its
+    /// socket addresses that accepts the connection, applying the socket options
+    /// (see [`ConnectOptions::connect_tcp`]).
+    ///
+    /// # Errors
+    /// An error is returned if the address cannot be resolved. If no address
+    /// accepts the connection, the error from the last attempt is returned.
+    pub fn connect_host(&self, addr: &HostAddr) -> Result<TcpStream> {
+        let mut last_error = None;
+        for socket_addr in addr.resolve()? {
+            trace!("Trying to connect to {addr} at {socket_addr}");
+            match self.connect_tcp(&socket_addr) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(
+            last_error.unwrap_or_else(|| InstrumentError::ConnectionError {
+                details: format!("unable to connect to {addr}"),
+            }),
+        )
+    }
+}
+
+/// (De)serialize a [`Duration`] as a whole number of milliseconds.
+mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u64(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Duration, D::Error> {
+        Ok(Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+
+    pub mod option {
+        use std::time::Duration;
+
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        #[allow(clippy::ref_option)] // The signature is dictated by `serde(with)`
+        pub fn serialize<S: Serializer>(
+            duration: &Option<Duration>,
+            serializer: S,
+        ) -> std::result::Result<S::Ok, S::Error> {
+            match duration {
+                Some(d) => super::serialize(d, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> std::result::Result<Option<Duration>, D::Error> {
+            Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_millis))
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::{net::TcpListener, time::Duration};
+
+    use super::ConnectOptions;
+    use crate::interface::host::{Host, HostAddr};
+
+    #[test]
+    fn connect_tcp_applies_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("should bind to a local port");
+        let addr = listener
+            .local_addr()
+            .expect("listener should have an address");
+
+        let options = ConnectOptions {
+            connect_timeout: Some(Duration::from_secs(1)),
+            read_timeout: Some(Duration::from_secs(2)),
+            write_timeout: None,
+            tcp_keepalive: Some(Duration::from_secs(30)),
+            tcp_nodelay: true,
+            ..ConnectOptions::default()
+        };
+
+        let stream = options
+            .connect_tcp(&addr)
+            .expect("should connect to local listener");
+
+        assert_eq!(
+            stream.read_timeout().expect("should get read timeout"),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            stream.write_timeout().expect("should get write timeout"),
+            None
+        );
+        assert!(stream.nodelay().expect("should get nodelay"));
+        assert!(socket2::SockRef::from(&stream)
+            .keepalive()
+            .expect("should get keepalive"));
+    }
+
+    #[test]
+    fn connect_host_by_name() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("should bind to a local port");
+        let port = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .port();
+
+        let stream = ConnectOptions::default()
+            .connect_host(&HostAddr::new(Host::Name("localhost".to_string()), port))
+            .expect("should connect to local listener by name");
+
+        assert_eq!(
+            stream
+                .peer_addr()
+                .expect("should have a peer address")
+                .port(),
+            port
+        );
+    }
+
+    #[test]
+    fn deserialize_partial_options() {
+        let actual: ConnectOptions =
+            serde_json::from_str(r#"{"connect_timeout": 1500, "read_timeout": null}"#)
+                .expect("should deserialize partial options");
+
+        let expected = ConnectOptions {
+            connect_timeout: Some(Duration::from_millis(1500)),
+            read_timeout: None,
+            ..ConnectOptions::default()
+        };
+
+        assert_eq!(actual, expected);
+    }
+}
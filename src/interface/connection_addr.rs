@@ -1,6 +1,6 @@
 use std::fmt::Display;
 use std::io::{Read, Write};
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::net::SocketAddr;
 use std::str::FromStr;
 use std::time::Duration;
 
@@ -15,23 +15,36 @@ use visa_rs::{AsResourceManager, VisaString};
 use tracing::error;
 
 use crate::instrument::info::InstrumentInfo;
+use crate::interface::connect_options::ConnectOptions;
+use crate::interface::host::{Host, HostAddr};
+use crate::interface::resource::{LanDevice, VisaResource};
 use crate::model::{Model, Vendor};
 use crate::InstrumentError;
 
+/// How to connect to an instrument.
+///
+/// A [`ConnectionInfo`] is parsed from either a host name or IP address (with an
+/// optional port, which defaults to 5025) or a VISA resource string (see
+/// [`crate::interface::resource`]). Host names are kept as given and only resolved
+/// when connecting. The `string` of each VISA variant is the
+/// canonical form of the resource string, so the [`Display`] output of any
+/// [`ConnectionInfo`] parses back to the same value.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ConnectionInfo {
     /// A raw socket connection.
-    Lan { addr: SocketAddr },
+    Lan { addr: HostAddr },
     /// A VXI-11 connection (requires VISA to use)
-    Vxi11 { string: String, addr: Ipv4Addr },
+    Vxi11 { string: String, addr: Host },
 
     #[allow(clippy::doc_markdown)] // RustDoc wants "HiSLIP" to be a code term, but it isn't
     /// A HiSLIP connection (requires VISA to use)
-    HiSlip { string: String, addr: IpAddr },
+    HiSlip { string: String, addr: Host },
     /// A raw socket connection over VISA (requires VISA to use)
-    VisaSocket { string: String, addr: SocketAddr },
+    VisaSocket { string: String, addr: HostAddr },
     /// A GPIB connection (requires VISA to use)
     Gpib { string: String },
+    /// A serial connection (requires VISA to use)
+    Asrl { string: String },
     /// A USBTMC connection (requires VISA to use)
     Usb {
         string: String,
@@ -50,13 +63,30 @@ impl Display for ConnectionInfo {
             | Self::HiSlip { string, .. }
             | Self::VisaSocket { string, .. }
             | Self::Gpib { string }
-            | Self::Usb { string, .. } => string.to_string(),
+            | Self::Asrl { string }
+            | Self::Usb { string, .. } => string.clone(),
         };
 
         write!(f, "{s}")
     }
 }
 
+/// A [`ConnectionInfo`] is serialized as its connection string.
+impl serde::Serialize for ConnectionInfo {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A [`ConnectionInfo`] is deserialized from any connection string accepted by
+/// [`ConnectionInfo::from_str`].
+impl<'de> serde::Deserialize<'de> for ConnectionInfo {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 impl ConnectionInfo {
     /// Check to see if this instrument can be connected to.
     ///
@@ -70,12 +100,14 @@ impl ConnectionInfo {
             | Self::Vxi11 { .. }
             | Self::HiSlip { .. }
             | Self::VisaSocket { .. } => self.get_info(),
-            Self::Gpib { string } | Self::Usb { string, .. } => self.ping_usb_gpib(string),
+            Self::Gpib { string } | Self::Asrl { string } | Self::Usb { string, .. } => {
+                self.ping_visa_resource(string)
+            }
         }
     }
 
     #[cfg(feature = "visa")]
-    fn ping_usb_gpib(&self, addr: &str) -> Result<InstrumentInfo, InstrumentError> {
+    fn ping_visa_resource(&self, addr: &str) -> Result<InstrumentInfo, InstrumentError> {
         let rm = match visa_rs::DefaultRM::new() {
             Ok(r) => r,
             Err(e) => {
@@ -103,7 +135,7 @@ impl ConnectionInfo {
     #[cfg(not(feature = "visa"))]
     #[allow(clippy::unused_self)] // This is the counterpart to the visa enabled-version so we need
                                   // to keep the same shape.
-    const fn ping_usb_gpib(&self, _: &str) -> Result<InstrumentInfo, InstrumentError> {
+    const fn ping_visa_resource(&self, _: &str) -> Result<InstrumentInfo, InstrumentError> {
         Err(InstrumentError::NoVisa)
     }
 
@@ -114,12 +146,26 @@ impl ConnectionInfo {
     /// or the IDN string (depending on the connection protocol)
     #[instrument(skip(self))]
     pub fn get_info(&self) -> Result<InstrumentInfo, InstrumentError> {
+        self.get_info_with_options(&ConnectOptions::default())
+    }
+
+    /// Get the info from this connection information, using the given
+    /// [`ConnectOptions`] for any connections that need to be made.
+    ///
+    /// # Errors
+    /// Errors may occur when fetching or parsing the data from LXI identification page
+    /// or the IDN string (depending on the connection protocol)
+    #[instrument(skip(self, options))]
+    pub fn get_info_with_options(
+        &self,
+        options: &ConnectOptions,
+    ) -> Result<InstrumentInfo, InstrumentError> {
         trace!("getting instrument info");
         let xml = match self {
-            Self::Lan { addr } if addr.ip().is_loopback() => {
+            Self::Lan { addr } if addr.host.is_loopback() => {
                 trace!("getting info over loopback");
                 //Special case for TSPop
-                let mut inst = TcpStream::connect(addr)?;
+                let mut inst = options.connect_host(addr)?;
                 inst.write_all(b"abort\n")?;
                 inst.write_all(b"*CLS\n")?;
                 std::thread::sleep(Duration::from_millis(100));
@@ -135,7 +181,7 @@ impl ConnectionInfo {
             | Self::HiSlip { .. }
             | Self::VisaSocket { .. } => {
                 trace!("getting information from LXI identification page");
-                self.get_lxi_id_xml()?
+                self.get_lxi_id_xml(options)?
             }
             // The USBTMC resource string requires the USB model identifier, so we can
             // get that directly and return it.
@@ -153,11 +199,11 @@ impl ConnectionInfo {
                     firmware_rev: None,
                 });
             }
-            // GPIB just uses `*IDN?` and assumes the 2nd comma-separated element is
-            // the value we need, parses it, and directly returns it.
-            Self::Gpib { string } => {
-                trace!("Getting information over GPIB");
-                return Self::get_gpib_info(string);
+            // GPIB and serial just use `*IDN?` and assume the 2nd comma-separated
+            // element is the value we need, parse it, and directly return it.
+            Self::Gpib { string } | Self::Asrl { string } => {
+                trace!("Getting information with *IDN?");
+                return Self::get_idn_info(string, options);
             }
         };
 
@@ -180,10 +226,15 @@ impl ConnectionInfo {
     }
 
     #[cfg(feature = "visa")]
-    fn get_gpib_info(string: &str) -> Result<InstrumentInfo, InstrumentError> {
+    fn get_idn_info(
+        string: &str,
+        options: &ConnectOptions,
+    ) -> Result<InstrumentInfo, InstrumentError> {
         use std::io::{Read, Write};
 
-        use visa_rs::{flags::AccessMode, AsResourceManager, DefaultRM, TIMEOUT_INFINITE};
+        use visa_rs::{AsResourceManager, DefaultRM, TIMEOUT_INFINITE};
+
+        use crate::protocol::visa::access_mode;
 
         let rm = DefaultRM::new()?;
         let Some(string) = VisaString::from_string(string.to_string()) else {
@@ -191,7 +242,11 @@ impl ConnectionInfo {
                 "unable to convert '{string}' to VisaString"
             )));
         };
-        let mut inst = rm.open(&string, AccessMode::NO_LOCK, TIMEOUT_INFINITE)?;
+        let mut inst = rm.open(
+            &string,
+            access_mode(options.lock_mode),
+            options.visa_open_timeout.unwrap_or(TIMEOUT_INFINITE),
+        )?;
         inst.write_all(b"abort\n")?;
         inst.write_all(b"*CLS\n")?;
         std::thread::sleep(Duration::from_millis(100));
@@ -203,30 +258,35 @@ impl ConnectionInfo {
     }
 
     #[cfg(not(feature = "visa"))]
-    const fn get_gpib_info(_string: &str) -> Result<InstrumentInfo, InstrumentError> {
+    const fn get_idn_info(
+        _string: &str,
+        _options: &ConnectOptions,
+    ) -> Result<InstrumentInfo, InstrumentError> {
         Err(InstrumentError::NoVisa)
     }
 
-    fn get_lxi_id_xml(&self) -> Result<Option<String>, InstrumentError> {
+    fn get_lxi_id_xml(&self, options: &ConnectOptions) -> Result<Option<String>, InstrumentError> {
         // FIXME: If an instrument is serving `https`, the certificate will be self-signed.
         // for now, just ignore it. A better option would be to load a copy of the cert
         // into the rustls backend.
-        let client = Client::builder()
+        let mut client = Client::builder()
             .danger_accept_invalid_certs(true)
-            .timeout(Duration::from_millis(100))
-            .build()?;
+            .timeout(options.http_timeout);
+        if let Some(timeout) = options.connect_timeout {
+            client = client.connect_timeout(timeout);
+        }
+        let client = client.build()?;
 
         // Most connection modes require getting an xml document, so assume that is the
         // case and save the XML document off here. Anything that can get the model
         // number via a different route (i.e. `*IDN?` or from the resource string)
         // should return directly from the associated match arm.
         let xml = match self {
-            Self::Lan { addr } => {
+            Self::Lan { addr } | Self::VisaSocket { addr, .. } => {
                 // We don't know whether the instrument serves `https` or not, but if
                 // it does it will redirect, so just use `http`
                 client
-                    .get(format!("http://{}/lxi/identification", addr.ip()))
-                    .timeout(Duration::from_secs(2))
+                    .get(format!("http://{}/lxi/identification", addr.host))
                     .send()?
                     .text()?
             }
@@ -246,96 +306,64 @@ impl ConnectionInfo {
                     .send()?
                     .text()?
             }
-            Self::VisaSocket { addr, .. } => {
-                // We don't know whether the instrument serves `https` or not, but if
-                // it does it will redirect, so just use `http`
-                client
-                    .get(format!("http://{}/lxi/identification", addr.ip()))
-                    .send()?
-                    .text()?
-            }
-            Self::Usb { .. } | Self::Gpib { .. } => return Ok(None),
+            Self::Usb { .. } | Self::Gpib { .. } | Self::Asrl { .. } => return Ok(None),
         };
 
         Ok(Some(xml))
     }
 }
 
-/// If a string starts with `0x`, assume this is a u16 represented in hex. Otherwise,
-/// assume it is decimal. Ignore parsing errors and just return an [`Some`] if
-/// conversion succeeds or [`None`] otherwise.
-fn u16_from_str(s: &str) -> Option<u16> {
-    if s.len() < 2 {
-        return s.parse::<u16>().ok();
-    }
-    match &s[..2] {
-        "0x" => u16::from_str_radix(&s[2..], 16).ok(),
-        _ => s.parse::<u16>().ok(),
-    }
-}
-
 fn parse_raw_socket(s: &str) -> Option<ConnectionInfo> {
-    // If the user supplied an IP address has a port number on it...
-    let ip = s.parse::<SocketAddr>();
-    if let Ok(ip) = ip {
-        return Some(ConnectionInfo::Lan { addr: ip });
+    // If the user supplied an IP address or host name with a port number on it...
+    if let Ok(addr) = s.parse::<HostAddr>() {
+        return Some(ConnectionInfo::Lan { addr });
     }
-    // If the user supplied an IP address with NO port number, default to port 5025
-    let ip = s.parse::<IpAddr>();
-    if let Ok(ip) = ip {
+    // If the user supplied an IP address or host name with NO port number, default
+    // to port 5025
+    if let Ok(host) = s.parse::<Host>() {
         return Some(ConnectionInfo::Lan {
-            addr: SocketAddr::new(ip, 5025),
+            addr: HostAddr::new(host, 5025),
         });
     }
     None
 }
 
-fn parse_tcpip_resource_string(s: &str, parts: &[&str]) -> Result<ConnectionInfo, InstrumentError> {
-    match parts
-        .last()
-        .expect("should have at least 1 element")
-        .chars()
-        .next()
-    {
-        Some('S') => {
-            let addr = parts[1].parse::<IpAddr>()?;
-            let port = match parts[2].parse::<u16>() {
-                Ok(p) => p,
-                Err(e) => {
-                    return Err(InstrumentError::AddressParsingError(format!(
-                        "unable to parse port number '{}': {e}",
-                        parts[2]
-                    )));
-                }
-            };
-            Ok(ConnectionInfo::VisaSocket {
-                string: s.trim().to_string(),
-                addr: SocketAddr::new(addr, port),
-            })
-        }
-        Some('I') => {
-            if matches!(
-                &parts[parts.len().saturating_sub(2)].chars().next(),
-                Some('h')
-            ) {
-                let addr = parts[1].parse::<IpAddr>()?;
-                Ok(ConnectionInfo::HiSlip {
-                    string: s.trim().to_string(),
-                    addr,
-                })
-            } else {
-                // if it is a TCPIP connection that doesn't explicitly declare `hislip`, just
-                // assume it is VXI-11
-                let addr = parts[1].parse::<Ipv4Addr>()?;
-                Ok(ConnectionInfo::Vxi11 {
-                    string: s.trim().to_string(),
-                    addr,
-                })
-            }
+impl TryFrom<VisaResource> for ConnectionInfo {
+    type Error = InstrumentError;
+
+    fn try_from(resource: VisaResource) -> Result<Self, Self::Error> {
+        let string = resource.to_string();
+        match resource {
+            VisaResource::TcpipInstr {
+                host,
+                device: LanDevice::HiSlip { .. },
+                ..
+            } => Ok(Self::HiSlip { addr: host, string }),
+            VisaResource::TcpipInstr {
+                host,
+                device: LanDevice::Vxi11(_),
+                ..
+            } => Ok(Self::Vxi11 { addr: host, string }),
+            VisaResource::TcpipSocket { host, port, .. } => Ok(Self::VisaSocket {
+                addr: HostAddr::new(host, port),
+                string,
+            }),
+            VisaResource::UsbInstr {
+                vendor_id,
+                product_id,
+                serial,
+                interface_number,
+                ..
+            } => Ok(Self::Usb {
+                vendor: vendor_id.try_into()?,
+                model: Model::from_pid(product_id),
+                serial,
+                interface_number,
+                string,
+            }),
+            VisaResource::GpibInstr { .. } => Ok(Self::Gpib { string }),
+            VisaResource::AsrlInstr { .. } => Ok(Self::Asrl { string }),
         }
-        _ => Err(InstrumentError::AddressParsingError(format!(
-            "'{s}' did not have a recognized VISA address"
-        ))),
     }
 }
 
@@ -343,65 +371,16 @@ impl FromStr for ConnectionInfo {
     type Err = InstrumentError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Some(lan) = parse_raw_socket(s) {
-            return Ok(lan);
+        let s = s.trim();
+        // A bare IP address is never a VISA resource string, but a host name such as
+        // `asrl1` might be, so only fall back to host names if `s` isn't a valid VISA
+        // resource string.
+        if let Ok(ip) = s.parse::<SocketAddr>() {
+            return Ok(Self::Lan { addr: ip.into() });
         }
-
-        let resource_string: Vec<&str> = s.split("::").collect();
-
-        if resource_string.is_empty() {
-            return Err(InstrumentError::AddressParsingError(format!(
-                "{s} did not contain the expected format for a connection string"
-            )));
-        }
-
-        match &resource_string[0][..3] {
-            "TCP" => parse_tcpip_resource_string(s, &resource_string),
-            "USB" => {
-                if resource_string.len() < 4 {
-                    Err(InstrumentError::AddressParsingError(format!(
-                        "'{s}' did not have a recognized VISA address"
-                    )))
-                } else {
-                    let Some(vid) = u16_from_str(resource_string[1]) else {
-                        return Err(InstrumentError::AddressParsingError(format!(
-                            "'{s}' did not have a recognized VISA address"
-                        )));
-                    };
-                    let vid: Vendor = vid.try_into()?;
-                    let Some(pid) = u16_from_str(resource_string[2]) else {
-                        return Err(InstrumentError::AddressParsingError(format!(
-                            "'{s}' did not have a recognized VISA address"
-                        )));
-                    };
-                    let pid: Model = Model::from_pid(pid);
-
-                    let serial = resource_string[3].to_string();
-
-                    let interface_number = if resource_string.len() == 5 {
-                        if resource_string[4].starts_with("INSTR") {
-                            None
-                        } else {
-                            u16_from_str(resource_string[4])
-                        }
-                    } else {
-                        u16_from_str(resource_string[4])
-                    };
-                    Ok(Self::Usb {
-                        string: s.trim().to_string(),
-                        vendor: vid,
-                        model: pid,
-                        serial,
-                        interface_number,
-                    })
-                }
-            }
-            "GPI" => Ok(Self::Gpib {
-                string: s.trim().to_string(),
-            }),
-            _ => Err(InstrumentError::AddressParsingError(format!(
-                "'{s}' did not have a recognized VISA address"
-            ))),
+        match s.parse::<VisaResource>() {
+            Ok(resource) => resource.try_into(),
+            Err(e) => parse_raw_socket(s).ok_or(e),
         }
     }
 }
@@ -412,7 +391,10 @@ pub mod unit {
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
     use super::{ConnectionInfo, Vendor};
-    use crate::model::Model;
+    use crate::{
+        interface::host::{Host, HostAddr},
+        model::Model,
+    };
 
     fn multitest_connection_info_parse(cases: &[(&str, ConnectionInfo)]) {
         for c in cases {
@@ -433,7 +415,7 @@ pub mod unit {
             (
                 "USB0::0x5e6::0x2461::12345678::INSTR",
                 ConnectionInfo::Usb {
-                    string: "USB0::0x5e6::0x2461::12345678::INSTR".to_string(),
+                    string: "USB0::0x05E6::0x2461::12345678::INSTR".to_string(),
                     vendor: Vendor::Keithley,
                     model: Model::_2461,
                     serial: "12345678".to_string(),
@@ -443,7 +425,7 @@ pub mod unit {
             (
                 "USB0::0x699::0x5103::asdf::INSTR",
                 ConnectionInfo::Usb {
-                    string: "USB0::0x699::0x5103::asdf::INSTR".to_string(),
+                    string: "USB0::0x0699::0x5103::asdf::INSTR".to_string(),
                     vendor: Vendor::Tektronix,
                     model: Model::MP5103,
                     serial: "asdf".to_string(),
@@ -453,7 +435,7 @@ pub mod unit {
             (
                 "USB0::0x699::0x2636::asdf::1::INSTR",
                 ConnectionInfo::Usb {
-                    string: "USB0::0x699::0x2636::asdf::1::INSTR".to_string(),
+                    string: "USB0::0x0699::0x2636::asdf::1::INSTR".to_string(),
                     vendor: Vendor::Tektronix,
                     model: Model::_2636B,
                     serial: "asdf".to_string(),
@@ -469,13 +451,13 @@ pub mod unit {
             (
                 "192.168.0.1",
                 ConnectionInfo::Lan {
-                    addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)), 5025),
+                    addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)), 5025).into(),
                 },
             ),
             (
                 "192.168.0.1:5",
                 ConnectionInfo::Lan {
-                    addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)), 5),
+                    addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)), 5).into(),
                 },
             ),
             (
@@ -486,7 +468,8 @@ pub mod unit {
                             0x2001, 0x0db8, 0x0, 0x0, 0x0, 0xff00, 0x0042, 0x8329,
                         )),
                         5025,
-                    ),
+                    )
+                    .into(),
                 },
             ),
             (
@@ -497,7 +480,8 @@ pub mod unit {
                             0x2001, 0x0db8, 0x0, 0x0, 0x0, 0xff00, 0x0042, 0x8329,
                         )),
                         5025,
-                    ),
+                    )
+                    .into(),
                 },
             ),
             (
@@ -508,7 +492,8 @@ pub mod unit {
                             0x2001, 0x0db8, 0x0, 0x0, 0x0, 0xff00, 0x0042, 0x8329,
                         )),
                         5025,
-                    ),
+                    )
+                    .into(),
                 },
             ),
             (
@@ -519,7 +504,8 @@ pub mod unit {
                             0x2001, 0x0db8, 0x0, 0x0, 0x0, 0xff00, 0x0042, 0x8329,
                         )),
                         3,
-                    ),
+                    )
+                    .into(),
                 },
             ),
         ]);
@@ -532,23 +518,208 @@ pub mod unit {
                 "TCPIP0::192.168.0.1::inst0::INSTR",
                 ConnectionInfo::Vxi11 {
                     string: "TCPIP0::192.168.0.1::inst0::INSTR".to_string(),
-                    addr: Ipv4Addr::new(192, 168, 0, 1),
+                    addr: Host::Ip(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))),
+                },
+            ),
+            (
+                "TCPIP1::[2001:db8::ff00:42:8329]",
+                ConnectionInfo::Vxi11 {
+                    string: "TCPIP1::[2001:db8::ff00:42:8329]::inst0::INSTR".to_string(),
+                    addr: Host::Ip(IpAddr::V6(Ipv6Addr::new(
+                        0x2001, 0x0db8, 0x0, 0x0, 0x0, 0xff00, 0x0042, 0x8329,
+                    ))),
+                },
+            ),
+            (
+                "TCPIP0::192.168.0.1::hislip0,4881::INSTR",
+                ConnectionInfo::HiSlip {
+                    string: "TCPIP0::192.168.0.1::hislip0,4881::INSTR".to_string(),
+                    addr: Host::Ip(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))),
                 },
             ),
             (
                 "TCPIP0::192.168.0.1::hislip0::INSTR",
                 ConnectionInfo::HiSlip {
                     string: "TCPIP0::192.168.0.1::hislip0::INSTR".to_string(),
-                    addr: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
+                    addr: Host::Ip(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))),
                 },
             ),
             (
                 "TCPIP0::192.168.0.1::123::SOCKET",
                 ConnectionInfo::VisaSocket {
                     string: "TCPIP0::192.168.0.1::123::SOCKET".to_string(),
-                    addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)), 123),
+                    addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)), 123).into(),
                 },
             ),
         ]);
     }
+
+    #[test]
+    fn gpib_and_serial_parse() {
+        multitest_connection_info_parse(&[
+            (
+                "GPIB1::5::3",
+                ConnectionInfo::Gpib {
+                    string: "GPIB1::5::3::INSTR".to_string(),
+                },
+            ),
+            (
+                "ASRL4::INSTR",
+                ConnectionInfo::Asrl {
+                    string: "ASRL4::INSTR".to_string(),
+                },
+            ),
+        ]);
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        for input in ["", ":::", "[::", "T:", "é"] {
+            assert!(
+                input.parse::<ConnectionInfo>().is_err(),
+                "'{input}' should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn host_name_parse() {
+        multitest_connection_info_parse(&[
+            (
+                "mysmu.lab.local:5030",
+                ConnectionInfo::Lan {
+                    addr: HostAddr::new(Host::Name("mysmu.lab.local".to_string()), 5030),
+                },
+            ),
+            (
+                "MySmu",
+                ConnectionInfo::Lan {
+                    addr: HostAddr::new(Host::Name("mysmu".to_string()), 5025),
+                },
+            ),
+            (
+                "TCPIP0::mysmu.lab.local::inst0::INSTR",
+                ConnectionInfo::Vxi11 {
+                    string: "TCPIP0::mysmu.lab.local::inst0::INSTR".to_string(),
+                    addr: Host::Name("mysmu.lab.local".to_string()),
+                },
+            ),
+            (
+                "TCPIP0::mysmu::hislip0::INSTR",
+                ConnectionInfo::HiSlip {
+                    string: "TCPIP0::mysmu::hislip0::INSTR".to_string(),
+                    addr: Host::Name("mysmu".to_string()),
+                },
+            ),
+            (
+                "TCPIP0::mysmu::5025::SOCKET",
+                ConnectionInfo::VisaSocket {
+                    string: "TCPIP0::mysmu::5025::SOCKET".to_string(),
+                    addr: HostAddr::new(Host::Name("mysmu".to_string()), 5025),
+                },
+            ),
+        ]);
+        assert_eq!(
+            "mysmu.lab.local:5030"
+                .parse::<ConnectionInfo>()
+                .unwrap()
+                .to_string(),
+            "mysmu.lab.local:5030"
+        );
+    }
+
+    mod fuzz {
+        use std::net::{IpAddr, SocketAddr};
+
+        use proptest::prelude::*;
+
+        use super::super::ConnectionInfo;
+
+        /// Strategy for a host name.
+        fn host_name() -> impl Strategy<Value = String> {
+            "[a-zA-Z]([a-zA-Z0-9-]{0,6}[a-zA-Z0-9])?(\\.[a-z][a-z0-9]{0,5}){0,2}"
+        }
+
+        /// Strategy for a host as it could appear in a `TCPIP` resource.
+        fn visa_ip() -> impl Strategy<Value = String> {
+            prop_oneof![
+                any::<IpAddr>().prop_map(|ip| match ip {
+                    IpAddr::V4(v4) => v4.to_string(),
+                    IpAddr::V6(v6) => format!("[{v6}]"),
+                }),
+                host_name(),
+            ]
+        }
+
+        /// Strategy for any valid connection string, written with arbitrary keyword
+        /// case and optional parts left out.
+        fn connection_string() -> impl Strategy<Value = String> {
+            let board = prop::option::of(0u16..4)
+                .prop_map(|b| b.map(|b| b.to_string()).unwrap_or_default());
+            let instr = prop_oneof![Just(""), Just("::INSTR"), Just("::instr")];
+            prop_oneof![
+                any::<SocketAddr>().prop_map(|a| a.to_string()),
+                any::<IpAddr>().prop_map(|a| a.to_string()),
+                host_name(),
+                (host_name(), any::<u16>()).prop_map(|(h, p)| format!("{h}:{p}")),
+                (board.clone(), visa_ip(), instr.clone())
+                    .prop_map(|(b, ip, i)| format!("TCPIP{b}::{ip}::inst0{i}")),
+                (
+                    board.clone(),
+                    visa_ip(),
+                    0u16..4,
+                    prop::option::of(any::<u16>()),
+                    instr.clone()
+                )
+                    .prop_map(|(b, ip, n, port, i)| {
+                        let port = port.map(|p| format!(",{p}")).unwrap_or_default();
+                        format!("tcpip{b}::{ip}::HiSLIP{n}{port}{i}")
+                    }),
+                (board.clone(), visa_ip(), any::<u16>())
+                    .prop_map(|(b, ip, port)| format!("TCPIP{b}::{ip}::{port}::SOCKET")),
+                (
+                    board.clone(),
+                    prop_oneof![Just(0x05e6u16), Just(0x0699u16)],
+                    any::<u16>(),
+                    "[A-Za-z0-9]{1,10}",
+                    prop::option::of(any::<u16>()),
+                    instr.clone(),
+                )
+                    .prop_map(|(b, vid, pid, serial, intf, i)| {
+                        let intf = intf.map(|n| format!("::{n}")).unwrap_or_default();
+                        format!("USB{b}::{vid:#x}::{pid}::{serial}{intf}{i}")
+                    }),
+                (board, 0u8..=30, prop::option::of(0u8..=30), instr.clone()).prop_map(
+                    |(b, p, sec, i)| {
+                        let sec = sec.map(|n| format!("::{n}")).unwrap_or_default();
+                        format!("GPIB{b}::{p}{sec}{i}")
+                    }
+                ),
+                (0u16..256, instr).prop_map(|(port, i)| format!("ASRL{port}{i}")),
+            ]
+        }
+
+        proptest! {
+            #[test]
+            fn parse_never_panics(s in "\\PC*") {
+                let _ = s.parse::<ConnectionInfo>();
+            }
+
+            #[test]
+            fn parse_resource_like_never_panics(
+                s in "(?i)(tcpip|usb|gpib|asrl)[0-9é]{0,3}(::[\\[\\]0-9a-fx.:,é]{0,12}){0,6}"
+            ) {
+                let _ = s.parse::<ConnectionInfo>();
+            }
+
+            #[test]
+            fn display_round_trips(s in connection_string()) {
+                let parsed: ConnectionInfo = s.parse().expect("generated string should parse");
+                let canonical = parsed.to_string();
+                let reparsed: ConnectionInfo = canonical.parse().expect("canonical string should parse");
+                prop_assert_eq!(&reparsed, &parsed);
+                prop_assert_eq!(reparsed.to_string(), canonical);
+            }
+        }
+    }
 }
@@ -0,0 +1,291 @@
+//! Network hosts that may be given as an IP address or a DNS host name.
+
+use std::{
+    fmt::Display,
+    hash::{Hash, Hasher},
+    net::{IpAddr, Ipv6Addr, SocketAddr, ToSocketAddrs},
+    str::FromStr,
+};
+
+use crate::{error::Result, InstrumentError};
+
+/// A network host, given either as an IP address or as a DNS host name.
+///
+/// Host names are kept as given and only resolved when a connection is made, so they
+/// are preserved for display and LXI lookups. Like DNS, host names are compared and
+/// hashed without regard to case. IPv6 addresses are displayed in square brackets so
+/// they can be used directly in URLs and VISA resource strings.
+#[derive(Debug, Clone)]
+pub enum Host {
+    /// An IPv4 or IPv6 address.
+    Ip(IpAddr),
+    /// A DNS host name.
+    Name(String),
+}
+
+impl Host {
+    /// Check whether this host refers to the local machine. Host names other than
+    /// `localhost` are not resolved and are assumed to be remote.
+    #[must_use]
+    pub fn is_loopback(&self) -> bool {
+        match self {
+            Self::Ip(ip) => ip.is_loopback(),
+            Self::Name(name) => name.eq_ignore_ascii_case("localhost"),
+        }
+    }
+
+    /// Resolve this host to all of its socket addresses with the given port.
+    ///
+    /// # Errors
+    /// An error is returned if the host name cannot be resolved or resolves to no
+    /// addresses.
+    pub fn resolve(&self, port: u16) -> Result<Vec<SocketAddr>> {
+        let addrs: Vec<SocketAddr> = match self {
+            Self::Ip(ip) => vec![SocketAddr::new(*ip, port)],
+            Self::Name(name) => (name.as_str(), port).to_socket_addrs()?.collect(),
+        };
+        if addrs.is_empty() {
+            return Err(InstrumentError::ConnectionError {
+                details: format!("'{self}' did not resolve to any addresses"),
+            });
+        }
+        Ok(addrs)
+    }
+}
+
+impl PartialEq for Host {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Ip(a), Self::Ip(b)) => a == b,
+            (Self::Name(a), Self::Name(b)) => a.eq_ignore_ascii_case(b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Host {}
+
+impl Hash for Host {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Self::Ip(ip) => {
+                0u8.hash(state);
+                ip.hash(state);
+            }
+            Self::Name(name) => {
+                1u8.hash(state);
+                name.to_ascii_lowercase().hash(state);
+            }
+        }
+    }
+}
+
+impl From<IpAddr> for Host {
+    fn from(value: IpAddr) -> Self {
+        Self::Ip(value)
+    }
+}
+
+impl Display for Host {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ip(IpAddr::V6(ip)) => write!(f, "[{ip}]"),
+            Self::Ip(ip) => write!(f, "{ip}"),
+            Self::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl FromStr for Host {
+    type Err = InstrumentError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(v6) = s.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+            return v6
+                .parse::<Ipv6Addr>()
+                .map(|ip| Self::Ip(ip.into()))
+                .map_err(|_| {
+                    InstrumentError::AddressParsingError(format!(
+                        "'{s}' is not a valid IPv6 address"
+                    ))
+                });
+        }
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            return Ok(Self::Ip(ip));
+        }
+        if is_host_name(s) {
+            return Ok(Self::Name(s.to_string()));
+        }
+        Err(InstrumentError::AddressParsingError(format!(
+            "'{s}' is not a valid host name or IP address"
+        )))
+    }
+}
+
+/// A [`Host`] and a port.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HostAddr {
+    /// The host to connect to.
+    pub host: Host,
+    /// The port to connect to.
+    pub port: u16,
+}
+
+impl HostAddr {
+    /// Create a new [`HostAddr`] from anything that can be converted into a [`Host`].
+    pub fn new(host: impl Into<Host>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+        }
+    }
+
+    /// Resolve this address to all of its socket addresses.
+    ///
+    /// # Errors
+    /// See [`Host::resolve`].
+    pub fn resolve(&self) -> Result<Vec<SocketAddr>> {
+        self.host.resolve(self.port)
+    }
+}
+
+impl From<SocketAddr> for HostAddr {
+    fn from(value: SocketAddr) -> Self {
+        Self::new(value.ip(), value.port())
+    }
+}
+
+impl Display for HostAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+impl FromStr for HostAddr {
+    type Err = InstrumentError;
+
+    /// Parse a `host:port` pair. IPv6 addresses must be in square brackets.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(addr.into());
+        }
+        let Some((name, port)) = s.rsplit_once(':') else {
+            return Err(InstrumentError::AddressParsingError(format!(
+                "'{s}' does not have a port number"
+            )));
+        };
+        let Ok(port) = port.parse::<u16>() else {
+            return Err(InstrumentError::AddressParsingError(format!(
+                "unable to parse port number '{port}' in '{s}'"
+            )));
+        };
+        if !is_host_name(name) {
+            return Err(InstrumentError::AddressParsingError(format!(
+                "'{name}' is not a valid host name"
+            )));
+        }
+        Ok(Self::new(Host::Name(name.to_string()), port))
+    }
+}
+
+/// Check whether the given string is a valid DNS host name.
+#[must_use]
+pub fn is_host_name(host: &str) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        // A purely numeric host name would be confused with an IPv4 address
+        && !host.split('.').all(|label| label.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod unit {
+    use std::{
+        hash::{DefaultHasher, Hash, Hasher},
+        net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    };
+
+    use super::{Host, HostAddr};
+
+    #[test]
+    fn host_parse_and_display() {
+        for (input, expected, display) in [
+            (
+                "192.168.0.1",
+                Host::Ip(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))),
+                "192.168.0.1",
+            ),
+            ("[::1]", Host::Ip(IpAddr::V6(Ipv6Addr::LOCALHOST)), "[::1]"),
+            ("::1", Host::Ip(IpAddr::V6(Ipv6Addr::LOCALHOST)), "[::1]"),
+            (
+                "MySmu.Lab.Local",
+                Host::Name("mysmu.lab.local".to_string()),
+                "MySmu.Lab.Local",
+            ),
+        ] {
+            let actual: Host = input.parse().expect("host should parse");
+            assert_eq!(actual, expected);
+            assert_eq!(actual.to_string(), display);
+        }
+
+        for input in ["", "-bad", "bad..name", "1.2.3", "[mysmu]", "my smu"] {
+            assert!(input.parse::<Host>().is_err(), "'{input}' should not parse");
+        }
+    }
+
+    #[test]
+    fn host_names_ignore_case() {
+        let hash = |host: &Host| {
+            let mut hasher = DefaultHasher::new();
+            host.hash(&mut hasher);
+            hasher.finish()
+        };
+        let upper: Host = "MySmu.Lab.Local".parse().unwrap();
+        let lower: Host = "mysmu.lab.local".parse().unwrap();
+
+        assert_eq!(upper, lower);
+        assert_eq!(hash(&upper), hash(&lower));
+        assert_ne!(upper, "othersmu".parse::<Host>().unwrap());
+        assert!(Host::Name("LocalHost".to_string()).is_loopback());
+        assert_eq!(
+            "MySmu:5025".parse::<HostAddr>().unwrap().to_string(),
+            "MySmu:5025"
+        );
+    }
+
+    #[test]
+    fn host_addr_parse() {
+        assert_eq!(
+            "mysmu.lab.local:5025".parse::<HostAddr>().unwrap(),
+            HostAddr::new(Host::Name("mysmu.lab.local".to_string()), 5025)
+        );
+        assert_eq!(
+            "[::1]:80".parse::<HostAddr>().unwrap(),
+            HostAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 80)
+        );
+        assert!("mysmu.lab.local".parse::<HostAddr>().is_err());
+        assert!("mysmu.lab.local:99999".parse::<HostAddr>().is_err());
+    }
+
+    #[test]
+    fn localhost_resolves() {
+        let addrs = HostAddr::new(Host::Name("localhost".to_string()), 5025)
+            .resolve()
+            .expect("localhost should resolve");
+
+        assert!(addrs
+            .iter()
+            .all(|a| a.ip().is_loopback() && a.port() == 5025));
+    }
+}
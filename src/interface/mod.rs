@@ -11,7 +11,10 @@ use std::{
 };
 
 pub mod async_stream;
+pub mod connect_options;
 pub mod connection_addr;
+pub mod host;
+pub mod resource;
 
 /// Defines a marker trait that we will implement on each device interface
 pub trait Interface: NonBlock + Read + Write {}
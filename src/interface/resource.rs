@@ -0,0 +1,543 @@
+//! A parser for VISA resource strings.
+//!
+//! The supported grammar is (keywords are case-insensitive, `[]` denotes an
+//! optional part):
+//!
+//! ```text
+//! TCPIP[board]::host[::lan device name][::INSTR]
+//! TCPIP[board]::host::port::SOCKET
+//! USB[board]::vendor id::product id::serial number[::interface number][::INSTR]
+//! GPIB[board]::primary address[::secondary address][::INSTR]
+//! ASRL<port>[::INSTR]
+//! ```
+//!
+//! where `host` is an IPv4 address, an IPv6 address in square brackets or a host name
+//! and `lan device name` is a VXI-11 device name (`inst0`, `gpib0,5`, ...) or a
+//! HiSLIP device name with an optional port (`hislip0`, `hislip0,4880`).
+//!
+//! Every [`VisaResource`] is displayed in a canonical form that parses back to the
+//! same value: the board number and `::INSTR` suffix are always present, keywords
+//! and device names are normalized, and USB IDs are written as 4-digit hex numbers.
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::{interface::host::Host, InstrumentError};
+
+/// The LAN device name of a `TCPIP::INSTR` resource.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LanDevice {
+    /// A VXI-11 device name such as `inst0` or `gpib0,5`.
+    Vxi11(String),
+
+    /// A HiSLIP device `hislip<index>` with an optional port.
+    HiSlip {
+        /// The HiSLIP sub-address index.
+        index: u16,
+        /// The port of the HiSLIP server, if it isn't the default.
+        port: Option<u16>,
+    },
+}
+
+impl Default for LanDevice {
+    fn default() -> Self {
+        Self::Vxi11("inst0".to_string())
+    }
+}
+
+impl Display for LanDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Vxi11(name) => write!(f, "{name}"),
+            Self::HiSlip { index, port: None } => write!(f, "hislip{index}"),
+            Self::HiSlip {
+                index,
+                port: Some(port),
+            } => write!(f, "hislip{index},{port}"),
+        }
+    }
+}
+
+impl FromStr for LanDevice {
+    type Err = InstrumentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        if let Some(rest) = name.strip_prefix("hislip") {
+            let (index, port) = match rest.split_once(',') {
+                Some((index, port)) => (index, Some(port)),
+                None => (rest, None),
+            };
+            let index = parse_number(index, s, "HiSLIP index")?;
+            let port = port
+                .map(|p| parse_number(p, s, "HiSLIP port"))
+                .transpose()?;
+            return Ok(Self::HiSlip { index, port });
+        }
+
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == ',' || c == '_')
+            || !name.starts_with(|c: char| c.is_ascii_alphabetic())
+        {
+            return Err(InstrumentError::AddressParsingError(format!(
+                "'{s}' is not a valid LAN device name"
+            )));
+        }
+        Ok(Self::Vxi11(name))
+    }
+}
+
+/// A parsed VISA resource string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum VisaResource {
+    /// `TCPIP[board]::host[::lan device name][::INSTR]`
+    TcpipInstr {
+        /// The board number.
+        board: u16,
+        /// The host name or IP address of the instrument.
+        host: Host,
+        /// The LAN device name.
+        device: LanDevice,
+    },
+    /// `TCPIP[board]::host::port::SOCKET`
+    TcpipSocket {
+        /// The board number.
+        board: u16,
+        /// The host name or IP address of the instrument.
+        host: Host,
+        /// The port of the raw socket.
+        port: u16,
+    },
+    /// `USB[board]::vendor id::product id::serial number[::interface number][::INSTR]`
+    UsbInstr {
+        /// The board number.
+        board: u16,
+        /// The USB vendor ID.
+        vendor_id: u16,
+        /// The USB product ID.
+        product_id: u16,
+        /// The serial number of the instrument.
+        serial: String,
+        /// The USB interface number, if given.
+        interface_number: Option<u16>,
+    },
+    /// `GPIB[board]::primary address[::secondary address][::INSTR]`
+    GpibInstr {
+        /// The board number.
+        board: u16,
+        /// The primary GPIB address (0-30).
+        primary: u8,
+        /// The secondary GPIB address (0-30), if given.
+        secondary: Option<u8>,
+    },
+    /// `ASRL<port>[::INSTR]`
+    AsrlInstr {
+        /// The serial port number or absolute device path.
+        port: String,
+    },
+}
+
+impl Display for VisaResource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TcpipInstr {
+                board,
+                host,
+                device,
+            } => write!(f, "TCPIP{board}::{host}::{device}::INSTR"),
+            Self::TcpipSocket { board, host, port } => {
+                write!(f, "TCPIP{board}::{host}::{port}::SOCKET")
+            }
+            Self::UsbInstr {
+                board,
+                vendor_id,
+                product_id,
+                serial,
+                interface_number,
+            } => {
+                write!(
+                    f,
+                    "USB{board}::{vendor_id:#06X}::{product_id:#06X}::{serial}"
+                )?;
+                if let Some(interface_number) = interface_number {
+                    write!(f, "::{interface_number}")?;
+                }
+                write!(f, "::INSTR")
+            }
+            Self::GpibInstr {
+                board,
+                primary,
+                secondary,
+            } => {
+                write!(f, "GPIB{board}::{primary}")?;
+                if let Some(secondary) = secondary {
+                    write!(f, "::{secondary}")?;
+                }
+                write!(f, "::INSTR")
+            }
+            Self::AsrlInstr { port } => write!(f, "ASRL{port}::INSTR"),
+        }
+    }
+}
+
+impl FromStr for VisaResource {
+    type Err = InstrumentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let mut parts = split_resource(s);
+
+        let Some(first) = parts.first().copied() else {
+            return Err(unrecognized(s));
+        };
+
+        if let Some(board) = interface_board(first, "TCPIP") {
+            let board = parse_board(board, s)?;
+            let Some(host) = parts.get(1) else {
+                return Err(unrecognized(s));
+            };
+            let host: Host = host.parse()?;
+            if parts.len() == 4
+                && parts
+                    .last()
+                    .is_some_and(|p| p.eq_ignore_ascii_case("SOCKET"))
+            {
+                let port = parse_number(parts[2], s, "port number")?;
+                return Ok(Self::TcpipSocket { board, host, port });
+            }
+            strip_instr(&mut parts);
+            let device = match parts.len() {
+                2 => LanDevice::default(),
+                3 => parts[2].parse()?,
+                _ => return Err(unrecognized(s)),
+            };
+            return Ok(Self::TcpipInstr {
+                board,
+                host,
+                device,
+            });
+        }
+
+        if let Some(board) = interface_board(first, "USB") {
+            let board = parse_board(board, s)?;
+            strip_instr(&mut parts);
+            let (vendor_id, product_id, serial, interface_number) = match parts[..] {
+                [_, vid, pid, serial] => (vid, pid, serial, None),
+                [_, vid, pid, serial, interface_number] => {
+                    (vid, pid, serial, Some(interface_number))
+                }
+                _ => return Err(unrecognized(s)),
+            };
+            let serial = serial.trim();
+            if serial.is_empty() || !serial.chars().all(is_name_char) {
+                return Err(InstrumentError::AddressParsingError(format!(
+                    "'{s}' does not have a valid serial number"
+                )));
+            }
+            return Ok(Self::UsbInstr {
+                board,
+                vendor_id: parse_number(vendor_id, s, "vendor ID")?,
+                product_id: parse_number(product_id, s, "product ID")?,
+                serial: serial.to_string(),
+                interface_number: interface_number
+                    .map(|i| parse_number(i, s, "interface number"))
+                    .transpose()?,
+            });
+        }
+
+        if let Some(board) = interface_board(first, "GPIB") {
+            let board = parse_board(board, s)?;
+            strip_instr(&mut parts);
+            let (primary, secondary) = match parts[..] {
+                [_, primary] => (primary, None),
+                [_, primary, secondary] => (primary, Some(secondary)),
+                _ => return Err(unrecognized(s)),
+            };
+            return Ok(Self::GpibInstr {
+                board,
+                primary: parse_gpib_address(primary, s)?,
+                secondary: secondary.map(|a| parse_gpib_address(a, s)).transpose()?,
+            });
+        }
+
+        if let Some(port) = interface_board(first, "ASRL") {
+            strip_instr(&mut parts);
+            let port = port.trim();
+            let is_port = !port.is_empty() && port.chars().all(|c| c.is_ascii_digit());
+            let is_path =
+                port.starts_with('/') && port.chars().all(|c| is_name_char(c) || c == '/');
+            if parts.len() != 1 || !(is_port || is_path) {
+                return Err(unrecognized(s));
+            }
+            return Ok(Self::AsrlInstr {
+                port: port.to_string(),
+            });
+        }
+
+        Err(unrecognized(s))
+    }
+}
+
+fn unrecognized(s: &str) -> InstrumentError {
+    InstrumentError::AddressParsingError(format!("'{s}' did not have a recognized VISA address"))
+}
+
+/// Split a resource string on `::`, ignoring any `::` inside square brackets (as in
+/// IPv6 addresses).
+fn split_resource(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth: usize = 0;
+    let mut start = 0;
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '[' => depth = depth.saturating_add(1),
+            ']' => depth = depth.saturating_sub(1),
+            ':' if depth == 0 && matches!(chars.peek(), Some((_, ':'))) => {
+                parts.push(&s[start..i]);
+                chars.next();
+                start = i.saturating_add(2);
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// If `part` starts with the given interface keyword (case-insensitive), return the
+/// rest of `part` (the board number).
+fn interface_board<'a>(part: &'a str, keyword: &str) -> Option<&'a str> {
+    let prefix = part.get(..keyword.len())?;
+    if prefix.eq_ignore_ascii_case(keyword) {
+        part.get(keyword.len()..)
+    } else {
+        None
+    }
+}
+
+/// Remove a trailing `INSTR` resource class, if present.
+fn strip_instr(parts: &mut Vec<&str>) {
+    if parts.len() > 1
+        && parts
+            .last()
+            .is_some_and(|p| p.eq_ignore_ascii_case("INSTR"))
+    {
+        parts.pop();
+    }
+}
+
+/// Characters allowed in free-form parts of a resource string (serial numbers and
+/// serial ports).
+const fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')
+}
+
+/// Parse the board number of an interface. A missing board number is `0`.
+fn parse_board(board: &str, s: &str) -> Result<u16, InstrumentError> {
+    if board.is_empty() {
+        return Ok(0);
+    }
+    parse_number(board, s, "board number")
+}
+
+/// Parse a number that may be given in decimal or, if prefixed with `0x`, in
+/// hexadecimal.
+fn parse_number<T: TryFrom<u64>>(part: &str, s: &str, what: &str) -> Result<T, InstrumentError> {
+    let part = part.trim();
+    let value = match part.strip_prefix("0x").or_else(|| part.strip_prefix("0X")) {
+        Some(hex) if hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+            u64::from_str_radix(hex, 16).ok()
+        }
+        None if part.chars().all(|c| c.is_ascii_digit()) => part.parse::<u64>().ok(),
+        _ => None,
+    };
+    value.and_then(|v| T::try_from(v).ok()).ok_or_else(|| {
+        InstrumentError::AddressParsingError(format!("unable to parse {what} '{part}' in '{s}'"))
+    })
+}
+
+fn parse_gpib_address(part: &str, s: &str) -> Result<u8, InstrumentError> {
+    let address: u8 = parse_number(part, s, "GPIB address")?;
+    if address > 30 {
+        return Err(InstrumentError::AddressParsingError(format!(
+            "GPIB address {address} in '{s}' is out of range (0-30)"
+        )));
+    }
+    Ok(address)
+}
+
+#[cfg(test)]
+mod unit {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::{LanDevice, VisaResource};
+    use crate::interface::host::Host;
+
+    fn multitest_resource_parse(cases: &[(&str, VisaResource, &str)]) {
+        for (input, expected, canonical) in cases {
+            match input.parse::<VisaResource>() {
+                Ok(actual) => {
+                    assert_eq!(&actual, expected, "'{input}' did not parse properly");
+                    assert_eq!(
+                        actual.to_string(),
+                        *canonical,
+                        "'{input}' did not display canonically"
+                    );
+                }
+                Err(e) => panic!("'{input}' could not be parsed into VisaResource: {e}"),
+            }
+        }
+    }
+
+    #[test]
+    fn tcpip_resources() {
+        multitest_resource_parse(&[
+            (
+                "TCPIP::192.168.0.1::INSTR",
+                VisaResource::TcpipInstr {
+                    board: 0,
+                    host: Host::Ip(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))),
+                    device: LanDevice::Vxi11("inst0".to_string()),
+                },
+                "TCPIP0::192.168.0.1::inst0::INSTR",
+            ),
+            (
+                "tcpip1::MySmu.Lab.Local",
+                VisaResource::TcpipInstr {
+                    board: 1,
+                    host: Host::Name("mysmu.lab.local".to_string()),
+                    device: LanDevice::Vxi11("inst0".to_string()),
+                },
+                "TCPIP1::MySmu.Lab.Local::inst0::INSTR",
+            ),
+            (
+                "TCPIP0::[2001:db8::ff00:42:8329]::gpib0,5::INSTR",
+                VisaResource::TcpipInstr {
+                    board: 0,
+                    host: Host::Ip(IpAddr::V6(Ipv6Addr::new(
+                        0x2001, 0x0db8, 0x0, 0x0, 0x0, 0xff00, 0x0042, 0x8329,
+                    ))),
+                    device: LanDevice::Vxi11("gpib0,5".to_string()),
+                },
+                "TCPIP0::[2001:db8::ff00:42:8329]::gpib0,5::INSTR",
+            ),
+            (
+                "TCPIP0::192.168.0.1::HiSLIP0,4881::INSTR",
+                VisaResource::TcpipInstr {
+                    board: 0,
+                    host: Host::Ip(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))),
+                    device: LanDevice::HiSlip {
+                        index: 0,
+                        port: Some(4881),
+                    },
+                },
+                "TCPIP0::192.168.0.1::hislip0,4881::INSTR",
+            ),
+            (
+                "TCPIP2::host-1::5025::socket",
+                VisaResource::TcpipSocket {
+                    board: 2,
+                    host: Host::Name("host-1".to_string()),
+                    port: 5025,
+                },
+                "TCPIP2::host-1::5025::SOCKET",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn usb_resources() {
+        multitest_resource_parse(&[
+            (
+                "USB::0x5e6::0x2461::04331961",
+                VisaResource::UsbInstr {
+                    board: 0,
+                    vendor_id: 0x05E6,
+                    product_id: 0x2461,
+                    serial: "04331961".to_string(),
+                    interface_number: None,
+                },
+                "USB0::0x05E6::0x2461::04331961::INSTR",
+            ),
+            (
+                "usb1::1689::9782::asdf::1::instr",
+                VisaResource::UsbInstr {
+                    board: 1,
+                    vendor_id: 0x0699,
+                    product_id: 0x2636,
+                    serial: "asdf".to_string(),
+                    interface_number: Some(1),
+                },
+                "USB1::0x0699::0x2636::asdf::1::INSTR",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn gpib_and_serial_resources() {
+        multitest_resource_parse(&[
+            (
+                "GPIB::26",
+                VisaResource::GpibInstr {
+                    board: 0,
+                    primary: 26,
+                    secondary: None,
+                },
+                "GPIB0::26::INSTR",
+            ),
+            (
+                "GPIB1::5::3::INSTR",
+                VisaResource::GpibInstr {
+                    board: 1,
+                    primary: 5,
+                    secondary: Some(3),
+                },
+                "GPIB1::5::3::INSTR",
+            ),
+            (
+                "ASRL3::INSTR",
+                VisaResource::AsrlInstr {
+                    port: "3".to_string(),
+                },
+                "ASRL3::INSTR",
+            ),
+            (
+                "ASRL/dev/ttyUSB0",
+                VisaResource::AsrlInstr {
+                    port: "/dev/ttyUSB0".to_string(),
+                },
+                "ASRL/dev/ttyUSB0::INSTR",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn invalid_resources() {
+        for input in [
+            "",
+            "T",
+            "TCPIP",
+            "TCPIP0::",
+            "TCPIP0::2001:db8::1::INSTR",
+            "TCPIP0::192.168.0.1::99999::SOCKET",
+            "TCPIPX::192.168.0.1::INSTR",
+            "TCPIP0::192.168.0.1::inst0::extra::INSTR",
+            "USB0::0x5e6::0x2461",
+            "USB0::0x5e6::0x2461::::INSTR",
+            "USB0::0x15e6f::0x2461::1::INSTR",
+            "GPIB0::31::INSTR",
+            "GPIB0::INSTR",
+            "ASRL::INSTR",
+            "ASRLabc::INSTR",
+            "VXI0::1::INSTR",
+            "é::",
+        ] {
+            assert!(
+                input.parse::<VisaResource>().is_err(),
+                "'{input}' should not be a valid VISA resource"
+            );
+        }
+    }
+}
@@ -11,15 +11,19 @@
 pub mod error;
 pub mod instrument;
 pub mod interface;
+pub mod manager;
 pub mod model;
+pub mod profile;
 
 #[cfg(test)]
 pub(crate) mod test_util;
 
 pub use error::InstrumentError;
 pub use instrument::firmware::Flash;
-pub use interface::{connection_addr::ConnectionInfo, Interface};
+pub use interface::{connect_options::ConnectOptions, connection_addr::ConnectionInfo, Interface};
+pub use manager::InstrumentManager;
 pub use model::{ki2600, tti, versatest};
+pub use profile::{Profile, ProfileStore};
 
 pub mod protocol;
 pub use protocol::is_visa_installed;
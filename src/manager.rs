@@ -0,0 +1,500 @@
+//! Drive many instruments from one process.
+//!
+//! An [`InstrumentManager`] connects to a set of instruments in parallel and keeps
+//! each one on its own worker thread, so that operations can be run on every
+//! instrument at once. Instruments are tracked by the name they were given when
+//! connecting and can also be found by serial number.
+//!
+//! When the manager is closed (or dropped) the instruments are torn down one at a
+//! time in the reverse of the order they were given in: each one is aborted and then
+//! disconnected before the next one is torn down.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use tracing::{instrument, trace, warn};
+
+use crate::{
+    error::Result,
+    instrument::{
+        authenticate::Authentication, info::InstrumentInfo, read_error_queue, Instrument,
+    },
+    interface::{connect_options::ConnectOptions, connection_addr::ConnectionInfo},
+    profile::Profile,
+    InstrumentError,
+};
+
+/// A unit of work to run on a managed instrument's worker thread.
+type Job = Box<dyn FnOnce(&mut dyn Instrument) + Send>;
+
+/// Creates an instrument on its worker thread.
+type Connector = Box<dyn FnOnce() -> Result<(Box<dyn Instrument>, InstrumentInfo)> + Send>;
+
+/// A single instrument owned by a worker thread.
+struct Managed {
+    name: String,
+    info: InstrumentInfo,
+    jobs: Option<Sender<Job>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Managed {
+    /// Run `f` on the instrument and return a channel that will receive the result.
+    fn submit<T, F>(&self, f: F) -> Receiver<Result<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn Instrument) -> Result<T> + Send + 'static,
+    {
+        let (send, recv) = mpsc::channel();
+        if let Some(jobs) = &self.jobs {
+            // If the worker has stopped, `send` is dropped and `recv` reports it.
+            let _ = jobs.send(Box::new(move |inst: &mut dyn Instrument| {
+                let _ = send.send(f(inst));
+            }));
+        }
+        recv
+    }
+
+    /// Wait for the result of a job submitted with [`Managed::submit`].
+    fn wait<T>(&self, recv: &Receiver<Result<T>>) -> Result<T> {
+        recv.recv().unwrap_or_else(|_| {
+            Err(InstrumentError::ConnectionError {
+                details: format!("the worker for \"{}\" has stopped", self.name),
+            })
+        })
+    }
+
+    /// Close the job queue and wait for the worker to tear the instrument down.
+    fn shutdown(&mut self) {
+        trace!("Tearing down \"{}\"", self.name);
+        drop(self.jobs.take());
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                warn!("The worker for \"{}\" panicked", self.name);
+            }
+        }
+    }
+}
+
+/// The body of a worker thread: connect, report the result, run jobs until the
+/// queue is closed and then tear the instrument down.
+fn run_worker(
+    name: &str,
+    connect: Connector,
+    ready: &Sender<Result<InstrumentInfo>>,
+    jobs: &Receiver<Job>,
+) {
+    let mut instrument = match connect() {
+        Ok((instrument, info)) => {
+            if ready.send(Ok(info)).is_err() {
+                // The manager gave up on connecting, so just disconnect.
+                return;
+            }
+            instrument
+        }
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+
+    for job in jobs {
+        job(instrument.as_mut());
+    }
+
+    trace!("Aborting \"{name}\" before disconnecting");
+    if let Err(e) = instrument.abort() {
+        warn!("Unable to abort \"{name}\": {e}");
+    }
+    drop(instrument);
+}
+
+/// A set of connected instruments that can be operated on together.
+#[allow(clippy::module_name_repetitions)]
+pub struct InstrumentManager {
+    /// The managed instruments in the order they were given.
+    instruments: Vec<Managed>,
+}
+
+impl InstrumentManager {
+    /// Connect to each of the named connections in parallel with the same
+    /// authentication and [`ConnectOptions`].
+    ///
+    /// # Errors
+    /// See [`InstrumentManager::connect_profiles`].
+    pub fn connect<I, N>(
+        targets: I,
+        auth: &Authentication,
+        options: &ConnectOptions,
+    ) -> Result<Self>
+    where
+        I: IntoIterator<Item = (N, ConnectionInfo)>,
+        N: Into<String>,
+    {
+        Self::connect_profiles(targets.into_iter().map(|(name, conn)| {
+            (
+                name,
+                Profile {
+                    options: options.clone(),
+                    auth: auth.clone(),
+                    ..Profile::new(conn)
+                },
+            )
+        }))
+    }
+
+    /// Connect to each of the named [`Profile`]s in parallel.
+    ///
+    /// # Errors
+    /// An error is returned if two instruments have the same name or if any
+    /// instrument could not be connected. If any connection fails, all the
+    /// instruments that did connect are torn down again.
+    #[instrument(skip(targets))]
+    pub fn connect_profiles<I, N>(targets: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (N, Profile)>,
+        N: Into<String>,
+    {
+        Self::spawn(targets.into_iter().map(|(name, profile)| {
+            let connect: Connector = Box::new(move || {
+                let mut instrument = profile.connect()?;
+                let info = instrument.info()?;
+                Ok((instrument, info))
+            });
+            (name.into(), connect)
+        }))
+    }
+
+    /// Start a worker for each instrument and wait for all of them to connect.
+    fn spawn(targets: impl IntoIterator<Item = (String, Connector)>) -> Result<Self> {
+        let mut pending: Vec<(String, Connector)> = Vec::new();
+        for (name, connect) in targets {
+            if pending.iter().any(|(n, _)| n == &name) {
+                return Err(InstrumentError::Other(format!(
+                    "duplicate instrument name \"{name}\""
+                )));
+            }
+            pending.push((name, connect));
+        }
+
+        let started: Vec<_> = pending
+            .into_iter()
+            .map(|(name, connect)| {
+                let (ready_send, ready) = mpsc::channel();
+                let (jobs, jobs_recv) = mpsc::channel::<Job>();
+                let worker_name = name.clone();
+                let worker = std::thread::spawn(move || {
+                    run_worker(&worker_name, connect, &ready_send, &jobs_recv);
+                });
+                (name, ready, jobs, worker)
+            })
+            .collect();
+
+        let mut connected = Self {
+            instruments: Vec::new(),
+        };
+        let mut failures = Vec::new();
+        for (name, ready, jobs, worker) in started {
+            let mut managed = Managed {
+                name,
+                info: InstrumentInfo::default(),
+                jobs: Some(jobs),
+                worker: Some(worker),
+            };
+            match managed.wait(&ready) {
+                Ok(info) => {
+                    trace!("Connected to \"{}\": {info}", managed.name);
+                    managed.info = info;
+                    connected.instruments.push(managed);
+                }
+                Err(e) => {
+                    managed.shutdown();
+                    failures.push(format!("{}: {e}", managed.name));
+                }
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(connected)
+        } else {
+            Err(InstrumentError::ConnectionError {
+                details: format!(
+                    "unable to connect to all instruments: {}",
+                    failures.join("; ")
+                ),
+            })
+        }
+    }
+
+    /// The number of managed instruments.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.instruments.len()
+    }
+
+    /// Check whether there are no managed instruments.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.instruments.is_empty()
+    }
+
+    /// The names of the managed instruments in the order they were given.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.instruments.iter().map(|m| m.name.as_str())
+    }
+
+    /// The information of the instrument with the given name.
+    #[must_use]
+    pub fn info(&self, name: &str) -> Option<&InstrumentInfo> {
+        self.get(name).ok().map(|m| &m.info)
+    }
+
+    /// The name of the instrument with the given serial number.
+    #[must_use]
+    pub fn find_serial(&self, serial_number: &str) -> Option<&str> {
+        self.instruments
+            .iter()
+            .find(|m| m.info.serial_number == serial_number)
+            .map(|m| m.name.as_str())
+    }
+
+    fn get(&self, name: &str) -> Result<&Managed> {
+        self.instruments
+            .iter()
+            .find(|m| m.name == name)
+            .ok_or_else(|| InstrumentError::UnknownInstrument {
+                name: name.to_string(),
+            })
+    }
+
+    /// Run `f` on the instrument with the given name and wait for the result.
+    ///
+    /// # Errors
+    /// [`InstrumentError::UnknownInstrument`] if there is no instrument with the
+    /// given name, a [`InstrumentError::ConnectionError`] if the instrument's worker
+    /// has stopped, or any error returned by `f`.
+    pub fn with<T, F>(&self, name: &str, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn Instrument) -> Result<T> + Send + 'static,
+    {
+        let managed = self.get(name)?;
+        managed.wait(&managed.submit(f))
+    }
+
+    /// Run `f` on every instrument concurrently and wait for all the results, which
+    /// are keyed by instrument name.
+    #[must_use]
+    pub fn broadcast<T, F>(&self, f: F) -> BTreeMap<String, Result<T>>
+    where
+        T: Send + 'static,
+        F: Fn(&mut dyn Instrument) -> Result<T> + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+        // Every job must be submitted before waiting on any of them so that they run
+        // concurrently.
+        #[allow(clippy::needless_collect)]
+        let pending: Vec<_> = self
+            .instruments
+            .iter()
+            .map(|m| {
+                let f = Arc::clone(&f);
+                (m, m.submit(move |inst| f(inst)))
+            })
+            .collect();
+
+        pending
+            .into_iter()
+            .map(|(m, recv)| (m.name.clone(), m.wait(&recv)))
+            .collect()
+    }
+
+    /// Abort the current operation on every instrument.
+    #[must_use]
+    pub fn abort_all(&self) -> BTreeMap<String, Result<()>> {
+        self.broadcast(|inst| inst.abort())
+    }
+
+    /// Reset every instrument.
+    #[must_use]
+    pub fn reset_all(&self) -> BTreeMap<String, Result<()>> {
+        self.broadcast(|inst| inst.reset())
+    }
+
+    /// Write the given script to every instrument. See
+    /// [`Script::write_script`](crate::instrument::Script::write_script).
+    #[must_use]
+    pub fn write_script_all(
+        &self,
+        name: &[u8],
+        script: &[u8],
+        save_script: bool,
+        run_script: bool,
+    ) -> BTreeMap<String, Result<()>> {
+        let name: Arc<[u8]> = name.into();
+        let script: Arc<[u8]> = script.into();
+        self.broadcast(move |inst| inst.write_script(&name, &script, save_script, run_script))
+    }
+
+    /// Read and clear the error queue of every instrument. See
+    /// [`read_error_queue`].
+    #[must_use]
+    pub fn collect_errors(&self) -> BTreeMap<String, Result<Vec<String>>> {
+        self.broadcast(|inst| read_error_queue(inst, 100, Duration::from_millis(100)))
+    }
+
+    /// Tear down the instrument with the given name and stop managing it.
+    ///
+    /// # Errors
+    /// [`InstrumentError::UnknownInstrument`] if there is no instrument with the
+    /// given name.
+    pub fn disconnect(&mut self, name: &str) -> Result<()> {
+        let Some(index) = self.instruments.iter().position(|m| m.name == name) else {
+            return Err(InstrumentError::UnknownInstrument {
+                name: name.to_string(),
+            });
+        };
+        self.instruments.remove(index).shutdown();
+        Ok(())
+    }
+
+    /// Tear down all instruments in the reverse of the order they were given in.
+    pub fn close(mut self) {
+        self.teardown();
+    }
+
+    fn teardown(&mut self) {
+        while let Some(mut managed) = self.instruments.pop() {
+            managed.shutdown();
+        }
+    }
+}
+
+impl Drop for InstrumentManager {
+    fn drop(&mut self) {
+        self.teardown();
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        instrument::{authenticate::Authentication, info::InstrumentInfo, Instrument},
+        model::ki2600,
+        protocol::Protocol,
+        test_util::FakeInstrument,
+        InstrumentError,
+    };
+
+    use super::{Connector, InstrumentManager};
+
+    fn recorder(name: &str, serial: &str, log: &Arc<Mutex<Vec<String>>>) -> (String, Connector) {
+        let recorder = FakeInstrument::new().with_log(name, log);
+        let info = InstrumentInfo {
+            serial_number: serial.to_string(),
+            ..InstrumentInfo::default()
+        };
+        let connect: Connector = Box::new(move || {
+            let instrument: Box<dyn Instrument> = Box::new(ki2600::Instrument::new(
+                Protocol::new(recorder),
+                Authentication::NoAuth,
+            ));
+            Ok((instrument, info))
+        });
+        (name.to_string(), connect)
+    }
+
+    fn manager(log: &Arc<Mutex<Vec<String>>>) -> InstrumentManager {
+        InstrumentManager::spawn([recorder("smu1", "1001", log), recorder("smu2", "1002", log)])
+            .expect("workers should start")
+    }
+
+    #[test]
+    fn broadcast_runs_on_every_instrument() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let manager = manager(&log);
+
+        let results = manager.broadcast(|inst| {
+            inst.write_all(b"print('hi')\n")?;
+            Ok(())
+        });
+
+        assert_eq!(results.len(), 2);
+        assert!(results.values().all(Result::is_ok));
+        let log = log.lock().unwrap().clone();
+        assert!(log.contains(&"smu1: print('hi')".to_string()));
+        assert!(log.contains(&"smu2: print('hi')".to_string()));
+    }
+
+    #[test]
+    fn lookup_by_name_and_serial() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let manager = manager(&log);
+
+        assert_eq!(manager.names().collect::<Vec<_>>(), vec!["smu1", "smu2"]);
+        assert_eq!(manager.find_serial("1002"), Some("smu2"));
+        assert_eq!(manager.info("smu1").unwrap().serial_number, "1001");
+        assert!(matches!(
+            manager.with("smu3", |_| Ok(())),
+            Err(InstrumentError::UnknownInstrument { .. })
+        ));
+    }
+
+    #[test]
+    fn teardown_in_reverse_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        manager(&log).close();
+
+        let log = log.lock().unwrap().clone();
+        let last_of = |name: &str| {
+            log.iter()
+                .rposition(|l| l.starts_with(name))
+                .expect("instrument should have been torn down")
+        };
+        let first_of = |name: &str| log.iter().position(|l| l.starts_with(name)).unwrap();
+        assert!(last_of("smu2") < first_of("smu1"));
+        assert_eq!(log.first(), Some(&"smu2: abort".to_string()));
+    }
+
+    #[test]
+    fn failed_connection_tears_down_the_rest() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let failing: Connector = Box::new(|| {
+            Err(InstrumentError::ConnectionError {
+                details: "no route to host".to_string(),
+            })
+        });
+
+        let result = InstrumentManager::spawn([
+            recorder("smu1", "1001", &log),
+            ("smu2".to_string(), failing),
+        ]);
+
+        assert!(matches!(
+            result,
+            Err(InstrumentError::ConnectionError { .. })
+        ));
+        assert!(log.lock().unwrap().contains(&"smu1: abort".to_string()));
+    }
+
+    #[test]
+    fn duplicate_names_are_rejected() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let result = InstrumentManager::spawn([
+            recorder("smu1", "1001", &log),
+            recorder("smu1", "1002", &log),
+        ]);
+
+        assert!(result.is_err());
+    }
+}
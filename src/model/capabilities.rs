@@ -0,0 +1,273 @@
+//! The capabilities of each [`Model`].
+//!
+//! Anything that differs between instruments (the number and names of SMU channels,
+//! the supported languages, how firmware is framed, how to log in, etc.) is described
+//! by [`Capabilities`] so that drivers and [`Protocol`](crate::protocol::Protocol)
+//! don't need to hard-code per-family constants.
+
+use crate::{
+    instrument::{language::CmdLanguage, password::PasswordDialect, status::StatusModel},
+    model::{Family, Model},
+};
+
+/// How a firmware image is sent to the instrument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FirmwareContainer {
+    /// The raw image is sent between `flash` and `endflash`.
+    Flash,
+    /// The raw image is sent between `prevflash` and `endflash`.
+    PrevFlash,
+    /// A firmware package (a ZIP file that may contain module firmware) is sent
+    /// between `flash` and `endflash` and is validated by the instrument.
+    Package,
+}
+
+impl FirmwareContainer {
+    /// The command that starts the firmware transfer.
+    #[must_use]
+    pub const fn begin_command(self) -> &'static str {
+        match self {
+            Self::Flash | Self::Package => "flash",
+            Self::PrevFlash => "prevflash",
+        }
+    }
+}
+
+/// How credentials are sent to the instrument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LoginStyle {
+    /// `password <password>`
+    Password,
+    /// `login <password>`
+    Login,
+    /// `login [<username>] <password>`
+    UsernameLogin,
+}
+
+impl LoginStyle {
+    /// The command (without a line ending) that logs in with the given credentials.
+    #[must_use]
+    pub fn command(self, username: &str, password: &str) -> String {
+        match self {
+            Self::Password => format!("password {password}"),
+            Self::Login => format!("login {password}"),
+            Self::UsernameLogin if username.is_empty() => format!("login {password}"),
+            Self::UsernameLogin => format!("login {username} {password}"),
+        }
+    }
+}
+
+/// The TSP commands used to manage a TSP-Link network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TspLinkDialect {
+    /// `tsplink.reset()` and `node[N].revision`
+    Reset,
+    /// `tsplink.initialize()` and `node[N].version`
+    Initialize,
+}
+
+impl TspLinkDialect {
+    /// The TSP expression that initializes the network and evaluates to the number
+    /// of nodes found.
+    #[must_use]
+    pub const fn initialize_command(self) -> &'static str {
+        match self {
+            Self::Reset => "tsplink.reset()",
+            Self::Initialize => "tsplink.initialize()",
+        }
+    }
+
+    /// The attribute of `node[N]` that holds the firmware revision of the node.
+    #[must_use]
+    pub const fn revision_attribute(self) -> &'static str {
+        match self {
+            Self::Reset => "revision",
+            Self::Initialize => "version",
+        }
+    }
+}
+
+/// The structured capabilities of an instrument model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The names of the SMU channels (e.g. `smua`, `smub`). Modular Platform
+    /// channels depend on the installed modules, so none are listed.
+    pub smu_channels: &'static [&'static str],
+    /// The command languages the instrument supports.
+    pub languages: &'static [CmdLanguage],
+    /// The maximum length of a script name.
+    pub max_script_name_len: usize,
+    /// The largest message that should be written at once over USBTMC.
+    pub usb_write_chunk: usize,
+    /// How firmware is sent to the instrument.
+    pub firmware: FirmwareContainer,
+    /// How the instrument manages TSP-Link, or [`None`] if it doesn't support
+    /// TSP-Link.
+    pub tsplink: Option<TspLinkDialect>,
+    /// How credentials are sent to the instrument.
+    pub login: LoginStyle,
+    /// The `status.*` registers and the names of their bits.
+    pub status: StatusModel,
+    /// How the password is managed, or [`None`] if it can't be managed remotely.
+    pub password: Option<PasswordDialect>,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Capabilities {
+    /// The capabilities assumed for instruments that are not recognized.
+    pub const DEFAULT: Self = Self {
+        smu_channels: &[],
+        languages: &[CmdLanguage::Tsp],
+        max_script_name_len: 31,
+        usb_write_chunk: 1000,
+        firmware: FirmwareContainer::Package,
+        tsplink: Some(TspLinkDialect::Initialize),
+        login: LoginStyle::UsernameLogin,
+        status: StatusModel::DEFAULT,
+        password: None,
+    };
+
+    const KI2600: Self = Self {
+        smu_channels: &["smua", "smub"],
+        firmware: FirmwareContainer::Flash,
+        tsplink: Some(TspLinkDialect::Reset),
+        login: LoginStyle::Password,
+        status: StatusModel::KI2600,
+        password: Some(PasswordDialect::PasswordMode),
+        ..Self::DEFAULT
+    };
+
+    const KI3700: Self = Self {
+        smu_channels: &[],
+        firmware: FirmwareContainer::PrevFlash,
+        status: StatusModel::KI3700,
+        ..Self::KI2600
+    };
+
+    const TTI: Self = Self {
+        languages: &[CmdLanguage::Tsp, CmdLanguage::Scpi],
+        firmware: FirmwareContainer::PrevFlash,
+        login: LoginStyle::Login,
+        status: StatusModel::TTI,
+        password: Some(PasswordDialect::PasswordOnly),
+        ..Self::DEFAULT
+    };
+
+    const MODULAR_PLATFORM: Self = Self {
+        usb_write_chunk: 4500,
+        ..Self::DEFAULT
+    };
+
+    /// The capabilities shared by every model in the given family.
+    #[must_use]
+    pub const fn for_family(family: Option<&Family>) -> Self {
+        match family {
+            Some(Family::_26xx) => Self::KI2600,
+            Some(Family::_3700) => Self::KI3700,
+            Some(Family::Tti) => Self::TTI,
+            Some(Family::ModularPlatform) => Self::MODULAR_PLATFORM,
+            None => Self::DEFAULT,
+        }
+    }
+
+    /// The capabilities of the given model.
+    #[must_use]
+    pub fn for_model(model: &Model) -> Self {
+        let family = Self::for_family(model.family().as_ref());
+        match model {
+            Model::_2601A
+            | Model::_2611A
+            | Model::_2635A
+            | Model::_2651A
+            | Model::_2657A
+            | Model::_2601B
+            | Model::_2601BPulse
+            | Model::_2611B
+            | Model::_2635B
+            | Model::_2601BL
+            | Model::_2611BL
+            | Model::_2635BL => Self {
+                smu_channels: &["smua"],
+                ..family
+            },
+            Model::_2604B
+            | Model::_2614B
+            | Model::_2634B
+            | Model::_2604BL
+            | Model::_2614BL
+            | Model::_2634BL
+            | Model::TSPop => Self {
+                tsplink: None,
+                ..family
+            },
+            Model::_2450 | Model::_2470 | Model::_2460 | Model::_2461 | Model::_2461Sys => Self {
+                smu_channels: &["smu"],
+                ..family
+            },
+            _ => family,
+        }
+    }
+}
+
+impl Model {
+    /// The [`Capabilities`] of this model.
+    #[must_use]
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::for_model(self)
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use crate::{
+        instrument::{language::CmdLanguage, status::StatusModel},
+        model::Model,
+    };
+
+    use super::{Capabilities, FirmwareContainer, LoginStyle, TspLinkDialect};
+
+    #[test]
+    fn family_capabilities() {
+        let caps = Model::_2636B.capabilities();
+        assert_eq!(caps.smu_channels, &["smua", "smub"]);
+        assert_eq!(caps.login, LoginStyle::Password);
+        assert_eq!(caps.tsplink, Some(TspLinkDialect::Reset));
+
+        let caps = Model::_2461.capabilities();
+        assert_eq!(caps.smu_channels, &["smu"]);
+        assert_eq!(caps.languages, &[CmdLanguage::Tsp, CmdLanguage::Scpi]);
+        assert_eq!(caps.firmware, FirmwareContainer::PrevFlash);
+
+        assert_eq!(Model::MP5103.capabilities().usb_write_chunk, 4500);
+        assert_eq!(Model::_707B.capabilities().usb_write_chunk, 1000);
+        assert_eq!(Model::_707B.capabilities().status, StatusModel::KI3700);
+        assert_eq!(Model::_2450.capabilities().status, StatusModel::TTI);
+    }
+
+    #[test]
+    fn model_overrides() {
+        assert_eq!(Model::_2601B.capabilities().smu_channels, &["smua"]);
+        assert_eq!(Model::_2634B.capabilities().tsplink, None);
+        assert_eq!(Model::TSPop.capabilities().tsplink, None);
+        assert_eq!(
+            Model::Other("9999".to_string()).capabilities(),
+            Capabilities::DEFAULT
+        );
+    }
+
+    #[test]
+    fn login_commands() {
+        assert_eq!(LoginStyle::Password.command("", "pw"), "password pw");
+        assert_eq!(LoginStyle::Login.command("admin", "pw"), "login pw");
+        assert_eq!(LoginStyle::UsernameLogin.command("", "pw"), "login pw");
+        assert_eq!(
+            LoginStyle::UsernameLogin.command("admin", "pw"),
+            "login admin pw"
+        );
+    }
+}
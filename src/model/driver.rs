@@ -0,0 +1,200 @@
+//! The [`Driver`] trait and the [`DriverRegistry`] that is used to pick the driver
+//! for a connected instrument.
+//!
+//! Each supported family has a driver that is registered by default. Other crates
+//! can add their own drivers with [`DriverRegistry::register_global`]; drivers
+//! registered later are tried first. If no driver matches an instrument, connecting
+//! fails with [`InstrumentError::UnsupportedInstrument`] unless a fallback driver has
+//! been opted into with [`DriverRegistry::set_global_fallback`].
+
+use std::sync::{Arc, OnceLock, PoisonError, RwLock, RwLockReadGuard};
+
+use tracing::trace;
+
+use crate::{
+    error::Result,
+    instrument::{authenticate::Authentication, info::InstrumentInfo, Instrument},
+    interface::{connect_options::ConnectOptions, connection_addr::ConnectionInfo},
+    model::{ki2600, ki3700, tti, versatest},
+    protocol::Protocol,
+    InstrumentError,
+};
+
+static GLOBAL: OnceLock<RwLock<DriverRegistry>> = OnceLock::new();
+
+/// Creates [`Instrument`]s for the instruments it supports.
+pub trait Driver: Send + Sync {
+    /// A short name for the driver, used for logging.
+    fn name(&self) -> &'static str;
+
+    /// Whether this driver supports the instrument with the given information.
+    fn matches(&self, info: &InstrumentInfo) -> bool;
+
+    /// Connect to the instrument with the given connection information.
+    ///
+    /// # Errors
+    /// Any errors that occur while connecting.
+    fn connect(
+        &self,
+        conn: &ConnectionInfo,
+        auth: Authentication,
+        options: &ConnectOptions,
+        info: InstrumentInfo,
+    ) -> Result<Box<dyn Instrument>>;
+
+    /// Create an instrument that communicates over an existing [`Protocol`].
+    fn create(
+        &self,
+        protocol: Protocol,
+        auth: Authentication,
+        info: InstrumentInfo,
+    ) -> Box<dyn Instrument>;
+}
+
+/// An ordered collection of [`Driver`]s.
+#[derive(Clone, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct DriverRegistry {
+    drivers: Vec<Arc<dyn Driver>>,
+    fallback: Option<Arc<dyn Driver>>,
+}
+
+impl std::fmt::Debug for DriverRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DriverRegistry")
+            .field(
+                "drivers",
+                &self.drivers.iter().map(|d| d.name()).collect::<Vec<_>>(),
+            )
+            .field("fallback", &self.fallback.as_ref().map(|d| d.name()))
+            .finish()
+    }
+}
+
+impl DriverRegistry {
+    /// A registry containing the drivers for each supported family and no fallback.
+    #[must_use]
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        registry.register(Arc::new(versatest::Driver));
+        registry.register(Arc::new(tti::Driver));
+        registry.register(Arc::new(ki3700::Driver));
+        registry.register(Arc::new(ki2600::Driver));
+        registry
+    }
+
+    /// Add a driver. Drivers added later are tried before those added earlier.
+    pub fn register(&mut self, driver: Arc<dyn Driver>) {
+        self.drivers.push(driver);
+    }
+
+    /// Set the driver to use when no other driver matches, or [`None`] to return an
+    /// error instead.
+    pub fn set_fallback(&mut self, driver: Option<Arc<dyn Driver>>) {
+        self.fallback = driver;
+    }
+
+    /// Find the driver for the instrument with the given information.
+    ///
+    /// # Errors
+    /// [`InstrumentError::UnsupportedInstrument`] if no driver matches and there is no
+    /// fallback.
+    pub fn find(&self, info: &InstrumentInfo) -> Result<Arc<dyn Driver>> {
+        if let Some(driver) = self.drivers.iter().rev().find(|d| d.matches(info)) {
+            trace!("using the {} driver for {}", driver.name(), info.model);
+            return Ok(Arc::clone(driver));
+        }
+        self.fallback.as_ref().map_or_else(
+            || {
+                Err(InstrumentError::UnsupportedInstrument {
+                    model: info.model.to_string(),
+                })
+            },
+            |driver| {
+                trace!(
+                    "using the fallback {} driver for {}",
+                    driver.name(),
+                    info.model
+                );
+                Ok(Arc::clone(driver))
+            },
+        )
+    }
+
+    /// Whether any driver (not including the fallback) supports the given instrument.
+    #[must_use]
+    pub fn supports(&self, info: &InstrumentInfo) -> bool {
+        self.drivers.iter().any(|d| d.matches(info))
+    }
+
+    /// The process-wide registry, which starts as [`DriverRegistry::builtin`].
+    pub fn global() -> RwLockReadGuard<'static, Self> {
+        global().read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Add a driver to the process-wide registry.
+    pub fn register_global(driver: Arc<dyn Driver>) {
+        global()
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .register(driver);
+    }
+
+    /// Set the fallback driver of the process-wide registry.
+    pub fn set_global_fallback(driver: Option<Arc<dyn Driver>>) {
+        global()
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_fallback(driver);
+    }
+}
+
+fn global() -> &'static RwLock<DriverRegistry> {
+    GLOBAL.get_or_init(|| RwLock::new(DriverRegistry::builtin()))
+}
+
+#[cfg(test)]
+mod unit {
+    use std::sync::Arc;
+
+    use crate::{
+        instrument::info::InstrumentInfo,
+        model::{versatest, Model},
+        InstrumentError,
+    };
+
+    use super::DriverRegistry;
+
+    fn info(model: Model) -> InstrumentInfo {
+        InstrumentInfo {
+            model,
+            ..InstrumentInfo::default()
+        }
+    }
+
+    #[test]
+    fn builtin_drivers() {
+        let registry = DriverRegistry::builtin();
+        let name = |m| registry.find(&info(m)).map(|d| d.name().to_string());
+
+        assert_eq!(name(Model::_2636B).unwrap(), "ki2600");
+        assert_eq!(name(Model::_707B).unwrap(), "ki3700");
+        assert_eq!(name(Model::DMM6500).unwrap(), "tti");
+        assert_eq!(name(Model::MP5103).unwrap(), "versatest");
+    }
+
+    #[test]
+    fn unknown_model_is_an_error_without_fallback() {
+        let mut registry = DriverRegistry::builtin();
+        let unknown = info(Model::Other("1234".to_string()));
+
+        assert!(matches!(
+            registry.find(&unknown),
+            Err(InstrumentError::UnsupportedInstrument { .. })
+        ));
+        assert!(!registry.supports(&unknown));
+
+        registry.set_fallback(Some(Arc::new(versatest::Driver)));
+        assert_eq!(registry.find(&unknown).unwrap().name(), "versatest");
+    }
+}
@@ -0,0 +1,583 @@
+//! A conservative driver for TSP instruments that are not in the model table.
+//!
+//! Rather than assuming the behavior of a family, the instrument is probed when it
+//! is connected (and again after logging in) to find out whether it responds to
+//! `*LANG?`, whether it has `firmware.update()` and how it manages TSP-Link.
+//! [`Flash`], [`Reset`], [`Language`] and [`TspLink`] then only use what was found.
+//! `login` and `password` are command interpreter commands rather than TSP
+//! functions, so [`Login`] finds the one the instrument accepts by trying them. This driver is opt-in: register it with
+//! [`DriverRegistry::register_global`](crate::model::driver::DriverRegistry::register_global)
+//! or [`DriverRegistry::set_global_fallback`](crate::model::driver::DriverRegistry::set_global_fallback).
+
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
+
+use tracing::{trace, warn};
+
+use crate::{
+    instrument::{
+        self, authenticate::Authentication, info::InstrumentInfo, language::CmdLanguage,
+        language::Language, query_tsp, read_until, status::StatusByte, Abort, AuthProvider,
+        Buffers, Info, Login, PasswordAdmin, Reset, Script, ServiceRequest, Status, TspLink,
+    },
+    interface::{connect_options::ConnectOptions, connection_addr::ConnectionInfo, NonBlock},
+    model::{
+        capabilities::{LoginStyle, TspLinkDialect},
+        driver,
+    },
+    protocol::{self, Clear, DeviceClear, Protocol},
+    Flash, InstrumentError,
+};
+
+/// The TSP that reports which firmware update function and TSP-Link functions exist,
+/// separated by tabs.
+const PROBE_TSP: &str = "print(type(firmware) == \"table\" and type(firmware.update) or \"nil\", \
+     type(tsplink) == \"table\" and (tsplink.initialize ~= nil and \"initialize\" \
+     or tsplink.reset ~= nil and \"reset\" or \"nil\") or \"nil\")";
+
+/// What a connected instrument was found to support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Probe {
+    /// The instrument responds to `*LANG?`, so it can switch between TSP and SCPI.
+    pub language_query: bool,
+    /// How credentials are sent to the instrument, once it has accepted a login.
+    pub login: Option<LoginStyle>,
+    /// The instrument has `firmware.update()`.
+    pub firmware_update: bool,
+    /// How the instrument manages TSP-Link, if it has TSP-Link.
+    pub tsplink: Option<TspLinkDialect>,
+}
+
+impl Probe {
+    /// Parse the output of [`PROBE_TSP`].
+    fn parse(line: &str, language_query: bool) -> Self {
+        let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+        let field = |i: usize| {
+            fields
+                .get(i)
+                .copied()
+                .filter(|f| !f.is_empty())
+                .unwrap_or("nil")
+        };
+        let tsplink = match field(1) {
+            "initialize" => Some(TspLinkDialect::Initialize),
+            "reset" => Some(TspLinkDialect::Reset),
+            _ => None,
+        };
+        Self {
+            language_query,
+            login: None,
+            firmware_update: field(0) == "function",
+            tsplink,
+        }
+    }
+
+    /// The login styles to try, in order: the one that was accepted before, or
+    /// `login` for instruments that also speak SCPI, or else `login` and then
+    /// `password`.
+    const fn login_styles(self) -> &'static [LoginStyle] {
+        match self.login {
+            Some(LoginStyle::Login) => &[LoginStyle::Login],
+            Some(LoginStyle::Password) => &[LoginStyle::Password],
+            Some(LoginStyle::UsernameLogin) => &[LoginStyle::UsernameLogin],
+            None if self.language_query => &[LoginStyle::Login],
+            None => &[LoginStyle::Login, LoginStyle::Password],
+        }
+    }
+}
+
+pub struct Instrument {
+    info: Option<InstrumentInfo>,
+    protocol: Protocol,
+    auth: Authentication,
+    probe: Probe,
+}
+
+impl Instrument {
+    /// Connect to an instrument with the given connection information and
+    /// [`ConnectOptions`] and probe what it supports.
+    ///
+    /// # Errors
+    /// There can be issues in creating the protocol from the given [`ConnectionInfo`].
+    #[tracing::instrument(skip(conn, auth, options))]
+    pub fn connect(
+        conn: &ConnectionInfo,
+        auth: Authentication,
+        options: &ConnectOptions,
+    ) -> Result<Self, InstrumentError> {
+        let protocol = Protocol::connect(conn, options)?;
+        let mut instrument = Self::new(protocol, auth);
+        instrument.probe();
+        Ok(instrument)
+    }
+
+    /// Create an instrument that assumes nothing is supported until
+    /// [`Instrument::probe`] is called.
+    #[must_use]
+    pub const fn new(protocol: Protocol, auth: Authentication) -> Self {
+        Self::with_probe(
+            protocol,
+            auth,
+            Probe {
+                language_query: false,
+                login: None,
+                firmware_update: false,
+                tsplink: None,
+            },
+        )
+    }
+
+    /// Create an instrument with an already-known [`Probe`].
+    #[must_use]
+    pub const fn with_probe(protocol: Protocol, auth: Authentication, probe: Probe) -> Self {
+        Self {
+            info: None,
+            protocol,
+            auth,
+            probe,
+        }
+    }
+
+    pub fn add_info(&mut self, info: InstrumentInfo) -> &Self {
+        self.info = Some(info);
+        self
+    }
+
+    /// What the instrument was found to support.
+    #[must_use]
+    pub const fn supported(&self) -> Probe {
+        self.probe
+    }
+
+    /// The command that resets the instrument: `*RST` if it answered `*LANG?`,
+    /// otherwise `reset()`.
+    const fn reset_command(&self) -> &'static [u8] {
+        if self.probe.language_query {
+            b"*RST\n"
+        } else {
+            b"reset()\n"
+        }
+    }
+
+    /// Probe the instrument for what it supports. If the instrument can't be probed
+    /// (for example, because it is locked), nothing is assumed to be supported.
+    /// The login style that was accepted, if any, is kept.
+    pub fn probe(&mut self) -> Probe {
+        let login = self.probe.login;
+        let language_query = self.query_language().is_ok();
+        // `*LANG?` adds an error to the queue of instruments that don't support it.
+        let _ = self.write_all(b"errorqueue.clear()\n");
+        self.probe = match query_tsp(self, PROBE_TSP, 20, Duration::from_millis(50)) {
+            Ok(lines) => Probe {
+                login,
+                ..Probe::parse(
+                    lines.last().map(String::as_str).unwrap_or_default(),
+                    language_query,
+                )
+            },
+            Err(e) => {
+                warn!("unable to probe instrument: {e}");
+                Probe {
+                    language_query,
+                    login,
+                    ..Probe::default()
+                }
+            }
+        };
+        trace!("probed instrument: {:?}", self.probe);
+        self.probe
+    }
+
+    fn query_language(&mut self) -> crate::error::Result<CmdLanguage> {
+        self.write_all(b"*LANG?\n")?;
+        let reply = read_until(
+            self,
+            &["TSP".to_string(), "SCPI".to_string()],
+            5,
+            Duration::from_millis(100),
+        )?;
+        reply.parse()
+    }
+}
+
+/// The [`driver::Driver`] for TSP instruments that are not in the model table.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Driver;
+
+impl driver::Driver for Driver {
+    fn name(&self) -> &'static str {
+        "generic"
+    }
+
+    fn matches(&self, info: &InstrumentInfo) -> bool {
+        info.model.is_other()
+    }
+
+    fn connect(
+        &self,
+        conn: &ConnectionInfo,
+        auth: Authentication,
+        options: &ConnectOptions,
+        info: InstrumentInfo,
+    ) -> crate::error::Result<Box<dyn instrument::Instrument>> {
+        let mut instrument = Instrument::connect(conn, auth, options)?;
+        instrument.add_info(info);
+        Ok(Box::new(instrument))
+    }
+
+    fn create(
+        &self,
+        protocol: Protocol,
+        auth: Authentication,
+        info: InstrumentInfo,
+    ) -> Box<dyn instrument::Instrument> {
+        let mut instrument = Instrument::new(protocol, auth);
+        instrument.probe();
+        instrument.add_info(info);
+        Box::new(instrument)
+    }
+}
+
+impl instrument::Instrument for Instrument {}
+
+impl Info for Instrument {}
+
+impl Language for Instrument {
+    fn get_language(&mut self) -> Result<CmdLanguage, InstrumentError> {
+        if self.probe.language_query {
+            self.query_language()
+        } else {
+            Ok(CmdLanguage::Tsp)
+        }
+    }
+
+    fn change_language(&mut self, lang: CmdLanguage) -> Result<(), InstrumentError> {
+        if self.probe.language_query {
+            self.write_all(format!("*LANG {lang}\n").as_bytes())?;
+            Ok(())
+        } else if lang == CmdLanguage::Tsp {
+            Ok(())
+        } else {
+            Err(InstrumentError::Other(format!(
+                "{lang} is not supported by this instrument"
+            )))
+        }
+    }
+}
+
+impl Login for Instrument {
+    fn check_login(&mut self) -> crate::error::Result<instrument::State> {
+        self.write_all(b"print('unlocked')\n")?;
+        let state = instrument::read_response(self, instrument::login::CHECK_TIMEOUT, |resp| {
+            if resp.contains("unlocked") {
+                Some(instrument::State::NotNeeded)
+            } else if resp.contains("Port in use") {
+                Some(instrument::State::LogoutNeeded)
+            } else if resp.contains("FAILURE") {
+                Some(instrument::State::Needed)
+            } else {
+                None
+            }
+        })?;
+        Ok(state.unwrap_or(instrument::State::Needed))
+    }
+
+    fn login(&mut self) -> crate::error::Result<()> {
+        let mut inst_login_state = self.check_login()?;
+        if instrument::State::NotNeeded == inst_login_state {
+            return Ok(());
+        } else if instrument::State::LogoutNeeded == inst_login_state {
+            return Err(InstrumentError::InterfaceLoginErr);
+        }
+
+        if self.auth.needs_identity() {
+            let info = match self.info.clone() {
+                Some(info) => info,
+                None => self.info()?,
+            };
+            self.auth.on_identified(&info)?;
+        }
+
+        let username = self.auth.read_username()?.unwrap_or_default();
+        if let Some(password) = self.auth.read_password()? {
+            for &style in self.probe.login_styles() {
+                let command = style.command(&username, &password);
+                self.write_all(format!("{command}\n").as_bytes())?;
+                inst_login_state = self.check_login()?;
+                if instrument::State::NotNeeded == inst_login_state {
+                    self.probe.login = Some(style);
+                    break;
+                }
+            }
+        }
+
+        if instrument::State::NotNeeded == inst_login_state {
+            let info = self.info()?;
+            self.auth.on_success(&info)?;
+            // The instrument couldn't be probed while it was locked.
+            self.probe();
+        } else if instrument::State::Needed == inst_login_state {
+            self.auth.on_rejected()?;
+            return Err(InstrumentError::LoginRejected);
+        }
+
+        Ok(())
+    }
+}
+
+impl Script for Instrument {}
+
+impl Buffers for Instrument {}
+
+impl Status for Instrument {}
+
+impl ServiceRequest for Instrument {
+    fn wait_for_srq(&mut self, timeout: Duration) -> crate::error::Result<StatusByte> {
+        self.protocol.wait_for_srq(timeout)
+    }
+}
+
+impl Clear for Instrument {
+    type Error = InstrumentError;
+
+    /// Clear the instrument, logging in again if the session was reconnected.
+    fn device_clear(&mut self) -> crate::error::Result<DeviceClear> {
+        let cleared = self.protocol.device_clear()?;
+        if cleared == DeviceClear::Reconnected {
+            self.login()?;
+        }
+        Ok(cleared)
+    }
+}
+
+impl PasswordAdmin for Instrument {}
+
+impl TspLink for Instrument {
+    fn tsplink_initialize_command(&self) -> Option<&'static str> {
+        self.probe.tsplink.map(TspLinkDialect::initialize_command)
+    }
+
+    fn node_revision_attribute(&self) -> &'static str {
+        self.probe
+            .tsplink
+            .map_or("version", TspLinkDialect::revision_attribute)
+    }
+}
+
+impl Flash for Instrument {
+    fn flash_firmware(&mut self, image: &[u8], slot: Option<u16>) -> crate::error::Result<()> {
+        if !self.probe.firmware_update || slot.is_some_and(|s| s > 0) {
+            return Err(InstrumentError::FwUpgradeFailure(
+                "firmware upgrades are not supported for this instrument".to_string(),
+            ));
+        }
+        self.write_all(b"localnode.prompts = 0\n")?;
+        self.write_all(b"flash\n")?;
+        self.write_all(image)?;
+        self.write_all(b"endflash\n")?;
+        self.write_all(b"firmware.update()\n")?;
+        Ok(())
+    }
+}
+
+impl Read for Instrument {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let b = self.protocol.read(buf)?;
+        let ascii = String::from_utf8_lossy(buf);
+        let ascii = ascii.trim_end().trim_matches(['\0', '\n', '\r']);
+        if !ascii.is_empty() {
+            trace!("read from instrument: '{ascii}'");
+        }
+        Ok(b)
+    }
+}
+
+impl Write for Instrument {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        trace!(
+            "writing to instrument: '{}'",
+            protocol::redact(&String::from_utf8_lossy(buf))
+        );
+        self.protocol.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.protocol.flush()
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.protocol.write_all(buf)
+    }
+}
+
+impl NonBlock for Instrument {
+    fn set_nonblocking(&mut self, enable: bool) -> crate::error::Result<()> {
+        match &mut self.protocol {
+            Protocol::Raw(r) => r.set_nonblocking(enable),
+
+            #[cfg(feature = "visa")]
+            Protocol::Visa { .. } => Ok(()),
+        }
+    }
+}
+
+impl Drop for Instrument {
+    #[tracing::instrument(skip(self))]
+    fn drop(&mut self) {
+        trace!("calling generic drop...");
+        // Don't wait for the reset to complete since the connection may be gone
+        let _ = self.write_all(b"abort\n");
+        let _ = self.write_all(self.reset_command());
+    }
+}
+
+impl Reset for Instrument {
+    #[tracing::instrument(skip(self))]
+    fn reset(&mut self) -> crate::error::Result<()> {
+        trace!("calling generic reset...");
+        self.write_all(b"abort\n")?;
+        self.write_all(self.reset_command())?;
+        crate::instrument::wait_complete(self, crate::instrument::COMPLETE_TIMEOUT)
+    }
+}
+
+impl Abort for Instrument {
+    #[tracing::instrument(skip(self))]
+    fn abort(&mut self) -> crate::error::Result<()> {
+        trace!("calling generic abort...");
+        self.write_all(b"abort\n")?;
+        crate::instrument::wait_complete(self, crate::instrument::COMPLETE_TIMEOUT)
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use crate::{
+        instrument::{authenticate::Authentication, Login, Reset},
+        model::capabilities::{LoginStyle, TspLinkDialect},
+        protocol::Protocol,
+        test_util::FakeInstrument,
+        Flash,
+    };
+
+    use super::{Instrument, Probe};
+
+    #[test]
+    fn parse_probe() {
+        assert_eq!(
+            Probe::parse("function\tinitialize", false),
+            Probe {
+                language_query: false,
+                login: None,
+                firmware_update: true,
+                tsplink: Some(TspLinkDialect::Initialize),
+            }
+        );
+        assert_eq!(
+            Probe::parse("nil\treset", true).tsplink,
+            Some(TspLinkDialect::Reset)
+        );
+        assert_eq!(Probe::parse("", false), Probe::default());
+    }
+
+    #[test]
+    fn unprobed_instrument_is_conservative() {
+        let fake = FakeInstrument::new();
+        let mut instrument = Instrument::new(Protocol::new(fake.clone()), Authentication::NoAuth);
+
+        assert!(instrument.flash_firmware(b"image", None).is_err());
+        instrument.reset().unwrap();
+
+        assert_eq!(fake.commands(), ["abort", "reset()"]);
+    }
+
+    #[test]
+    fn probed_features_are_used() {
+        let fake = FakeInstrument::new();
+        let mut instrument = Instrument::with_probe(
+            Protocol::new(fake.clone()),
+            Authentication::NoAuth,
+            Probe {
+                language_query: true,
+                firmware_update: true,
+                ..Probe::default()
+            },
+        );
+
+        instrument.flash_firmware(b"image", None).unwrap();
+        instrument.reset().unwrap();
+
+        assert_eq!(
+            fake.commands(),
+            [
+                "localnode.prompts = 0",
+                "flash",
+                "imageendflash",
+                "firmware.update()",
+                "abort",
+                "*RST"
+            ]
+        );
+    }
+
+    #[test]
+    fn login_finds_the_accepted_command() {
+        let unlocked = Arc::new(AtomicBool::new(false));
+        let fake = FakeInstrument::new()
+            .with_replies(&[b"KEITHLEY INSTRUMENTS,MODEL 9000,1,1.0.0\n"])
+            .with_responder({
+                let unlocked = Arc::clone(&unlocked);
+                move |command| match command {
+                    "print('unlocked')" if unlocked.load(Ordering::SeqCst) => {
+                        Some(b"unlocked\n".to_vec())
+                    }
+                    "print('unlocked')" => Some(b"FAILURE: locked\n".to_vec()),
+                    "password secret" => {
+                        unlocked.store(true, Ordering::SeqCst);
+                        None
+                    }
+                    c if c.starts_with("print(type(firmware)") => {
+                        Some(b"function\tinitialize\n".to_vec())
+                    }
+                    _ => None,
+                }
+            });
+        let mut instrument = Instrument::new(
+            Protocol::new(fake.clone()),
+            Authentication::Credential {
+                username: String::new(),
+                password: "secret".to_string(),
+            },
+        );
+
+        instrument.login().unwrap();
+
+        let commands = fake.commands();
+        assert_eq!(
+            commands[..5],
+            [
+                "print('unlocked')",
+                "login secret",
+                "print('unlocked')",
+                "password secret",
+                "print('unlocked')"
+            ]
+        );
+        assert_eq!(
+            instrument.supported(),
+            Probe {
+                language_query: false,
+                login: Some(LoginStyle::Password),
+                firmware_update: true,
+                tsplink: Some(TspLinkDialect::Initialize),
+            }
+        );
+    }
+}
@@ -1,23 +1,29 @@
 use std::{
-    io::{BufRead, ErrorKind, Read, Write},
+    io::{BufRead, Read, Write},
     time::Duration,
 };
 
 use bytes::Buf;
 use indicatif::{ProgressBar, ProgressStyle};
-use tracing::{error, trace};
+use tracing::trace;
 
 use crate::{
     instrument::{
-        self, authenticate::Authentication, info::InstrumentInfo, language, Abort, Info, Login,
-        Reset, Script,
+        self, authenticate::Authentication, buffer::BufferDialect, info::InstrumentInfo, language,
+        password::PasswordDialect, status::StatusByte, status::StatusModel, Abort, AuthProvider,
+        Buffers, Info, Login, PasswordAdmin, Reset, Script, ServiceRequest, Status, TspLink,
     },
-    interface::{connection_addr::ConnectionInfo, NonBlock},
-    model::Model,
-    protocol::Protocol,
+    interface::{connect_options::ConnectOptions, connection_addr::ConnectionInfo, NonBlock},
+    model::{
+        capabilities::{Capabilities, TspLinkDialect},
+        driver, Family, Model,
+    },
+    protocol::{self, Clear, DeviceClear, Protocol},
     Flash, InstrumentError,
 };
 
+pub mod smu;
+
 pub struct Instrument {
     info: Option<InstrumentInfo>,
     protocol: Protocol,
@@ -26,7 +32,7 @@ pub struct Instrument {
 
 impl Instrument {
     #[must_use]
-    pub const fn is(info: &InstrumentInfo) -> bool {
+    pub fn is(info: &InstrumentInfo) -> bool {
         info.model.is_2600()
     }
 
@@ -38,15 +44,22 @@ impl Instrument {
         model.is_2600()
     }
 
-    /// Connect to an instrument with the given connection information.
+    /// Connect to an instrument with the given connection information and
+    /// [`ConnectOptions`].
     ///
     /// # Errors
     /// There can be issues in creating the protocol from the given [`ConnectionInfo`].
     /// There can also be issues in getting the instrument information using
     /// [`ConnectionInfo::get_info()`].
-    #[tracing::instrument(skip(conn, auth))]
-    pub fn connect(conn: &ConnectionInfo, auth: Authentication) -> Result<Self, InstrumentError> {
-        let protocol = Protocol::connect(conn)?;
+    #[tracing::instrument(skip(conn, auth, options))]
+    pub fn connect(
+        conn: &ConnectionInfo,
+        auth: Authentication,
+        options: &ConnectOptions,
+    ) -> Result<Self, InstrumentError> {
+        let mut protocol = Protocol::connect(conn, options)?;
+        protocol
+            .set_write_chunk_size(Capabilities::for_family(Some(&Family::_26xx)).usb_write_chunk);
 
         Ok(Self {
             info: None,
@@ -65,9 +78,58 @@ impl Instrument {
     }
 
     pub fn add_info(&mut self, info: InstrumentInfo) -> &Self {
+        self.protocol
+            .set_write_chunk_size(info.model.capabilities().usb_write_chunk);
         self.info = Some(info);
         self
     }
+
+    /// The [`Capabilities`] of this instrument. If the exact model isn't known yet,
+    /// the capabilities common to the family are used.
+    #[must_use]
+    pub fn capabilities(&self) -> Capabilities {
+        self.info.as_ref().map_or_else(
+            || Capabilities::for_family(Some(&Family::_26xx)),
+            |info| info.model.capabilities(),
+        )
+    }
+}
+
+/// The [`driver::Driver`] for this family.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Driver;
+
+impl driver::Driver for Driver {
+    fn name(&self) -> &'static str {
+        "ki2600"
+    }
+
+    fn matches(&self, info: &InstrumentInfo) -> bool {
+        Instrument::is(info)
+    }
+
+    fn connect(
+        &self,
+        conn: &ConnectionInfo,
+        auth: Authentication,
+        options: &ConnectOptions,
+        info: InstrumentInfo,
+    ) -> crate::error::Result<Box<dyn instrument::Instrument>> {
+        let mut instrument = Instrument::connect(conn, auth, options)?;
+        instrument.add_info(info);
+        Ok(Box::new(instrument))
+    }
+
+    fn create(
+        &self,
+        protocol: Protocol,
+        auth: Authentication,
+        info: InstrumentInfo,
+    ) -> Box<dyn instrument::Instrument> {
+        let mut instrument = Instrument::new(protocol, auth);
+        instrument.add_info(info);
+        Box::new(instrument)
+    }
 }
 
 //Implement device_interface::Interface since it is a subset of instrument::Instrument trait.
@@ -80,32 +142,18 @@ impl language::Language for Instrument {}
 impl Login for Instrument {
     fn check_login(&mut self) -> crate::error::Result<instrument::State> {
         self.write_all(b"print('unlocked')\n")?;
-        for _i in 0..5 {
-            std::thread::sleep(Duration::from_millis(100));
-            let mut resp: Vec<u8> = vec![0; 256];
-            let read_size = match self.read(&mut resp) {
-                Ok(read_size) => read_size,
-                Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                    continue;
-                }
-                Err(e) => {
-                    error!("{e:?}: {e}");
-                    return Err(e.into());
-                }
-            };
-            let resp = &resp[..read_size];
-
-            let resp = std::str::from_utf8(resp).unwrap_or("").trim();
-
+        let state = instrument::read_response(self, instrument::login::CHECK_TIMEOUT, |resp| {
             if resp.contains("unlocked") {
-                return Ok(instrument::State::NotNeeded);
-            }
-            if resp.contains("Port in use") {
-                return Ok(instrument::State::LogoutNeeded);
+                Some(instrument::State::NotNeeded)
+            } else if resp.contains("Port in use") {
+                Some(instrument::State::LogoutNeeded)
+            } else if resp.contains("FAILURE") {
+                Some(instrument::State::Needed)
+            } else {
+                None
             }
-        }
-
-        Ok(instrument::State::Needed)
+        })?;
+        Ok(state.unwrap_or(instrument::State::Needed))
     }
 
     fn login(&mut self) -> crate::error::Result<()> {
@@ -116,16 +164,25 @@ impl Login for Instrument {
             return Err(InstrumentError::InterfaceLoginErr);
         }
 
+        if self.auth.needs_identity() {
+            let info = match self.info.clone() {
+                Some(info) => info,
+                None => self.info()?,
+            };
+            self.auth.on_identified(&info)?;
+        }
+
         if let Some(password) = self.auth.read_password()? {
-            self.write_all(format!("password {password}\n").as_bytes())?;
+            let command = self.capabilities().login.command("", &password);
+            self.write_all(format!("{command}\n").as_bytes())?;
         }
 
         inst_login_state = self.check_login()?;
         if instrument::State::NotNeeded == inst_login_state {
             let info = self.info()?;
-            self.auth
-                .save_credential(&info.model, &info.serial_number)?;
+            self.auth.on_success(&info)?;
         } else if instrument::State::Needed == inst_login_state {
+            self.auth.on_rejected()?;
             return Err(InstrumentError::LoginRejected);
         }
 
@@ -133,7 +190,62 @@ impl Login for Instrument {
     }
 }
 
-impl Script for Instrument {}
+impl Script for Instrument {
+    fn max_script_name_len(&self) -> usize {
+        self.capabilities().max_script_name_len
+    }
+}
+
+impl Buffers for Instrument {
+    fn buffer_dialect(&self) -> Option<BufferDialect> {
+        Some(BufferDialect::Smu(self.smu_channels()))
+    }
+}
+
+impl Status for Instrument {
+    fn status_model(&self) -> StatusModel {
+        self.capabilities().status
+    }
+}
+
+impl ServiceRequest for Instrument {
+    fn wait_for_srq(&mut self, timeout: Duration) -> crate::error::Result<StatusByte> {
+        self.protocol.wait_for_srq(timeout)
+    }
+}
+
+impl Clear for Instrument {
+    type Error = InstrumentError;
+
+    /// Clear the instrument, logging in again if the session was reconnected.
+    fn device_clear(&mut self) -> crate::error::Result<DeviceClear> {
+        let cleared = self.protocol.device_clear()?;
+        if cleared == DeviceClear::Reconnected {
+            self.login()?;
+        }
+        Ok(cleared)
+    }
+}
+
+impl PasswordAdmin for Instrument {
+    fn password_dialect(&self) -> Option<PasswordDialect> {
+        self.capabilities().password
+    }
+}
+
+impl TspLink for Instrument {
+    fn tsplink_initialize_command(&self) -> Option<&'static str> {
+        self.capabilities()
+            .tsplink
+            .map(TspLinkDialect::initialize_command)
+    }
+
+    fn node_revision_attribute(&self) -> &'static str {
+        self.capabilities()
+            .tsplink
+            .map_or("version", TspLinkDialect::revision_attribute)
+    }
+}
 
 impl Flash for Instrument {
     fn flash_firmware(&mut self, image: &[u8], _: Option<u16>) -> crate::error::Result<()> {
@@ -156,7 +268,8 @@ impl Flash for Instrument {
         };
         let mut image = image.reader();
         self.write_all(b"localnode.prompts = 0\n")?;
-        self.write_all(b"flash\n")?;
+        let begin = self.capabilities().firmware.begin_command();
+        self.write_all(format!("{begin}\n").as_bytes())?;
         self.write_all(image.fill_buf().unwrap())?;
         self.write_all(b"endflash\n")?;
         if let Some(pb) = spinner {
@@ -186,11 +299,10 @@ impl Read for Instrument {
 impl Write for Instrument {
     #[tracing::instrument(skip(self, buf))]
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        if String::from_utf8_lossy(buf).contains("password") {
-            trace!("writing to instrument: 'password ****'");
-        } else {
-            trace!("writing to instrument: '{}'", String::from_utf8_lossy(buf));
-        }
+        trace!(
+            "writing to instrument: '{}'",
+            protocol::redact(&String::from_utf8_lossy(buf))
+        );
         self.protocol.write(buf)
     }
 
@@ -218,7 +330,9 @@ impl Drop for Instrument {
     #[tracing::instrument(skip(self))]
     fn drop(&mut self) {
         trace!("calling ki2600 drop...");
-        let _ = self.reset();
+        // Don't wait for the reset to complete since the connection may be gone
+        let _ = self.write_all(b"*RST\n");
+        let _ = self.write_all(b"abort\n");
         let _ = self.write_all(b"password\n");
         std::thread::sleep(Duration::from_millis(100));
     }
@@ -228,11 +342,9 @@ impl Reset for Instrument {
     #[tracing::instrument(skip(self))]
     fn reset(&mut self) -> crate::error::Result<()> {
         trace!("Calling ki2600 reset...");
-        let _ = self.write_all(b"*RST\n");
-        std::thread::sleep(Duration::from_millis(100));
-        let _ = self.write_all(b"abort\n");
-        std::thread::sleep(Duration::from_millis(100));
-        Ok(())
+        self.write_all(b"*RST\n")?;
+        self.write_all(b"abort\n")?;
+        instrument::wait_complete(self, instrument::COMPLETE_TIMEOUT)
     }
 }
 
@@ -240,9 +352,8 @@ impl Abort for Instrument {
     #[tracing::instrument(skip(self))]
     fn abort(&mut self) -> crate::error::Result<()> {
         trace!("Calling ki2600 abort...");
-        let _ = self.write_all(b"abort\n");
-        std::thread::sleep(Duration::from_millis(100));
-        Ok(())
+        self.write_all(b"abort\n")?;
+        instrument::wait_complete(self, instrument::COMPLETE_TIMEOUT)
     }
 }
 
@@ -367,7 +478,7 @@ mod unit {
 
         interface
             .expect_read()
-            .times(5)
+            .times(1)
             .withf(|buf: &[u8]| buf.len() >= 8)
             .returning(|buf: &mut [u8]| {
                 let msg = b"FAILURE\n";
@@ -391,7 +502,7 @@ mod unit {
 
         interface
             .expect_read()
-            .times(5)
+            .times(1)
             .in_sequence(&mut seq)
             .withf(|buf: &[u8]| buf.len() >= 8)
             .returning(|buf: &mut [u8]| {
@@ -543,7 +654,7 @@ mod unit {
 
         interface
             .expect_read()
-            .times(5)
+            .times(1)
             .in_sequence(&mut seq)
             .withf(|buf: &[u8]| buf.len() >= 8)
             .returning(|buf: &mut [u8]| {
@@ -568,7 +679,7 @@ mod unit {
 
         interface
             .expect_read()
-            .times(5)
+            .times(1)
             .in_sequence(&mut seq)
             .withf(|buf: &[u8]| buf.len() >= 8)
             .returning(|buf: &mut [u8]| {
@@ -601,7 +712,7 @@ mod unit {
 
         interface
             .expect_read()
-            .times(5)
+            .times(1)
             .in_sequence(&mut seq)
             .withf(|buf: &[u8]| buf.len() >= 8)
             .returning(|buf: &mut [u8]| {
@@ -626,7 +737,7 @@ mod unit {
 
         interface
             .expect_read()
-            .times(5)
+            .times(1)
             .in_sequence(&mut seq)
             .withf(|buf: &[u8]| buf.len() >= 8)
             .returning(|buf: &mut [u8]| {
@@ -743,6 +854,7 @@ mod unit {
     fn write_script() {
         let mut interface = MockInterface::new();
         let mut seq = Sequence::new();
+        test_util::expect_wait_complete!(interface);
 
         interface
             .expect_write()
@@ -818,6 +930,7 @@ mod unit {
     fn write_script_run() {
         let mut interface = MockInterface::new();
         let mut seq = Sequence::new();
+        test_util::expect_wait_complete!(interface);
 
         interface
             .expect_write()
@@ -900,6 +1013,7 @@ mod unit {
     fn write_script_save() {
         let mut interface = MockInterface::new();
         let mut seq = Sequence::new();
+        test_util::expect_wait_complete!(interface);
 
         interface
             .expect_write()
@@ -982,6 +1096,7 @@ mod unit {
     fn write_script_save_run() {
         let mut interface = MockInterface::new();
         let mut seq = Sequence::new();
+        test_util::expect_wait_complete!(interface);
         interface
             .expect_write()
             .times(..)
@@ -0,0 +1,626 @@
+//! Sourcing and measuring with the SMU channels of 2600 series instruments.
+//!
+//! An [`Smu`] is a handle to one channel (`smua` or `smub`) of an [`Instrument`].
+//! The channels that are available come from the
+//! [`Capabilities`](crate::model::capabilities::Capabilities) of the model, so
+//! `smub` can't be used on single-channel models like the 2601B.
+
+use std::{fmt::Display, io::Write, time::Duration};
+
+use crate::{error::Result, instrument::query_tsp_timeout, InstrumentError};
+
+use super::Instrument;
+
+/// The range of NPLC values accepted by the 2600 series.
+const NPLC_RANGE: std::ops::RangeInclusive<f64> = 0.001..=25.0;
+
+/// What an SMU channel sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SourceFunction {
+    /// Source a DC voltage.
+    Voltage,
+    /// Source a DC current.
+    Current,
+}
+
+impl SourceFunction {
+    const fn suffix(self) -> char {
+        match self {
+            Self::Voltage => 'v',
+            Self::Current => 'i',
+        }
+    }
+
+    const fn output(self) -> &'static str {
+        match self {
+            Self::Voltage => "OUTPUT_DCVOLTS",
+            Self::Current => "OUTPUT_DCAMPS",
+        }
+    }
+
+    /// The unit of the sourced value.
+    #[must_use]
+    pub const fn unit(self) -> Unit {
+        match self {
+            Self::Voltage => Unit::Volt,
+            Self::Current => Unit::Amp,
+        }
+    }
+}
+
+/// What an SMU channel measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Measurement {
+    /// Current in amps.
+    Current,
+    /// Voltage in volts.
+    Voltage,
+    /// Resistance in ohms.
+    Resistance,
+    /// Power in watts.
+    Power,
+}
+
+impl Measurement {
+    const fn suffix(self) -> char {
+        match self {
+            Self::Current => 'i',
+            Self::Voltage => 'v',
+            Self::Resistance => 'r',
+            Self::Power => 'p',
+        }
+    }
+
+    /// The unit of the measured value.
+    #[must_use]
+    pub const fn unit(self) -> Unit {
+        match self {
+            Self::Current => Unit::Amp,
+            Self::Voltage => Unit::Volt,
+            Self::Resistance => Unit::Ohm,
+            Self::Power => Unit::Watt,
+        }
+    }
+}
+
+/// The unit of a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unit {
+    /// Amps (A)
+    Amp,
+    /// Volts (V)
+    Volt,
+    /// Ohms (Ω)
+    Ohm,
+    /// Watts (W)
+    Watt,
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            Self::Amp => "A",
+            Self::Volt => "V",
+            Self::Ohm => "Ω",
+            Self::Watt => "W",
+        };
+        write!(f, "{symbol}")
+    }
+}
+
+/// A source or measure range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Range {
+    /// Let the instrument pick the range.
+    Auto,
+    /// Use the range that fits the given value.
+    Fixed(f64),
+}
+
+/// When the instrument measures its internal references.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AutoZero {
+    /// Never; use the last reference measurements.
+    Off,
+    /// Once, then turn autozero off.
+    Once,
+    /// Before every measurement.
+    Auto,
+}
+
+/// A single measurement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    /// The measured value
+    pub value: f64,
+    /// The unit of the value
+    pub unit: Unit,
+}
+
+impl Display for Reading {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.value, self.unit)
+    }
+}
+
+/// The source values of a sweep.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SweepPoints {
+    /// `points` evenly spaced values from `start` to `stop`.
+    Linear { start: f64, stop: f64, points: u16 },
+    /// `points` logarithmically spaced values from `start` to `stop`, which must
+    /// have the same sign and not be zero.
+    Log { start: f64, stop: f64, points: u16 },
+    /// The given values.
+    List(Vec<f64>),
+}
+
+impl SweepPoints {
+    /// The source values of the sweep.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the sweep has no points or has invalid values.
+    pub fn values(&self) -> Result<Vec<f64>> {
+        let values = match self {
+            Self::Linear {
+                start,
+                stop,
+                points,
+            } => spaced(*points, |t| (stop - start).mul_add(t, *start))?,
+            Self::Log {
+                start,
+                stop,
+                points,
+            } => {
+                if *start == 0.0 || *stop == 0.0 || start.signum() != stop.signum() {
+                    return Err(InstrumentError::Other(
+                        "log sweeps must start and stop at non-zero values of the same sign"
+                            .to_string(),
+                    ));
+                }
+                spaced(*points, |t| start * (stop / start).powf(t))?
+            }
+            Self::List(values) => values.clone(),
+        };
+        if values.is_empty() || values.iter().any(|v| !v.is_finite()) {
+            return Err(InstrumentError::Other(
+                "sweeps must have at least one finite point".to_string(),
+            ));
+        }
+        Ok(values)
+    }
+}
+
+/// Generate `points` values from `f(0.0)` to `f(1.0)`.
+fn spaced(points: u16, f: impl Fn(f64) -> f64) -> Result<Vec<f64>> {
+    match points {
+        0 => Err(InstrumentError::Other(
+            "sweeps must have at least one point".to_string(),
+        )),
+        1 => Ok(vec![f(0.0)]),
+        n => {
+            let last = f64::from(n.saturating_sub(1));
+            Ok((0..n).map(|i| f(f64::from(i) / last)).collect())
+        }
+    }
+}
+
+/// A sweep of the source while measuring at each point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+    /// What to source.
+    pub source: SourceFunction,
+    /// The source values.
+    pub points: SweepPoints,
+    /// What to measure at each point.
+    pub measure: Measurement,
+}
+
+/// The result of a [`Sweep`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepResult {
+    /// The unit of the source values.
+    pub source_unit: Unit,
+    /// The source value of each point.
+    pub source: Vec<f64>,
+    /// The unit of the readings.
+    pub unit: Unit,
+    /// The reading at each point.
+    pub readings: Vec<f64>,
+}
+
+/// A handle to one SMU channel of an [`Instrument`].
+pub struct Smu<'a> {
+    instrument: &'a mut Instrument,
+    name: &'static str,
+}
+
+impl Instrument {
+    /// The names of the SMU channels of this instrument.
+    #[must_use]
+    pub fn smu_channels(&self) -> &'static [&'static str] {
+        self.capabilities().smu_channels
+    }
+
+    /// Get a handle to the SMU channel with the given name (e.g. `smua`).
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the instrument doesn't have the given channel.
+    pub fn smu(&mut self, name: &str) -> Result<Smu<'_>> {
+        let Some(name) = self.smu_channels().iter().find(|c| **c == name) else {
+            return Err(InstrumentError::Other(format!(
+                "this instrument has no SMU channel named '{name}'"
+            )));
+        };
+        Ok(Smu {
+            instrument: self,
+            name,
+        })
+    }
+
+    /// Get a handle to SMU A.
+    ///
+    /// # Errors
+    /// See [`Instrument::smu`].
+    pub fn smua(&mut self) -> Result<Smu<'_>> {
+        self.smu("smua")
+    }
+
+    /// Get a handle to SMU B.
+    ///
+    /// # Errors
+    /// See [`Instrument::smu`].
+    pub fn smub(&mut self) -> Result<Smu<'_>> {
+        self.smu("smub")
+    }
+}
+
+impl Smu<'_> {
+    /// The name of this channel (e.g. `smua`).
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Set what the channel sources.
+    ///
+    /// # Errors
+    /// Any IO errors.
+    pub fn set_source_function(&mut self, function: SourceFunction) -> Result<()> {
+        let smu = self.name;
+        self.send(&format!("{smu}.source.func = {smu}.{}", function.output()))
+    }
+
+    /// Set the source level of the given function.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the level isn't finite. Any IO errors.
+    pub fn set_level(&mut self, function: SourceFunction, level: f64) -> Result<()> {
+        let smu = self.name;
+        let level = number(level)?;
+        self.send(&format!(
+            "{smu}.source.level{} = {level}",
+            function.suffix()
+        ))
+    }
+
+    /// Set the compliance limit of voltage, current or power.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the measurement can't be limited or the limit
+    /// isn't finite. Any IO errors.
+    pub fn set_limit(&mut self, measurement: Measurement, limit: f64) -> Result<()> {
+        if measurement == Measurement::Resistance {
+            return Err(InstrumentError::Other(
+                "resistance can't be used as a source limit".to_string(),
+            ));
+        }
+        let smu = self.name;
+        let limit = number(limit)?;
+        self.send(&format!(
+            "{smu}.source.limit{} = {limit}",
+            measurement.suffix()
+        ))
+    }
+
+    /// Set the source range of the given function.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if a fixed range isn't finite. Any IO errors.
+    pub fn set_source_range(&mut self, function: SourceFunction, range: Range) -> Result<()> {
+        self.set_range("source", function.suffix(), range)
+    }
+
+    /// Set the measure range of voltage or current.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the measurement doesn't have a range or a fixed
+    /// range isn't finite. Any IO errors.
+    pub fn set_measure_range(&mut self, measurement: Measurement, range: Range) -> Result<()> {
+        if !matches!(measurement, Measurement::Current | Measurement::Voltage) {
+            return Err(InstrumentError::Other(
+                "only current and voltage have measure ranges".to_string(),
+            ));
+        }
+        self.set_range("measure", measurement.suffix(), range)
+    }
+
+    fn set_range(&mut self, kind: &str, suffix: char, range: Range) -> Result<()> {
+        let smu = self.name;
+        match range {
+            Range::Auto => self.send(&format!(
+                "{smu}.{kind}.autorange{suffix} = {smu}.AUTORANGE_ON"
+            )),
+            Range::Fixed(value) => {
+                let value = number(value)?;
+                self.send(&format!("{smu}.{kind}.range{suffix} = {value}"))
+            }
+        }
+    }
+
+    /// Turn the output on or off.
+    ///
+    /// # Errors
+    /// Any IO errors.
+    pub fn set_output(&mut self, on: bool) -> Result<()> {
+        let smu = self.name;
+        let state = if on { "OUTPUT_ON" } else { "OUTPUT_OFF" };
+        self.send(&format!("{smu}.source.output = {smu}.{state}"))
+    }
+
+    /// Set the integration time in power line cycles.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the NPLC is outside of 0.001 to 25. Any IO
+    /// errors.
+    pub fn set_nplc(&mut self, nplc: f64) -> Result<()> {
+        if !NPLC_RANGE.contains(&nplc) {
+            return Err(InstrumentError::Other(format!(
+                "NPLC must be between {} and {}",
+                NPLC_RANGE.start(),
+                NPLC_RANGE.end()
+            )));
+        }
+        let smu = self.name;
+        self.send(&format!("{smu}.measure.nplc = {}", number(nplc)?))
+    }
+
+    /// Set when the internal references are measured.
+    ///
+    /// # Errors
+    /// Any IO errors.
+    pub fn set_autozero(&mut self, autozero: AutoZero) -> Result<()> {
+        let smu = self.name;
+        let mode = match autozero {
+            AutoZero::Off => "AUTOZERO_OFF",
+            AutoZero::Once => "AUTOZERO_ONCE",
+            AutoZero::Auto => "AUTOZERO_AUTO",
+        };
+        self.send(&format!("{smu}.measure.autozero = {smu}.{mode}"))
+    }
+
+    /// Take a single measurement, waiting up to `timeout` for it. The timeout must
+    /// allow for the NPLC, autozero and any filtering of the channel.
+    ///
+    /// # Errors
+    /// Any IO errors, [`InstrumentError::Other`] if the reading wasn't read within
+    /// `timeout`, or [`InstrumentError::InformationRetrievalError`] if the reading
+    /// could not be parsed.
+    pub fn measure(&mut self, measurement: Measurement, timeout: Duration) -> Result<Reading> {
+        let smu = self.name;
+        let lines = query_tsp_timeout(
+            self.instrument,
+            &format!("print({smu}.measure.{}())", measurement.suffix()),
+            timeout,
+        )?;
+        let values = parse_readings(&lines)?;
+        let [value] = values[..] else {
+            return Err(InstrumentError::InformationRetrievalError {
+                details: format!("expected one reading but read {}", values.len()),
+            });
+        };
+        Ok(Reading {
+            value,
+            unit: measurement.unit(),
+        })
+    }
+
+    /// Run the given sweep and return the reading at each point, waiting up to
+    /// `timeout` for the whole sweep. The timeout must allow for every point, so it
+    /// grows with the number of points and the NPLC. The output is turned on for the
+    /// sweep and off afterwards.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the sweep points are invalid or the readings
+    /// weren't read within `timeout`, any IO errors, or
+    /// [`InstrumentError::InformationRetrievalError`] if the readings could not be
+    /// parsed.
+    pub fn sweep(&mut self, sweep: &Sweep, timeout: Duration) -> Result<SweepResult> {
+        let source = sweep.points.values()?;
+        let tsp = sweep_tsp(self.name, sweep, &source)?;
+        let lines = query_tsp_timeout(self.instrument, &tsp, timeout)?;
+        let readings = parse_readings(&lines)?;
+        if readings.len() != source.len() {
+            return Err(InstrumentError::InformationRetrievalError {
+                details: format!(
+                    "expected {} readings but read {}",
+                    source.len(),
+                    readings.len()
+                ),
+            });
+        }
+        Ok(SweepResult {
+            source_unit: sweep.source.unit(),
+            source,
+            unit: sweep.measure.unit(),
+            readings,
+        })
+    }
+
+    fn send(&mut self, tsp: &str) -> Result<()> {
+        self.instrument.write_all(format!("{tsp}\n").as_bytes())?;
+        self.instrument.flush()?;
+        Ok(())
+    }
+}
+
+/// Format a number for TSP, rejecting values TSP can't represent.
+fn number(value: f64) -> Result<String> {
+    if value.is_finite() {
+        Ok(format!("{value:e}"))
+    } else {
+        Err(InstrumentError::Other(format!(
+            "{value} is not a valid setting"
+        )))
+    }
+}
+
+/// Build the TSP for a sweep that prints the readings with `printbuffer`.
+fn sweep_tsp(smu: &str, sweep: &Sweep, source: &[f64]) -> Result<String> {
+    let values = source
+        .iter()
+        .map(|v| number(*v))
+        .collect::<Result<Vec<_>>>()?
+        .join(", ");
+    let level = sweep.source.suffix();
+    let measure = sweep.measure.suffix();
+    Ok(format!(
+        "{smu}.source.func = {smu}.{func} {smu}.nvbuffer1.clear() \
+         {smu}.source.output = {smu}.OUTPUT_ON \
+         for _, level in ipairs({{{values}}}) do {smu}.source.level{level} = level \
+         {smu}.measure.{measure}({smu}.nvbuffer1) end \
+         {smu}.source.output = {smu}.OUTPUT_OFF \
+         printbuffer(1, {smu}.nvbuffer1.n, {smu}.nvbuffer1.readings)",
+        func = sweep.source.output(),
+    ))
+}
+
+/// Parse comma- or line-separated readings.
+fn parse_readings(lines: &[String]) -> Result<Vec<f64>> {
+    lines
+        .iter()
+        .flat_map(|l| l.split(','))
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .map(|r| {
+            r.parse::<f64>()
+                .map_err(|_| InstrumentError::InformationRetrievalError {
+                    details: format!("unable to parse reading '{r}'"),
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod unit {
+    use std::time::Duration;
+
+    use crate::{
+        instrument::{authenticate::Authentication, info::InstrumentInfo},
+        model::Model,
+        protocol::Protocol,
+        test_util::FakeInstrument,
+    };
+
+    use super::{super::Instrument, Measurement, Range, SourceFunction, Sweep, SweepPoints, Unit};
+
+    fn instrument(model: Model, fake: &FakeInstrument) -> Instrument {
+        let mut instrument = Instrument::new(Protocol::new(fake.clone()), Authentication::NoAuth);
+        instrument.add_info(InstrumentInfo {
+            model,
+            ..InstrumentInfo::default()
+        });
+        instrument
+    }
+
+    #[test]
+    fn channels_depend_on_model() {
+        let fake = FakeInstrument::new();
+        let mut single = instrument(Model::_2601B, &fake);
+        assert!(single.smua().is_ok());
+        assert!(single.smub().is_err());
+
+        let mut dual = instrument(Model::_2602B, &fake);
+        assert!(dual.smub().is_ok());
+    }
+
+    #[test]
+    fn settings_generate_tsp() {
+        let fake = FakeInstrument::new();
+        let mut inst = instrument(Model::_2602B, &fake);
+        let mut smu = inst.smub().unwrap();
+        smu.set_source_function(SourceFunction::Current).unwrap();
+        smu.set_level(SourceFunction::Current, 0.001).unwrap();
+        smu.set_limit(Measurement::Voltage, 10.0).unwrap();
+        smu.set_measure_range(Measurement::Voltage, Range::Auto)
+            .unwrap();
+        smu.set_output(true).unwrap();
+        assert!(smu.set_nplc(30.0).is_err());
+        assert!(smu.set_limit(Measurement::Resistance, 1.0).is_err());
+        assert!(smu.set_level(SourceFunction::Voltage, f64::NAN).is_err());
+
+        assert_eq!(
+            fake.written(),
+            "smub.source.func = smub.OUTPUT_DCAMPS\n\
+             smub.source.leveli = 1e-3\n\
+             smub.source.limitv = 1e1\n\
+             smub.measure.autorangev = smub.AUTORANGE_ON\n\
+             smub.source.output = smub.OUTPUT_ON\n"
+        );
+    }
+
+    #[test]
+    fn measure_and_sweep() {
+        let fake = FakeInstrument::new().with_lines(&["1.00000e-03"]);
+        let mut inst = instrument(Model::_2601B, &fake);
+        let reading = inst
+            .smua()
+            .unwrap()
+            .measure(Measurement::Current, Duration::from_secs(1))
+            .unwrap();
+        assert!((reading.value - 1e-3).abs() < f64::EPSILON);
+        assert_eq!(reading.unit, Unit::Amp);
+
+        let fake = FakeInstrument::new().with_lines(&["1.0e-03, 2.0e-03, 3.0e-03"]);
+        let mut inst = instrument(Model::_2601B, &fake);
+        let result = inst
+            .smua()
+            .unwrap()
+            .sweep(
+                &Sweep {
+                    source: SourceFunction::Voltage,
+                    points: SweepPoints::Linear {
+                        start: 0.0,
+                        stop: 2.0,
+                        points: 3,
+                    },
+                    measure: Measurement::Current,
+                },
+                Duration::from_secs(1),
+            )
+            .unwrap();
+        assert_eq!(result.source, vec![0.0, 1.0, 2.0]);
+        assert_eq!(result.readings, vec![1e-3, 2e-3, 3e-3]);
+        assert!(fake.written().contains("ipairs({0e0, 1e0, 2e0})"));
+    }
+
+    #[test]
+    fn sweep_points() {
+        let log = SweepPoints::Log {
+            start: 1.0,
+            stop: 100.0,
+            points: 3,
+        }
+        .values()
+        .unwrap();
+        assert!((log[1] - 10.0).abs() < 1e-9);
+        assert!(SweepPoints::Log {
+            start: -1.0,
+            stop: 1.0,
+            points: 3
+        }
+        .values()
+        .is_err());
+        assert!(SweepPoints::List(Vec::new()).values().is_err());
+    }
+}
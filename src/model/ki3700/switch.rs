@@ -0,0 +1,620 @@
+//! Switch cards and channels for the 3706A and 707B/708B mainframes.
+//!
+//! Channels are addressed by slot and then either by channel number (multiplexer
+//! cards), by row and column (matrix cards) or by analog backplane relay. Channel
+//! lists are checked against the cards that are installed before they are sent to
+//! the instrument.
+
+use std::{fmt::Display, time::Duration};
+
+use crate::{error::Result, instrument::query_tsp, model::Model, InstrumentError};
+
+use super::Instrument;
+
+/// The highest slot number on any supported mainframe.
+pub const MAX_SLOT: u8 = 6;
+
+/// The number of attempts to make when reading a response.
+const READ_ATTEMPTS: usize = 50;
+
+/// The delay between attempts when reading a response.
+const READ_DELAY: Duration = Duration::from_millis(20);
+
+/// How matrix rows are written in channel names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RowStyle {
+    /// Rows are numbers (`1205` is slot 1, row 2, column 5), as on the 3706A.
+    Numeric,
+    /// Rows are letters (`1B05` is slot 1, row B, column 5), as on the 707B and 708B.
+    Letter,
+}
+
+/// What a [`Channel`] refers to within its slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ChannelKind {
+    /// A multiplexer channel.
+    Channel(u16),
+    /// A matrix crosspoint. Rows and columns start at 1.
+    Crosspoint {
+        /// The row of the crosspoint
+        row: u8,
+        /// The column of the crosspoint
+        column: u8,
+    },
+    /// An analog backplane relay (e.g. `911`).
+    Backplane(u16),
+}
+
+/// A channel on a switch card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Channel {
+    /// The slot of the card.
+    pub slot: u8,
+    /// The channel within the slot.
+    pub kind: ChannelKind,
+}
+
+impl Channel {
+    /// A multiplexer channel.
+    #[must_use]
+    pub const fn mux(slot: u8, channel: u16) -> Self {
+        Self {
+            slot,
+            kind: ChannelKind::Channel(channel),
+        }
+    }
+
+    /// A matrix crosspoint.
+    #[must_use]
+    pub const fn crosspoint(slot: u8, row: u8, column: u8) -> Self {
+        Self {
+            slot,
+            kind: ChannelKind::Crosspoint { row, column },
+        }
+    }
+
+    /// An analog backplane relay.
+    #[must_use]
+    pub const fn backplane(slot: u8, relay: u16) -> Self {
+        Self {
+            slot,
+            kind: ChannelKind::Backplane(relay),
+        }
+    }
+
+    /// The name of this channel as used by the instrument.
+    #[must_use]
+    pub fn name(self, style: RowStyle) -> String {
+        let slot = self.slot;
+        match self.kind {
+            ChannelKind::Channel(n) => format!("{slot}{n:03}"),
+            ChannelKind::Crosspoint { row, column } => match style {
+                RowStyle::Numeric => format!("{slot}{row}{column:02}"),
+                RowStyle::Letter => {
+                    format!("{slot}{}{column:02}", char::from(b'@'.saturating_add(row)))
+                }
+            },
+            ChannelKind::Backplane(n) => format!("{slot}{n}"),
+        }
+    }
+
+    /// Parse a channel name returned by the instrument, using the installed cards to
+    /// tell multiplexer channels and matrix crosspoints apart.
+    ///
+    /// # Errors
+    /// [`InstrumentError::InvalidChannel`] if the name is not a valid channel name.
+    pub fn parse(name: &str, cards: &[CardInfo]) -> Result<Self> {
+        let invalid = |details: &str| InstrumentError::InvalidChannel {
+            channel: name.to_string(),
+            details: details.to_string(),
+        };
+        let name = name.trim();
+        let mut chars = name.chars();
+        let slot = chars
+            .next()
+            .and_then(|c| c.to_digit(10))
+            .and_then(|d| u8::try_from(d).ok())
+            .ok_or_else(|| invalid("expected a slot number"))?;
+        let rest = chars.as_str();
+        if rest.len() != 3 {
+            return Err(invalid("expected three characters after the slot number"));
+        }
+        let (first, column) = rest.split_at(1);
+        if let Some(row) = first
+            .bytes()
+            .next()
+            .filter(u8::is_ascii_uppercase)
+            .and_then(|b| b.checked_sub(b'@'))
+        {
+            let column = column
+                .parse()
+                .map_err(|_| invalid("expected a column number"))?;
+            return Ok(Self::crosspoint(slot, row, column));
+        }
+        let n: u16 = rest
+            .parse()
+            .map_err(|_| invalid("expected a channel number"))?;
+        if (900..1000).contains(&n) {
+            return Ok(Self::backplane(slot, n));
+        }
+        let is_matrix = cards
+            .iter()
+            .any(|c| c.slot == slot && matches!(c.layout, Some(CardLayout::Matrix { .. })));
+        if is_matrix {
+            let row = first
+                .parse()
+                .map_err(|_| invalid("expected a row number"))?;
+            let column = column
+                .parse()
+                .map_err(|_| invalid("expected a column number"))?;
+            Ok(Self::crosspoint(slot, row, column))
+        } else {
+            Ok(Self::mux(slot, n))
+        }
+    }
+}
+
+/// The channels a card has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CardLayout {
+    /// A card with numbered channels from 1 to `channels`.
+    Multiplexer {
+        /// The number of channels
+        channels: u16,
+        /// Whether the card has analog backplane relays 911-916 and 921-926
+        backplane: bool,
+    },
+    /// A card with `rows` by `columns` crosspoints.
+    Matrix {
+        /// The number of rows
+        rows: u8,
+        /// The number of columns
+        columns: u8,
+        /// Whether the card has analog backplane relays 911-916 and 921-926
+        backplane: bool,
+    },
+}
+
+impl CardLayout {
+    /// The layout of a known card model.
+    #[must_use]
+    pub fn of(model: &str) -> Option<Self> {
+        let mux = |channels| Self::Multiplexer {
+            channels,
+            backplane: true,
+        };
+        match model {
+            "3720" | "3723" | "3724" => Some(mux(60)),
+            "3721" => Some(mux(42)),
+            "3722" => Some(mux(96)),
+            "3740" => Some(mux(32)),
+            "3730" | "3731" => Some(Self::Matrix {
+                rows: 6,
+                columns: 16,
+                backplane: true,
+            }),
+            "7071" | "7072" | "7174" | "7174A" => Some(Self::Matrix {
+                rows: 8,
+                columns: 12,
+                backplane: false,
+            }),
+            "7173" | "7173-50" => Some(Self::Matrix {
+                rows: 4,
+                columns: 12,
+                backplane: false,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Check that the given channel exists on a card with this layout.
+    fn check(self, kind: ChannelKind) -> std::result::Result<(), String> {
+        let backplane = match self {
+            Self::Multiplexer { backplane, .. } | Self::Matrix { backplane, .. } => backplane,
+        };
+        match (self, kind) {
+            (Self::Multiplexer { channels, .. }, ChannelKind::Channel(n)) => {
+                if (1..=channels).contains(&n) {
+                    Ok(())
+                } else {
+                    Err(format!("the card only has channels 1-{channels}"))
+                }
+            }
+            (Self::Matrix { rows, columns, .. }, ChannelKind::Crosspoint { row, column }) => {
+                if (1..=rows).contains(&row) && (1..=columns).contains(&column) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "the card only has {rows} rows and {columns} columns"
+                    ))
+                }
+            }
+            (_, ChannelKind::Backplane(n)) => {
+                if backplane && matches!(n, 911..=916 | 921..=926) {
+                    Ok(())
+                } else {
+                    Err("the card has no such backplane relay".to_string())
+                }
+            }
+            (Self::Multiplexer { .. }, ChannelKind::Crosspoint { .. }) => {
+                Err("the card is a multiplexer, not a matrix".to_string())
+            }
+            (Self::Matrix { .. }, ChannelKind::Channel(_)) => {
+                Err("the card is a matrix, not a multiplexer".to_string())
+            }
+        }
+    }
+}
+
+/// A card installed in a slot, from `slot[N].idn`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CardInfo {
+    /// The slot the card is installed in.
+    pub slot: u8,
+    /// The model of the card (e.g. `3720`).
+    pub model: String,
+    /// The description of the card (e.g. `Dual 1x30 Multiplexer`).
+    pub description: String,
+    /// The firmware revision of the card.
+    pub firmware_rev: String,
+    /// The serial number of the card.
+    pub serial_number: String,
+    /// The channels of the card, if the card model is known.
+    pub layout: Option<CardLayout>,
+}
+
+impl CardInfo {
+    /// Parse a `slot number, idn` line. Empty slots return [`None`].
+    fn parse(line: &str) -> Option<Self> {
+        let (slot, idn) = line.split_once('\t')?;
+        let slot = slot
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(|s| format!("{s:.0}").parse().ok())?;
+        let idn = idn.trim();
+        if idn.is_empty() || idn == "nil" || idn.eq_ignore_ascii_case("empty slot") {
+            return None;
+        }
+        let mut fields = idn.split(',').map(str::trim);
+        let model = fields.next().unwrap_or_default().to_string();
+        Some(Self {
+            slot,
+            layout: CardLayout::of(&model),
+            model,
+            description: fields.next().unwrap_or_default().to_string(),
+            firmware_rev: fields.next().unwrap_or_default().to_string(),
+            serial_number: fields.next().unwrap_or_default().to_string(),
+        })
+    }
+}
+
+impl Display for CardInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "slot {}: {} {}", self.slot, self.model, self.description)
+    }
+}
+
+impl Instrument {
+    /// How matrix rows are written in channel names on this mainframe.
+    #[must_use]
+    pub fn row_style(&self) -> RowStyle {
+        match self.info.as_ref().map(|i| &i.model) {
+            Some(Model::_707B | Model::_708B) => RowStyle::Letter,
+            _ => RowStyle::Numeric,
+        }
+    }
+
+    /// Read the cards that are installed in the mainframe. The result is cached for
+    /// validating channel lists.
+    ///
+    /// # Errors
+    /// Any IO errors or errors reading the output.
+    pub fn read_cards(&mut self) -> Result<Vec<CardInfo>> {
+        let lines = query_tsp(
+            self,
+            &format!(
+                "for i = 1, {MAX_SLOT} do if slot[i] ~= nil then print(i, slot[i].idn) end end"
+            ),
+            READ_ATTEMPTS,
+            READ_DELAY,
+        )?;
+        let cards: Vec<CardInfo> = lines.iter().filter_map(|l| CardInfo::parse(l)).collect();
+        self.cards = Some(cards.clone());
+        Ok(cards)
+    }
+
+    /// The installed cards, read from the instrument if they haven't been read yet.
+    ///
+    /// # Errors
+    /// See [`Instrument::read_cards`].
+    pub fn cards(&mut self) -> Result<&[CardInfo]> {
+        if self.cards.is_none() {
+            self.read_cards()?;
+        }
+        Ok(self.cards.as_deref().unwrap_or_default())
+    }
+
+    /// Check the given channels against the installed cards and build the channel
+    /// list to send to the instrument.
+    ///
+    /// # Errors
+    /// [`InstrumentError::InvalidChannel`] if the list is empty, if there is no card
+    /// in a channel's slot or if a channel doesn't exist on its card.
+    pub fn channel_list(&mut self, channels: &[Channel]) -> Result<String> {
+        if channels.is_empty() {
+            return Err(InstrumentError::InvalidChannel {
+                channel: String::new(),
+                details: "the channel list is empty".to_string(),
+            });
+        }
+        let style = self.row_style();
+        let cards = self.cards()?;
+        let mut names = Vec::with_capacity(channels.len());
+        for channel in channels {
+            let name = channel.name(style);
+            let Some(card) = cards.iter().find(|c| c.slot == channel.slot) else {
+                return Err(InstrumentError::InvalidChannel {
+                    channel: name,
+                    details: format!("there is no card in slot {}", channel.slot),
+                });
+            };
+            if let Some(layout) = card.layout {
+                layout
+                    .check(channel.kind)
+                    .map_err(|details| InstrumentError::InvalidChannel {
+                        channel: name.clone(),
+                        details,
+                    })?;
+            }
+            names.push(name);
+        }
+        Ok(names.join(","))
+    }
+
+    /// Close the given channels.
+    ///
+    /// # Errors
+    /// See [`Instrument::channel_list`]. Any IO errors.
+    pub fn close(&mut self, channels: &[Channel]) -> Result<()> {
+        let list = self.channel_list(channels)?;
+        self.send(&format!("channel.close(\"{list}\")"))
+    }
+
+    /// Close the given channels and open every other channel.
+    ///
+    /// # Errors
+    /// See [`Instrument::channel_list`]. Any IO errors.
+    pub fn exclusive_close(&mut self, channels: &[Channel]) -> Result<()> {
+        let list = self.channel_list(channels)?;
+        self.send(&format!("channel.exclusiveclose(\"{list}\")"))
+    }
+
+    /// Open the given channels.
+    ///
+    /// # Errors
+    /// See [`Instrument::channel_list`]. Any IO errors.
+    pub fn open(&mut self, channels: &[Channel]) -> Result<()> {
+        let list = self.channel_list(channels)?;
+        self.send(&format!("channel.open(\"{list}\")"))
+    }
+
+    /// Open every channel in every slot.
+    ///
+    /// # Errors
+    /// Any IO errors.
+    pub fn open_all(&mut self) -> Result<()> {
+        self.send("channel.open(\"allslots\")")
+    }
+
+    /// Save the given channels as a channel pattern with the given name.
+    ///
+    /// # Errors
+    /// See [`Instrument::channel_list`]. [`InstrumentError::InvalidChannel`] if the
+    /// pattern name is not a valid name. Any IO errors.
+    pub fn set_pattern(&mut self, name: &str, channels: &[Channel]) -> Result<()> {
+        check_pattern_name(name)?;
+        let list = self.channel_list(channels)?;
+        self.send(&format!("channel.pattern.setimage(\"{list}\", \"{name}\")"))
+    }
+
+    /// Close the channels of the channel pattern with the given name.
+    ///
+    /// # Errors
+    /// [`InstrumentError::InvalidChannel`] if the pattern name is not a valid name.
+    /// Any IO errors.
+    pub fn close_pattern(&mut self, name: &str) -> Result<()> {
+        check_pattern_name(name)?;
+        self.send(&format!("channel.close(\"{name}\")"))
+    }
+
+    /// Open the channels of the channel pattern with the given name.
+    ///
+    /// # Errors
+    /// [`InstrumentError::InvalidChannel`] if the pattern name is not a valid name.
+    /// Any IO errors.
+    pub fn open_pattern(&mut self, name: &str) -> Result<()> {
+        check_pattern_name(name)?;
+        self.send(&format!("channel.open(\"{name}\")"))
+    }
+
+    /// The channels that are closed in any slot.
+    ///
+    /// # Errors
+    /// Any IO errors, or [`InstrumentError::InvalidChannel`] if a channel name
+    /// returned by the instrument could not be parsed.
+    pub fn closed_channels(&mut self) -> Result<Vec<Channel>> {
+        let lines = query_tsp(
+            self,
+            "print(channel.getclose(\"allslots\"))",
+            READ_ATTEMPTS,
+            READ_DELAY,
+        )?;
+        let cards = self.cards()?;
+        lines
+            .iter()
+            .flat_map(|l| l.split([';', ',']))
+            .map(str::trim)
+            .filter(|n| !n.is_empty() && *n != "nil")
+            .map(|n| Channel::parse(n, cards))
+            .collect()
+    }
+
+    /// The number of times the relays of each of the given channels have closed.
+    ///
+    /// # Errors
+    /// See [`Instrument::channel_list`]. Any IO errors, or
+    /// [`InstrumentError::InformationRetrievalError`] if the counts could not be read.
+    pub fn relay_counts(&mut self, channels: &[Channel]) -> Result<Vec<(Channel, u64)>> {
+        let list = self.channel_list(channels)?;
+        let lines = query_tsp(
+            self,
+            &format!("print(channel.getcount(\"{list}\"))"),
+            READ_ATTEMPTS,
+            READ_DELAY,
+        )?;
+        let counts = lines
+            .iter()
+            .flat_map(|l| l.split([';', ',']))
+            .map(|c| {
+                c.trim()
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|c| format!("{c:.0}").parse::<u64>().ok())
+                    .ok_or_else(|| InstrumentError::InformationRetrievalError {
+                        details: format!("unable to read relay count from '{c}'"),
+                    })
+            })
+            .collect::<Result<Vec<u64>>>()?;
+        if counts.len() != channels.len() {
+            return Err(InstrumentError::InformationRetrievalError {
+                details: format!(
+                    "expected {} relay counts but read {}",
+                    channels.len(),
+                    counts.len()
+                ),
+            });
+        }
+        Ok(channels.iter().copied().zip(counts).collect())
+    }
+
+    fn send(&mut self, tsp: &str) -> Result<()> {
+        use std::io::Write;
+        self.write_all(format!("{tsp}\n").as_bytes())?;
+        self.flush()?;
+        Ok(())
+    }
+}
+
+fn check_pattern_name(name: &str) -> Result<()> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(InstrumentError::InvalidChannel {
+            channel: name.to_string(),
+            details: "pattern names must be letters, digits and underscores".to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use crate::{
+        instrument::{authenticate::Authentication, info::InstrumentInfo},
+        model::Model,
+        protocol::Protocol,
+        test_util::FakeInstrument,
+        InstrumentError,
+    };
+
+    use super::{super::Instrument, CardInfo, CardLayout, Channel, RowStyle};
+
+    fn cards() -> Vec<CardInfo> {
+        [
+            "1\t3720,Dual 1x30 Multiplexer,01.40h,1234567",
+            "2\t3730,6x16 High Density Matrix,01.40h,7654321",
+            "3\tEmpty Slot",
+        ]
+        .iter()
+        .filter_map(|l| CardInfo::parse(l))
+        .collect()
+    }
+
+    #[test]
+    fn card_idn_parse() {
+        let cards = cards();
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].slot, 1);
+        assert_eq!(cards[0].description, "Dual 1x30 Multiplexer");
+        assert_eq!(cards[0].serial_number, "1234567");
+        assert!(matches!(
+            cards[1].layout,
+            Some(CardLayout::Matrix {
+                rows: 6,
+                columns: 16,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn channel_names_round_trip() {
+        let cards = cards();
+        for (channel, name) in [
+            (Channel::mux(1, 5), "1005"),
+            (Channel::crosspoint(2, 3, 12), "2312"),
+            (Channel::backplane(1, 911), "1911"),
+        ] {
+            assert_eq!(channel.name(RowStyle::Numeric), name);
+            assert_eq!(Channel::parse(name, &cards).unwrap(), channel);
+        }
+        assert_eq!(Channel::crosspoint(1, 2, 5).name(RowStyle::Letter), "1B05");
+        assert_eq!(
+            Channel::parse("1B05", &[]).unwrap(),
+            Channel::crosspoint(1, 2, 5)
+        );
+        assert!(Channel::parse("10", &cards).is_err());
+        assert!(Channel::parse("x001", &cards).is_err());
+    }
+
+    #[test]
+    fn close_validates_against_cards() {
+        let fake = FakeInstrument::new();
+        let mut instrument = Instrument::new(Protocol::new(fake.clone()), Authentication::NoAuth);
+        instrument.add_info(InstrumentInfo {
+            model: Model::_3706A,
+            ..InstrumentInfo::default()
+        });
+        instrument.cards = Some(cards());
+
+        instrument
+            .close(&[Channel::mux(1, 1), Channel::crosspoint(2, 6, 16)])
+            .unwrap();
+        assert_eq!(fake.written(), "channel.close(\"1001,2616\")\n");
+
+        for bad in [
+            Channel::mux(1, 61),
+            Channel::crosspoint(1, 1, 1),
+            Channel::mux(2, 1),
+            Channel::crosspoint(2, 7, 1),
+            Channel::mux(3, 1),
+        ] {
+            assert!(
+                matches!(
+                    instrument.close(&[bad]),
+                    Err(InstrumentError::InvalidChannel { .. })
+                ),
+                "{bad:?} should be rejected"
+            );
+        }
+        assert!(instrument.close(&[]).is_err());
+        assert!(instrument
+            .set_pattern("bad name", &[Channel::mux(1, 1)])
+            .is_err());
+    }
+}
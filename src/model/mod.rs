@@ -3,53 +3,63 @@ use std::{fmt::Display, str::FromStr};
 use tracing::{instrument, trace};
 
 use crate::{
-    instrument::{authenticate::Authentication, Instrument},
-    interface::connection_addr::ConnectionInfo,
+    instrument::{authenticate::Authentication, info::InstrumentInfo, Instrument},
+    interface::{connect_options::ConnectOptions, connection_addr::ConnectionInfo},
+    model::{driver::DriverRegistry, registry::ModelRegistry},
     protocol::Protocol,
     InstrumentError,
 };
 
+pub mod capabilities;
+pub mod driver;
+pub mod generic;
 pub mod ki2600;
 pub mod ki3700;
+pub mod registry;
 pub mod tti;
 pub mod versatest;
 
+/// Whether any registered [`Driver`](driver::Driver) supports the given model.
 #[must_use]
 pub fn is_supported(model: impl AsRef<str>) -> bool {
-    self::ki2600::Instrument::model_is(&model)
-        || self::ki3700::Instrument::model_is(&model)
-        || self::tti::Instrument::model_is(&model)
-        || self::versatest::Instrument::model_is(&model)
+    let Ok(model) = model.as_ref().parse::<Model>() else {
+        return false;
+    };
+    DriverRegistry::global().supports(&InstrumentInfo {
+        model,
+        ..InstrumentInfo::default()
+    })
 }
 
-/// Connect to an instrument given the instrument's connection information and authentication
-/// info.
+/// Connect to an instrument given the instrument's connection information, authentication
+/// info and the [`ConnectOptions`] to use while connecting.
 ///
 /// # Errors
 /// Errors may occur when getting the model (for LAN-based connections, this would
 /// likely be a [`reqwest`] error from trying to fetch the LXI Identification page).
 /// IO errors or parsing errors are possible. There could be errors in establishing the
-/// connection as well.
-#[instrument(skip(conn, auth))]
+/// connection as well. If no registered [`Driver`](driver::Driver) supports the
+/// instrument, [`InstrumentError::UnsupportedInstrument`] is returned.
+#[instrument(skip(conn, auth, options))]
 pub fn connect_to(
     conn: &ConnectionInfo,
     auth: Authentication,
+    options: &ConnectOptions,
 ) -> Result<Box<dyn Instrument>, InstrumentError> {
     trace!("Connecting to {conn}");
-    let model: Model = conn.get_model()?;
-
-    Ok(if model.is_2600() {
-        Box::new(ki2600::Instrument::connect(conn, auth)?)
-    } else if model.is_3700_70x() {
-        Box::new(ki3700::Instrument::connect(conn, auth)?)
-    } else if model.is_tti() {
-        Box::new(tti::Instrument::connect(conn, auth)?)
-    } else if model.is_mp() {
-        Box::new(versatest::Instrument::connect(conn, auth)?)
-    } else {
-        trace!("Unable to determine instrument model, defaulting to MP5000 series connection procedure.");
-        Box::new(versatest::Instrument::connect(conn, auth)?)
-    })
+    let info = conn.get_info_with_options(options)?;
+    let driver = DriverRegistry::global().find(&info)?;
+    driver.connect(conn, auth, options, info)
+}
+
+/// Connect to the instrument with the given profile name from the default
+/// [`ProfileStore`](crate::profile::ProfileStore).
+///
+/// # Errors
+/// Errors may occur when loading the profile store, if there is no profile with the
+/// given name, or when connecting to the instrument (see [`connect_to`]).
+pub fn connect_to_profile(name: &str) -> Result<Box<dyn Instrument>, InstrumentError> {
+    crate::profile::ProfileStore::load_default()?.connect(name)
 }
 
 /// Connect to an instrument given the instrument's connection information and authentication
@@ -59,26 +69,16 @@ pub fn connect_to(
 /// Errors may occur when getting the model (for LAN-based connections, this would
 /// likely be a [`reqwest`] error from trying to fetch the LXI Identification page).
 /// IO errors or parsing errors are possible. There could be errors in establishing the
-/// connection as well.
+/// connection as well. If no registered [`Driver`](driver::Driver) supports the
+/// instrument, [`InstrumentError::UnsupportedInstrument`] is returned.
 pub fn connect_protocol(
     conn: &ConnectionInfo,
     proto: Protocol,
     auth: Authentication,
 ) -> Result<Box<dyn Instrument>, InstrumentError> {
-    let model: Model = conn.get_model()?;
-
-    Ok(if model.is_2600() {
-        Box::new(ki2600::Instrument::new(proto, auth))
-    } else if model.is_3700_70x() {
-        Box::new(ki3700::Instrument::new(proto, auth))
-    } else if model.is_tti() {
-        Box::new(tti::Instrument::new(proto, auth))
-    } else if model.is_mp() {
-        Box::new(versatest::Instrument::new(proto, auth))
-    } else {
-        trace!("Unable to determine instrument model, defaulting to MP5000 series connection procedure.");
-        Box::new(versatest::Instrument::new(proto, auth))
-    })
+    let info = conn.get_info()?;
+    let driver = DriverRegistry::global().find(&info)?;
+    Ok(driver.create(proto, auth, info))
 }
 
 //impl TryFrom<Protocol> for Box<dyn Instrument> {
@@ -157,7 +157,7 @@ const TEKTRONIX_VID: u16 = 0x0699u16;
 const KEITHLEY_VID: u16 = 0x05E6u16;
 
 #[repr(u16)]
-#[derive(Clone, Debug, PartialEq, Hash, Eq, Default, serde::Serialize)]
+#[derive(Clone, Debug, PartialEq, Hash, Eq, Default, serde::Serialize, serde::Deserialize)]
 pub enum Vendor {
     #[serde(rename = "TEKTRONIX")]
     Tektronix = TEKTRONIX_VID,
@@ -214,54 +214,41 @@ impl FromStr for Vendor {
 /// This macro is intended only to define instrument models. It should not be used for
 /// any other purpose and must not be made public in any way.
 ///
-/// This macro generates an enum with a list of variants that represent all the models
-/// this library supports (as well as an `Other` variant which allows the use of unsupported
-/// instruments on a best-guess basis). It also implements [`std::str::FromStr`],
-/// [`std::fmt::Display`], and [`std::convert::From<u16>`] (for USB product IDs) for
-/// each model provided.
+/// This macro generates an enum with a variant for each model that this library
+/// refers to by name (as well as an `Other` variant for every other model). It
+/// implements [`std::fmt::Display`] and the conversion from the name of each model.
+/// Aliases, USB product IDs and families come from the
+/// [`ModelRegistry`](crate::model::registry::ModelRegistry), so supporting a new
+/// model only needs an entry in its table.
 ///
 /// # Syntax
 /// The syntax is as follows:
 /// ```ignore
 /// define_models! {
-///     <VIS> enum Models[<ERROR-TYPE>, <FAMILY-TYPE>] {
-///         <VARIANT-NAME>[<u16-PID] <- [<STRING-REPRESENTATION>,<OTHER-STRING-REPS>...] #<FAMILY-GROUP,
-///         <VARIANT-NAME> <- [<STRING-REPRESENTATION>,<OTHER-STRING-REPS>...] #<FAMILY-GROUP,
+///     <VIS> enum Models {
+///         <VARIANT-NAME> <- <STRING-REPRESENTATION>,
 ///     }
 /// }
 /// ```
 /// # Example
 ///
 /// ```ignore
-/// # #[derive(Debug, Clone, thiserror::Error)]
-/// # pub enum CarError {
-/// #   #[error("Error: {0}")]
-/// #   A(String),
-/// # }
-///
-/// pub enum Family {
-///     A,
-///     B,
-///     C,
-/// }
-///
 /// define_models! {
-///     pub enum Cars[CarError, Family] {
-///         ModelT <- ["Ford Model T", "Model T", "Mr. T"] #Family::A,
-///         _300[0x0300] <- ["Chrysler 300", "300"] #Family::B,
-///         M3[0x0003] <- ["BMW M3"] #Family::C,
+///     pub enum Cars {
+///         ModelT <- "Ford Model T",
+///         _300 <- "Chrysler 300",
 ///     }
 /// }
 /// ```
 macro_rules! define_models {
     (
-        pub enum $name:ident[$error:path, $fam:path] {
+        pub enum $name:ident {
             $(
-                $variant:ident$([$pid:expr])? <- [$string_val:literal$(,$alt_string_val:expr),* $(,)?] #$family:path
+                $variant:ident <- $string_val:literal
             ),+ $(,)?
         }
     ) => {
-        #[derive(Debug, Hash, PartialEq, Eq, Clone, serde::Serialize)]
+        #[derive(Debug, Hash, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
         pub enum $name {
             $(#[serde(rename=$string_val)]$variant),+,
             #[serde(rename="Unknown Model")]
@@ -283,141 +270,163 @@ macro_rules! define_models {
             }
         }
 
-        impl std::str::FromStr for $name {
-            type Err = $error;
-            fn from_str(val: &str) -> Result<Self, Self::Err> {
-                match val {
-                    $(
-                        $string_val$( | $alt_string_val)* => Ok($name::$variant)
-                    ),+,
-                    _ => Ok($name::Other(val.to_string()))
-                }
-            }
-        }
-
         impl $name {
-            #[must_use]
-            pub const fn family(&self) -> Option<$fam> {
-                match self {
+            /// The names of the models that have a variant.
+            #[cfg(test)]
+            const NAMES: &[&str] = &[$($string_val),+];
+
+            /// The model with the given name, as a variant if it has one.
+            fn named(val: &str) -> Self {
+                match val {
                     $(
-                        $name::$variant => Some($family)
+                        $string_val => $name::$variant
                     ),+,
-                    $name::Other(_) => None,
+                    _ => $name::Other(val.to_string()),
                 }
             }
-
-            #[must_use]
-            pub fn from_pid(pid: u16) -> Self {
-                $(
-                    define_models!(@match_pid $name, $variant, pid, $($pid)?);
-                )*
-                $name::Other(format!("PID: {pid:#X}"))
-            }
         }
     };
-    (@match_pid $name:ident, $variant:ident, $input:ident, $pid:literal) => {
-        if $input == $pid {
-            return $name::$variant;
-        }
-    };
-    (@match_pid $name:ident, $variant:ident, $input:ident, ) => { };
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum Family {
+    #[serde(rename = "2600")]
     _26xx,
+    #[serde(rename = "3700")]
     _3700,
+    #[serde(rename = "TTI")]
     Tti,
+    #[serde(rename = "Modular Platform")]
     ModularPlatform,
 }
 
 define_models! {
-    pub enum Model[InstrumentError, Family] {
+    pub enum Model {
         //2600
-        _2601A <- ["2601"] #Family::_26xx,
-        _2602A <- ["2602"] #Family::_26xx,
-        _2611A <- ["2611"] #Family::_26xx,
-        _2612A <- ["2612"] #Family::_26xx,
-        _2635A <- ["2635"] #Family::_26xx,
-        _2636A <- ["2636"] #Family::_26xx,
-        _2651A <- ["2651"] #Family::_26xx,
-        _2657A <- ["2657"] #Family::_26xx,
-        _2601B[0x2601] <- ["2601B"] #Family::_26xx,
-        _2601BPulse[0x26F1] <- ["2601B-PULSE"] #Family::_26xx,
-        _2602B[0x2602] <- ["2602B"] #Family::_26xx,
-        _2606B[0x2606] <- ["2606B"] #Family::_26xx,
-        _2611B[0x2611] <- ["2611B"] #Family::_26xx,
-        _2612B[0x2612] <- ["2612B"] #Family::_26xx,
-        _2635B[0x2635] <- ["2635B"] #Family::_26xx,
-        _2636B[0x2636] <- ["2636B"] #Family::_26xx,
-        _2604B[0x2604] <- ["2604B"] #Family::_26xx,
-        _2614B[0x2614] <- ["2614B"] #Family::_26xx,
-        _2634B[0x2634] <- ["2634B"] #Family::_26xx,
-        _2601BL <- ["2601B-L"] #Family::_26xx,
-        _2602BL <- ["2602B-L"] #Family::_26xx,
-        _2611BL <- ["2611B-L"] #Family::_26xx,
-        _2612BL <- ["2612B-L"] #Family::_26xx,
-        _2635BL <- ["2635B-L"] #Family::_26xx,
-        _2636BL <- ["2636B-L"] #Family::_26xx,
-        _2604BL <- ["2604B-L"] #Family::_26xx,
-        _2614BL <- ["2614B-L"] #Family::_26xx,
-        _2634BL <- ["2634B-L"] #Family::_26xx,
+        _2601A <- "2601",
+        _2602A <- "2602",
+        _2611A <- "2611",
+        _2612A <- "2612",
+        _2635A <- "2635",
+        _2636A <- "2636",
+        _2651A <- "2651",
+        _2657A <- "2657",
+        _2601B <- "2601B",
+        _2601BPulse <- "2601B-PULSE",
+        _2602B <- "2602B",
+        _2606B <- "2606B",
+        _2611B <- "2611B",
+        _2612B <- "2612B",
+        _2635B <- "2635B",
+        _2636B <- "2636B",
+        _2604B <- "2604B",
+        _2614B <- "2614B",
+        _2634B <- "2634B",
+        _2601BL <- "2601B-L",
+        _2602BL <- "2602B-L",
+        _2611BL <- "2611B-L",
+        _2612BL <- "2612B-L",
+        _2635BL <- "2635B-L",
+        _2636BL <- "2636B-L",
+        _2604BL <- "2604B-L",
+        _2614BL <- "2614B-L",
+        _2634BL <- "2634B-L",
 
         // 3706 or 70xB
-        _3706 <- ["3706"] #Family::_3700,
-        _3706S <- ["3706-S"] #Family::_3700,
-        _3706SNFP <- ["3706-SNFP"] #Family::_3700,
-        _3706NFP <- ["3706-NFP"] #Family::_3700,
-        _3706A[0x3706] <- ["3706A"] #Family::_3700,
-        _3706AS <- ["3706A-S"] #Family::_3700,
-        _3706ASNFP <- ["3706A-SNFP"] #Family::_3700,
-        _3706ANFP <- ["3706A-NFP"] #Family::_3700,
-        _707B[0x707B] <- ["707B"] #Family::_3700,
-        _708B[0x708B] <- ["708B"] #Family::_3700,
-        _5880Sru <- ["5880_SRU"] #Family::_3700,
-        _5881Sru <- ["5881_SRU"] #Family::_3700,
+        _3706 <- "3706",
+        _3706S <- "3706-S",
+        _3706SNFP <- "3706-SNFP",
+        _3706NFP <- "3706-NFP",
+        _3706A <- "3706A",
+        _3706AS <- "3706A-S",
+        _3706ASNFP <- "3706A-SNFP",
+        _3706ANFP <- "3706A-NFP",
+        _707B <- "707B",
+        _708B <- "708B",
+        _5880Sru <- "5880_SRU",
+        _5881Sru <- "5881_SRU",
 
         // TTI
-        _2450[0x2450] <- ["2450"] #Family::Tti,
-        _2470[0x2470] <- ["2470"] #Family::Tti,
-        _2460[0x2460] <- ["2460"] #Family::Tti,
-        _2461[0x2461] <- ["2461"] #Family::Tti,
-        _2461Sys[0x1642] <- ["2461-SYS"] #Family::Tti,
-        DMM7500[0x7500] <- ["DMM7500"] #Family::Tti,
-        DMM7510[0x7510] <- ["DMM7510"] #Family::Tti,
-        DMM7512[0x7512] <- ["DMM7512"] #Family::Tti,
-        DMM6500[0x6500] <- ["DMM6500"] #Family::Tti,
-        DAQ6510[0x6510] <- ["DAQ6510"] #Family::Tti,
+        _2450 <- "2450",
+        _2470 <- "2470",
+        _2460 <- "2460",
+        _2461 <- "2461",
+        _2461Sys <- "2461-SYS",
+        DMM7500 <- "DMM7500",
+        DMM7510 <- "DMM7510",
+        DMM7512 <- "DMM7512",
+        DMM6500 <- "DMM6500",
+        DAQ6510 <- "DAQ6510",
 
         // Modular Platform
-        MP5103[0x5103] <- ["MP5103"] #Family::ModularPlatform,
-        TSPop <- ["TSPop", "TSP"] #Family::ModularPlatform,
+        MP5103 <- "MP5103",
+        TSPop <- "TSPop",
+    }
+}
+
+impl FromStr for Model {
+    type Err = InstrumentError;
+
+    /// Parse a model name or alias. Names that aren't in the [`ModelRegistry`]
+    /// parse as [`Model::Other`], which has no [`Family`].
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        Ok(Self::resolve(&ModelRegistry::global(), val))
     }
 }
 
 impl Model {
+    /// The model with the given name or alias in `registry`.
+    fn resolve(registry: &ModelRegistry, val: &str) -> Self {
+        registry
+            .find(val)
+            .map_or_else(|| Self::named(val), |entry| Self::named(&entry.model))
+    }
+
+    /// The model with the given USB product ID in `registry`.
+    fn resolve_pid(registry: &ModelRegistry, pid: u16) -> Self {
+        registry.find_pid(pid).map_or_else(
+            || Self::Other(format!("PID: {pid:#X}")),
+            |entry| Self::named(&entry.model),
+        )
+    }
+
+    /// The model with the given USB product ID, from the [`ModelRegistry`].
+    #[must_use]
+    pub fn from_pid(pid: u16) -> Self {
+        Self::resolve_pid(&ModelRegistry::global(), pid)
+    }
+
+    /// The driver family of the model, from the [`ModelRegistry`], or [`None`] if
+    /// the model isn't in it.
+    #[must_use]
+    pub fn family(&self) -> Option<Family> {
+        ModelRegistry::global()
+            .find(&self.to_string())
+            .map(|entry| entry.family)
+    }
+
     #[must_use]
-    pub const fn is_tti(&self) -> bool {
+    pub fn is_tti(&self) -> bool {
         matches!(self.family(), Some(Family::Tti))
     }
 
     #[must_use]
-    pub const fn is_mp(&self) -> bool {
+    pub fn is_mp(&self) -> bool {
         matches!(self.family(), Some(Family::ModularPlatform))
     }
 
     #[must_use]
-    pub const fn is_3700_70x(&self) -> bool {
+    pub fn is_3700_70x(&self) -> bool {
         matches!(self.family(), Some(Family::_3700))
     }
 
     #[must_use]
-    pub const fn is_2600(&self) -> bool {
+    pub fn is_2600(&self) -> bool {
         matches!(self.family(), Some(Family::_26xx))
     }
 
     #[must_use]
-    pub const fn is_other(&self) -> bool {
+    pub fn is_other(&self) -> bool {
         self.family().is_none()
     }
 }
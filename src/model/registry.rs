@@ -0,0 +1,303 @@
+//! A data-driven table of instrument models.
+//!
+//! The table maps the name, aliases and USB product ID of each model to its driver
+//! [`Family`]; it is the only place this mapping is defined. The built-in table is
+//! embedded in the crate from `models.json` and can be extended with user
+//! overrides, either from [`ModelRegistry::default_path`] (loaded automatically) or
+//! at runtime with [`ModelRegistry::load_overrides`] and
+//! [`ModelRegistry::register_global`]. This allows newly released instruments to be
+//! mapped onto an existing driver [`Family`] without a new release of this crate;
+//! models without a variant parse as [`Model::Other`](super::Model::Other).
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{OnceLock, PoisonError, RwLock, RwLockReadGuard},
+};
+
+use crate::{error::Result, model::Family, profile::CONFIG_DIR_NAME, InstrumentError};
+
+/// The name of the user model override file in the configuration directory.
+const MODELS_FILE_NAME: &str = "models.json";
+
+/// The built-in model table.
+const EMBEDDED_MODELS: &str = include_str!("models.json");
+
+static GLOBAL: OnceLock<RwLock<ModelRegistry>> = OnceLock::new();
+
+/// A single model in the [`ModelRegistry`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ModelEntry {
+    /// The model name as reported by the instrument (e.g. `2450`).
+    pub model: String,
+    /// Other names that should be treated as this model.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// The USB product ID of the model, written as a hexadecimal string (e.g.
+    /// `"0x2450"`).
+    #[serde(default, with = "hex_pid", skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u16>,
+    /// The driver family used to communicate with the model.
+    pub family: Family,
+}
+
+impl ModelEntry {
+    /// Whether the given name is the name or an alias of this model.
+    #[must_use]
+    pub fn is_named(&self, name: &str) -> bool {
+        self.model == name || self.aliases.iter().any(|a| a == name)
+    }
+}
+
+/// A table of [`ModelEntry`]s. Entries added later take precedence over earlier ones
+/// with the same name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ModelRegistry {
+    entries: Vec<ModelEntry>,
+}
+
+impl ModelRegistry {
+    /// The built-in model table.
+    ///
+    /// # Panics
+    /// Panics if the embedded table is malformed, which is checked by the unit tests.
+    #[must_use]
+    pub fn embedded() -> Self {
+        Self::from_json(EMBEDDED_MODELS).expect("embedded model table should be valid")
+    }
+
+    /// Parse a model table from a JSON array of [`ModelEntry`]s.
+    ///
+    /// # Errors
+    /// An error is returned if the JSON is not a valid model table.
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// The location of the user model overrides in the user configuration directory.
+    ///
+    /// # Errors
+    /// An error is returned if the user configuration directory cannot be determined.
+    pub fn default_path() -> Result<PathBuf> {
+        let Some(dir) = dirs::config_dir() else {
+            return Err(InstrumentError::Other(
+                "unable to determine the user configuration directory".to_string(),
+            ));
+        };
+        Ok(dir.join(CONFIG_DIR_NAME).join(MODELS_FILE_NAME))
+    }
+
+    /// Load a model table from the given path. A missing file is treated as an empty
+    /// table.
+    ///
+    /// # Errors
+    /// An error is returned if the file exists but cannot be read or parsed.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let contents = match std::fs::read_to_string(path.as_ref()) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        Self::from_json(&contents)
+    }
+
+    /// All entries in the table, in the order they were added.
+    #[must_use]
+    pub fn entries(&self) -> &[ModelEntry] {
+        &self.entries
+    }
+
+    /// Add the given entry, replacing any existing entry with the same model name.
+    pub fn register(&mut self, entry: ModelEntry) {
+        self.entries.retain(|e| e.model != entry.model);
+        self.entries.push(entry);
+    }
+
+    /// Add all the entries of `overrides` to this table, replacing existing entries
+    /// with the same model name.
+    pub fn merge(&mut self, overrides: Self) {
+        for entry in overrides.entries {
+            self.register(entry);
+        }
+    }
+
+    /// Find the entry with the given name or alias.
+    #[must_use]
+    pub fn find(&self, name: &str) -> Option<&ModelEntry> {
+        self.entries.iter().rev().find(|e| e.is_named(name))
+    }
+
+    /// Find the entry with the given USB product ID.
+    #[must_use]
+    pub fn find_pid(&self, pid: u16) -> Option<&ModelEntry> {
+        self.entries.iter().rev().find(|e| e.pid == Some(pid))
+    }
+
+    /// The process-wide registry: the embedded table merged with the overrides at
+    /// [`ModelRegistry::default_path`], if any. Unit tests only use the embedded
+    /// table so they don't depend on the user configuration.
+    pub fn global() -> RwLockReadGuard<'static, Self> {
+        global().read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Merge the model table at the given path into the process-wide registry.
+    ///
+    /// # Errors
+    /// See [`ModelRegistry::load`].
+    pub fn load_overrides(path: impl AsRef<Path>) -> Result<()> {
+        let overrides = Self::load(path)?;
+        global()
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .merge(overrides);
+        Ok(())
+    }
+
+    /// Add the given entry to the process-wide registry.
+    pub fn register_global(entry: ModelEntry) {
+        global()
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .register(entry);
+    }
+}
+
+fn global() -> &'static RwLock<ModelRegistry> {
+    GLOBAL.get_or_init(|| {
+        let mut registry = ModelRegistry::embedded();
+        registry.merge(user_models());
+        RwLock::new(registry)
+    })
+}
+
+#[cfg(not(test))]
+fn user_models() -> ModelRegistry {
+    use tracing::{trace, warn};
+
+    match ModelRegistry::default_path().and_then(ModelRegistry::load) {
+        Ok(overrides) => {
+            trace!("loaded {} model overrides", overrides.entries.len());
+            overrides
+        }
+        Err(e) => {
+            warn!("unable to load model overrides: {e}");
+            ModelRegistry::default()
+        }
+    }
+}
+
+#[cfg(test)]
+fn user_models() -> ModelRegistry {
+    ModelRegistry::default()
+}
+
+mod hex_pid {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    #[allow(clippy::ref_option, clippy::trivially_copy_pass_by_ref)] // The signature is required by `serde(with)`
+    pub fn serialize<S: Serializer>(pid: &Option<u16>, s: S) -> Result<S::Ok, S::Error> {
+        match pid {
+            Some(pid) => s.serialize_str(&format!("{pid:#06X}")),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u16>, D::Error> {
+        let Some(pid) = Option::<String>::deserialize(d)? else {
+            return Ok(None);
+        };
+        let digits = pid
+            .strip_prefix("0x")
+            .or_else(|| pid.strip_prefix("0X"))
+            .unwrap_or(&pid);
+        u16::from_str_radix(digits, 16)
+            .map(Some)
+            .map_err(|_| D::Error::custom(format!("'{pid}' is not a valid USB product ID")))
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use crate::model::{Family, Model};
+
+    use super::{ModelEntry, ModelRegistry};
+
+    #[test]
+    fn overrides_take_precedence() {
+        let mut registry = ModelRegistry::from_json(r#"[{"model": "2450-NEXT", "family": "TTI"}]"#)
+            .expect("models should parse");
+        let overrides = ModelRegistry::from_json(
+            r#"[
+                {"model": "2450-NEXT", "family": "Modular Platform"},
+                {"model": "2651C", "aliases": ["2651C-X"], "pid": "0x265C", "family": "2600"}
+            ]"#,
+        )
+        .expect("overrides should parse");
+        registry.merge(overrides);
+
+        assert_eq!(
+            registry.find("2450-NEXT").map(|e| e.family),
+            Some(Family::ModularPlatform)
+        );
+        assert_eq!(
+            registry.find("2651C-X").map(|e| e.model.as_str()),
+            Some("2651C")
+        );
+        assert_eq!(
+            registry.find_pid(0x265C).map(|e| e.model.as_str()),
+            Some("2651C")
+        );
+        assert!(
+            ModelRegistry::from_json(r#"[{"model": "x", "pid": "0xZZ", "family": "TTI"}]"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn embedded_table_covers_named_models() {
+        let registry = ModelRegistry::embedded();
+        for name in Model::NAMES {
+            assert!(
+                registry.find(name).is_some(),
+                "{name} should be in the embedded table"
+            );
+            assert!(!Model::resolve(&registry, name).is_other());
+        }
+        assert_eq!(Model::resolve(&registry, "TSP"), Model::TSPop);
+    }
+
+    #[test]
+    fn other_resolves_through_overrides() {
+        let mut registry = ModelRegistry::embedded();
+        assert!(Model::resolve(&registry, "9000-TEST").is_other());
+        assert_eq!(registry.find("9000-TEST").map(|e| e.family), None);
+
+        registry.register(ModelEntry {
+            model: "9000-TEST".to_string(),
+            aliases: vec!["9000-T".to_string()],
+            pid: Some(0x9000),
+            family: Family::Tti,
+        });
+
+        let model = Model::resolve(&registry, "9000-T");
+        assert_eq!(model, Model::Other("9000-TEST".to_string()));
+        assert_eq!(Model::resolve_pid(&registry, 0x9000), model);
+        assert_eq!(
+            registry.find("9000-TEST").map(|e| e.family),
+            Some(Family::Tti)
+        );
+
+        registry.register(ModelEntry {
+            model: "2450".to_string(),
+            aliases: vec!["2450-TEST".to_string()],
+            pid: None,
+            family: Family::ModularPlatform,
+        });
+        assert_eq!(Model::resolve(&registry, "2450-TEST"), Model::_2450);
+        assert_eq!(
+            registry.find("2450").map(|e| e.family),
+            Some(Family::ModularPlatform)
+        );
+    }
+}
@@ -1,35 +1,91 @@
 use std::{
-    io::{BufRead, ErrorKind, Read, Write},
-    time::Duration,
+    io::{BufRead, Read, Write},
+    time::{Duration, Instant},
 };
 
 use bytes::Buf;
 use indicatif::{ProgressBar, ProgressStyle};
-use tracing::{self, error, trace};
+use tracing::{self, trace};
 
 use crate::{
     instrument::{
         self,
         authenticate::Authentication,
+        buffer::BufferDialect,
         info::InstrumentInfo,
         language::{CmdLanguage, Language},
-        Abort, Info, Login, Reset, Script,
+        password::PasswordDialect,
+        status::StatusByte,
+        status::StatusModel,
+        Abort, AuthProvider, Buffers, Info, Login, PasswordAdmin, Reset, Script, ServiceRequest,
+        Status, TspLink,
     },
-    interface::{connection_addr::ConnectionInfo, NonBlock},
-    model::Model,
-    protocol::Protocol,
+    interface::{
+        connect_options::ConnectOptions, connection_addr::ConnectionInfo, Interface, NonBlock,
+    },
+    model::{
+        capabilities::{Capabilities, TspLinkDialect},
+        driver, Family, Model,
+    },
+    protocol::{self, Clear, DeviceClear, Protocol},
     Flash, InstrumentError,
 };
 
+use scpi::Scpi;
+
+pub mod scpi;
+
 pub struct Instrument {
     info: Option<InstrumentInfo>,
     protocol: Protocol,
     auth: Authentication,
+    language: Option<CmdLanguage>,
+    connection: Option<(ConnectionInfo, ConnectOptions)>,
+}
+
+/// A session with a TTI instrument in the language it is currently using.
+pub enum Session<'a> {
+    /// The instrument is using TSP, so all of the [`Instrument`] traits can be used.
+    Tsp(&'a mut Instrument),
+    /// The instrument is using SCPI.
+    Scpi(Scpi<'a, Instrument>),
+}
+
+/// How to apply a language change with [`Instrument::switch_language`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reboot {
+    /// Don't reboot the instrument. The change is applied at the next power cycle.
+    No,
+    /// Wait up to the given time for the instrument to be power cycled by someone
+    /// else.
+    Wait(Duration),
+    /// Send the given command to reboot the instrument, then wait up to the given
+    /// time for it to come back.
+    Command {
+        /// The command that reboots the instrument in its current language.
+        command: String,
+        /// How long to wait for the instrument to come back.
+        timeout: Duration,
+    },
+}
+
+/// The result of [`Instrument::switch_language`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LanguageSwitch {
+    /// The instrument was already using the requested language.
+    Unchanged,
+    /// The language will change when the instrument is next power cycled.
+    RebootRequired,
+    /// The instrument was rebooted and is now using the requested language.
+    Switched,
 }
 
+/// The time to wait between attempts to reconnect after a reboot.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+
 impl Instrument {
     #[must_use]
-    pub const fn is(info: &InstrumentInfo) -> bool {
+    pub fn is(info: &InstrumentInfo) -> bool {
         info.model.is_tti()
     }
 
@@ -41,20 +97,28 @@ impl Instrument {
         model.is_tti()
     }
 
-    /// Connect to an instrument with the given connection information.
+    /// Connect to an instrument with the given connection information and
+    /// [`ConnectOptions`].
     ///
     /// # Errors
     /// There can be issues in creating the protocol from the given [`ConnectionInfo`].
     /// There can also be issues in getting the instrument information using
     /// [`ConnectionInfo::get_info()`].
-    #[tracing::instrument(skip(conn, auth))]
-    pub fn connect(conn: &ConnectionInfo, auth: Authentication) -> Result<Self, InstrumentError> {
-        let protocol = Protocol::connect(conn)?;
+    #[tracing::instrument(skip(conn, auth, options))]
+    pub fn connect(
+        conn: &ConnectionInfo,
+        auth: Authentication,
+        options: &ConnectOptions,
+    ) -> Result<Self, InstrumentError> {
+        let mut protocol = Protocol::connect(conn, options)?;
+        protocol.set_write_chunk_size(Capabilities::for_family(Some(&Family::Tti)).usb_write_chunk);
 
         Ok(Self {
             info: None,
             protocol,
             auth,
+            language: None,
+            connection: Some((conn.clone(), options.clone())),
         })
     }
 
@@ -64,13 +128,181 @@ impl Instrument {
             info: None,
             protocol,
             auth,
+            language: None,
+            connection: None,
         }
     }
 
     pub fn add_info(&mut self, info: InstrumentInfo) -> &Self {
+        self.protocol
+            .set_write_chunk_size(info.model.capabilities().usb_write_chunk);
         self.info = Some(info);
         self
     }
+
+    /// The [`Capabilities`] of this instrument. If the exact model isn't known yet,
+    /// the capabilities common to the family are used.
+    #[must_use]
+    pub fn capabilities(&self) -> Capabilities {
+        self.info.as_ref().map_or_else(
+            || Capabilities::for_family(Some(&Family::Tti)),
+            |info| info.model.capabilities(),
+        )
+    }
+
+    /// Start a [`Session`] in the language the instrument is using, as reported by
+    /// [`Language::get_language`].
+    ///
+    /// # Errors
+    /// See [`Language::get_language`].
+    pub fn session(&mut self) -> Result<Session<'_>, InstrumentError> {
+        Ok(match self.get_language()? {
+            CmdLanguage::Tsp => Session::Tsp(self),
+            CmdLanguage::Scpi => Session::Scpi(Scpi::new(self)),
+        })
+    }
+
+    /// Wait for pending operations to complete, with `*OPC?` if the instrument is
+    /// known to be in SCPI mode or `waitcomplete()` otherwise.
+    fn wait_complete(&mut self) -> Result<(), InstrumentError> {
+        if self.language == Some(CmdLanguage::Scpi) {
+            Scpi::new(self).wait_complete()
+        } else {
+            instrument::wait_complete(self, instrument::COMPLETE_TIMEOUT)
+        }
+    }
+
+    /// Change the language of the instrument, which only takes effect after it is
+    /// rebooted, and optionally wait for the reboot.
+    ///
+    /// When rebooting, the current session is closed, since the instrument only
+    /// allows one, and the instrument is reconnected with the [`ConnectionInfo`] and
+    /// [`ConnectOptions`] it was connected with. The new language is confirmed with
+    /// [`Language::get_language`]. Log in again afterwards if the instrument is
+    /// password protected.
+    ///
+    /// # Errors
+    /// See [`Language::get_language`] and [`Language::change_language`].
+    /// [`InstrumentError::Other`] if the instrument must be reconnected but wasn't
+    /// created with [`Instrument::connect`], or [`InstrumentError::ConnectionError`]
+    /// if the instrument isn't using the new language before the timeout.
+    pub fn switch_language(
+        &mut self,
+        lang: CmdLanguage,
+        reboot: Reboot,
+    ) -> Result<LanguageSwitch, InstrumentError> {
+        if self.get_language()? == lang {
+            return Ok(LanguageSwitch::Unchanged);
+        }
+        let (command, timeout) = match reboot {
+            Reboot::No => {
+                self.change_language(lang)?;
+                return Ok(LanguageSwitch::RebootRequired);
+            }
+            Reboot::Wait(timeout) => (None, timeout),
+            Reboot::Command { command, timeout } => (Some(command), timeout),
+        };
+        let Some((conn, options)) = self.connection.clone() else {
+            return Err(InstrumentError::Other(
+                "the instrument can only be reconnected if it was connected with a ConnectionInfo"
+                    .to_string(),
+            ));
+        };
+        self.change_language(lang)?;
+        if let Some(command) = command {
+            self.write_all(format!("{command}\n").as_bytes())?;
+            self.flush()?;
+        }
+        // The instrument only allows one session, so close this one before
+        // reconnecting.
+        self.protocol = Protocol::new(Disconnected);
+        self.language = None;
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            std::thread::sleep(RECONNECT_INTERVAL);
+            match Protocol::connect(&conn, &options) {
+                Ok(mut protocol) => {
+                    protocol.set_write_chunk_size(self.capabilities().usb_write_chunk);
+                    self.protocol = protocol;
+                    match self.get_language() {
+                        Ok(l) if l == lang => return Ok(LanguageSwitch::Switched),
+                        Ok(l) => trace!("instrument is still using {l}"),
+                        Err(e) => trace!("unable to read language after reconnecting: {e}"),
+                    }
+                    self.protocol = Protocol::new(Disconnected);
+                    self.language = None;
+                }
+                Err(e) => trace!("unable to reconnect: {e}"),
+            }
+        }
+        Err(InstrumentError::ConnectionError {
+            details: format!("the instrument was not using {lang} after {timeout:?}"),
+        })
+    }
+}
+
+/// The interface of an [`Instrument`] while it is disconnected for a reboot.
+struct Disconnected;
+
+impl Read for Disconnected {
+    fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::NotConnected.into())
+    }
+}
+
+impl Write for Disconnected {
+    fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::NotConnected.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl NonBlock for Disconnected {
+    fn set_nonblocking(&mut self, _: bool) -> crate::error::Result<()> {
+        Ok(())
+    }
+}
+
+impl Interface for Disconnected {}
+
+/// The [`driver::Driver`] for this family.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Driver;
+
+impl driver::Driver for Driver {
+    fn name(&self) -> &'static str {
+        "tti"
+    }
+
+    fn matches(&self, info: &InstrumentInfo) -> bool {
+        Instrument::is(info)
+    }
+
+    fn connect(
+        &self,
+        conn: &ConnectionInfo,
+        auth: Authentication,
+        options: &ConnectOptions,
+        info: InstrumentInfo,
+    ) -> crate::error::Result<Box<dyn instrument::Instrument>> {
+        let mut instrument = Instrument::connect(conn, auth, options)?;
+        instrument.add_info(info);
+        Ok(Box::new(instrument))
+    }
+
+    fn create(
+        &self,
+        protocol: Protocol,
+        auth: Authentication,
+        info: InstrumentInfo,
+    ) -> Box<dyn instrument::Instrument> {
+        let mut instrument = Instrument::new(protocol, auth);
+        instrument.add_info(info);
+        Box::new(instrument)
+    }
 }
 
 //Implement device_interface::Interface since it is a subset of instrument::Instrument trait.
@@ -81,27 +313,18 @@ impl Info for Instrument {}
 impl Language for Instrument {
     fn get_language(&mut self) -> Result<CmdLanguage, InstrumentError> {
         self.write_all(b"*LANG?\n")?;
-        for _i in 0..5 {
-            std::thread::sleep(Duration::from_millis(100));
-            let mut lang: Vec<u8> = vec![0; 256];
-            let read_size = match self.read(&mut lang) {
-                Ok(read_size) => read_size,
-                Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                    continue;
-                }
-                Err(e) => {
-                    error!("{e:?}: {e}");
-                    return Err(e.into());
-                }
-            };
-            let lang = &lang[0..read_size];
-            let lang = std::str::from_utf8(lang).unwrap_or("").trim();
-
+        let lang = instrument::read_response(self, instrument::login::CHECK_TIMEOUT, |lang| {
             if lang.contains("TSP") {
-                return Ok(CmdLanguage::Tsp);
+                Some(CmdLanguage::Tsp)
             } else if lang.contains("SCPI") {
-                return Ok(CmdLanguage::Scpi);
+                Some(CmdLanguage::Scpi)
+            } else {
+                None
             }
+        })?;
+        if let Some(lang) = lang {
+            self.language = Some(lang);
+            return Ok(lang);
         }
         Err(InstrumentError::InformationRetrievalError {
             details: ("could not read language of the instrument").to_string(),
@@ -109,6 +332,11 @@ impl Language for Instrument {
     }
 
     fn change_language(&mut self, lang: CmdLanguage) -> Result<(), InstrumentError> {
+        if !self.capabilities().languages.contains(&lang) {
+            return Err(InstrumentError::Other(format!(
+                "{lang} is not supported by this instrument"
+            )));
+        }
         self.write_all(format!("*LANG {lang}\n").as_bytes())?;
         Ok(())
     }
@@ -117,35 +345,20 @@ impl Language for Instrument {
 impl Login for Instrument {
     fn check_login(&mut self) -> crate::error::Result<instrument::State> {
         self.write_all(b"*TST?\n")?;
-        for _i in 0..5 {
-            std::thread::sleep(Duration::from_millis(100));
-            let mut resp: Vec<u8> = vec![0; 256];
-            let read_size = match self.read(&mut resp) {
-                Ok(read_size) => read_size,
-                Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                    continue;
-                }
-                Err(e) => {
-                    error!("{e:?}: {e}");
-                    return Err(e.into());
-                }
-            };
-            let resp = &resp[..read_size];
-
-            let resp = std::str::from_utf8(resp).unwrap_or("").trim();
-
+        let state = instrument::read_response(self, instrument::login::CHECK_TIMEOUT, |resp| {
             if resp.contains("SUCCESS: Logged in") || resp.contains('0') {
-                return Ok(instrument::State::NotNeeded);
-            }
-
-            if resp.contains("FAILURE") {
+                Some(instrument::State::NotNeeded)
+            } else if resp.contains("FAILURE") {
                 if resp.contains("LOGOUT") {
-                    return Ok(instrument::State::LogoutNeeded);
+                    Some(instrument::State::LogoutNeeded)
+                } else {
+                    Some(instrument::State::Needed)
                 }
-                return Ok(instrument::State::Needed);
+            } else {
+                None
             }
-        }
-        Ok(instrument::State::Needed)
+        })?;
+        Ok(state.unwrap_or(instrument::State::Needed))
     }
 
     fn login(&mut self) -> crate::error::Result<()> {
@@ -156,16 +369,25 @@ impl Login for Instrument {
             return Err(InstrumentError::InterfaceLoginErr);
         }
 
+        if self.auth.needs_identity() {
+            let info = match self.info.clone() {
+                Some(info) => info,
+                None => self.info()?,
+            };
+            self.auth.on_identified(&info)?;
+        }
+
         if let Some(password) = self.auth.read_password()? {
-            self.write_all(format!("login {password}\n").as_bytes())?;
+            let command = self.capabilities().login.command("", &password);
+            self.write_all(format!("{command}\n").as_bytes())?;
         }
 
         inst_login_state = self.check_login()?;
         if instrument::State::NotNeeded == inst_login_state {
             let info = self.info()?;
-            self.auth
-                .save_credential(&info.model, &info.serial_number)?;
+            self.auth.on_success(&info)?;
         } else if instrument::State::Needed == inst_login_state {
+            self.auth.on_rejected()?;
             return Err(InstrumentError::LoginRejected);
         }
 
@@ -173,7 +395,79 @@ impl Login for Instrument {
     }
 }
 
-impl Script for Instrument {}
+impl Script for Instrument {
+    fn max_script_name_len(&self) -> usize {
+        self.capabilities().max_script_name_len
+    }
+
+    fn write_script(
+        &mut self,
+        name: &[u8],
+        script: &[u8],
+        save_script: bool,
+        run_script: bool,
+    ) -> crate::error::Result<()> {
+        if self.language == Some(CmdLanguage::Scpi) {
+            return Err(InstrumentError::Other(
+                "scripts can only be written while the instrument is using TSP".to_string(),
+            ));
+        }
+        instrument::script::write_script(self, name, script, save_script, run_script)
+    }
+}
+
+impl Buffers for Instrument {
+    fn buffer_dialect(&self) -> Option<BufferDialect> {
+        Some(BufferDialect::Tti)
+    }
+}
+
+impl Status for Instrument {
+    fn status_model(&self) -> StatusModel {
+        self.capabilities().status
+    }
+}
+
+impl ServiceRequest for Instrument {
+    fn wait_for_srq(&mut self, timeout: Duration) -> crate::error::Result<StatusByte> {
+        self.protocol.wait_for_srq(timeout)
+    }
+}
+
+impl Clear for Instrument {
+    type Error = InstrumentError;
+
+    /// Clear the instrument, logging in again if the session was reconnected.
+    fn device_clear(&mut self) -> crate::error::Result<DeviceClear> {
+        let cleared = self.protocol.device_clear()?;
+        if cleared == DeviceClear::Reconnected {
+            self.language = None;
+            self.login()?;
+            self.get_language()?;
+        }
+        Ok(cleared)
+    }
+}
+
+impl PasswordAdmin for Instrument {
+    fn password_dialect(&self) -> Option<PasswordDialect> {
+        self.capabilities().password
+    }
+}
+
+impl TspLink for Instrument {
+    fn tsplink_initialize_command(&self) -> Option<&'static str> {
+        self.capabilities()
+            .tsplink
+            .map(TspLinkDialect::initialize_command)
+    }
+
+    fn node_revision_attribute(&self) -> &'static str {
+        self.capabilities()
+            .tsplink
+            .map_or("version", TspLinkDialect::revision_attribute)
+    }
+}
 
 impl Flash for Instrument {
     fn flash_firmware(&mut self, image: &[u8], _: Option<u16>) -> crate::error::Result<()> {
@@ -198,7 +492,8 @@ impl Flash for Instrument {
 
         self.write_all(b"localnode.prompts=localnode.DISABLE\n")?;
         self.write_all(b"if ki.upgrade ~= nil and ki.upgrade.noacklater ~= nil then ki.upgrade.noacklater() end\n")?;
-        self.write_all(b"prevflash\n")?;
+        let begin = self.capabilities().firmware.begin_command();
+        self.write_all(format!("{begin}\n").as_bytes())?;
 
         self.write_all(image.fill_buf().unwrap())?;
 
@@ -231,11 +526,10 @@ impl Read for Instrument {
 impl Write for Instrument {
     #[tracing::instrument(skip(self, buf))]
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        if String::from_utf8_lossy(buf).contains("login") {
-            trace!("writing to instrument: 'login ****'");
-        } else {
-            trace!("writing to instrument: '{}'", String::from_utf8_lossy(buf));
-        }
+        trace!(
+            "writing to instrument: '{}'",
+            protocol::redact(&String::from_utf8_lossy(buf))
+        );
         self.protocol.write(buf)
     }
 
@@ -263,7 +557,9 @@ impl Drop for Instrument {
     #[tracing::instrument(skip(self))]
     fn drop(&mut self) {
         trace!("calling tti drop...");
-        let _ = self.reset();
+        // Don't wait for the reset to complete since the connection may be gone
+        let _ = self.write_all(b"abort\n");
+        let _ = self.write_all(b"*RST\n");
         let _ = self.write_all(b"logout\n");
         std::thread::sleep(Duration::from_millis(100));
     }
@@ -272,11 +568,9 @@ impl Drop for Instrument {
 impl Reset for Instrument {
     fn reset(&mut self) -> crate::error::Result<()> {
         trace!("calling tti reset...");
-        let _ = self.write_all(b"abort\n");
-        std::thread::sleep(Duration::from_millis(100));
-        let _ = self.write_all(b"*RST\n");
-        std::thread::sleep(Duration::from_millis(100));
-        Ok(())
+        self.write_all(b"abort\n")?;
+        self.write_all(b"*RST\n")?;
+        self.wait_complete()
     }
 }
 
@@ -284,16 +578,17 @@ impl Abort for Instrument {
     #[tracing::instrument(skip(self))]
     fn abort(&mut self) -> crate::error::Result<()> {
         trace!("Calling tti abort...");
-        let _ = self.write_all(b"abort\n");
-        std::thread::sleep(Duration::from_millis(100));
-        Ok(())
+        self.write_all(b"abort\n")?;
+        self.wait_complete()
     }
 }
 #[cfg(test)]
 mod unit {
     use std::{
         assert_matches::assert_matches,
-        io::{BufRead, Read, Write},
+        io::{BufRead, BufReader, Read, Write},
+        net::{IpAddr, TcpListener},
+        time::Duration,
     };
 
     use bytes::Buf;
@@ -301,12 +596,15 @@ mod unit {
 
     use crate::{
         instrument::{self, authenticate::Authentication, info::Info, Language, Login, Script},
-        interface::{self, NonBlock},
+        interface::{
+            self, connect_options::ConnectOptions, connection_addr::ConnectionInfo, host::HostAddr,
+            NonBlock,
+        },
         protocol::{self, raw::Raw},
         test_util, Flash, InstrumentError,
     };
 
-    use super::Instrument;
+    use super::{Instrument, LanguageSwitch, Reboot};
 
     #[test]
     fn login_not_needed() {
@@ -944,6 +1242,116 @@ mod unit {
             .is_ok());
     }
 
+    /// An interface that answers `*LANG?` with `language` and accepts any write.
+    fn language_interface(language: &'static [u8]) -> MockInterface {
+        let mut interface = MockInterface::new();
+        interface.expect_flush().times(..).returning(|| Ok(()));
+        interface
+            .expect_write()
+            .times(..)
+            .returning(|buf: &[u8]| Ok(buf.len()));
+        interface
+            .expect_read()
+            .times(..)
+            .returning(move |buf: &mut [u8]| {
+                let n = language.len().min(buf.len());
+                buf[..n].copy_from_slice(&language[..n]);
+                Ok(n)
+            });
+        interface
+    }
+
+    #[test]
+    fn switch_language_without_reboot() {
+        let mut instrument = Instrument::new(
+            protocol::Protocol::Raw(Raw::new(language_interface(b"TSP\n"))),
+            Authentication::NoAuth,
+        );
+
+        assert_eq!(
+            instrument
+                .switch_language(instrument::CmdLanguage::Tsp, Reboot::No)
+                .unwrap(),
+            LanguageSwitch::Unchanged
+        );
+        assert_eq!(
+            instrument
+                .switch_language(instrument::CmdLanguage::Scpi, Reboot::No)
+                .unwrap(),
+            LanguageSwitch::RebootRequired
+        );
+    }
+
+    #[test]
+    fn switch_language_reboot_needs_connection() {
+        let mut instrument = Instrument::new(
+            protocol::Protocol::Raw(Raw::new(language_interface(b"TSP\n"))),
+            Authentication::NoAuth,
+        );
+
+        assert_matches!(
+            instrument.switch_language(
+                instrument::CmdLanguage::Scpi,
+                Reboot::Wait(std::time::Duration::from_secs(1))
+            ),
+            Err(InstrumentError::Other(_))
+        );
+    }
+
+    #[test]
+    fn switch_language_reconnects_and_confirms() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conn = ConnectionInfo::Lan {
+            addr: HostAddr::new(
+                IpAddr::from([127, 0, 0, 1]),
+                listener.local_addr().unwrap().port(),
+            ),
+        };
+        let instrument_side = std::thread::spawn(move || {
+            let (first, _) = listener.accept().unwrap();
+            first
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            let mut first = BufReader::new(first);
+            let mut commands = Vec::new();
+            let mut line = String::new();
+            while first.read_line(&mut line).unwrap() > 0 {
+                if line.trim() == "*LANG?" {
+                    first.get_mut().write_all(b"TSP\n").unwrap();
+                }
+                commands.push(line.trim().to_string());
+                line.clear();
+            }
+
+            // The first session is closed, so the instrument accepts another.
+            let (second, _) = listener.accept().unwrap();
+            let mut second = BufReader::new(second);
+            second.read_line(&mut line).unwrap();
+            assert_eq!(line.trim(), "*LANG?");
+            second.get_mut().write_all(b"SCPI\n").unwrap();
+            commands
+        });
+
+        let mut instrument =
+            Instrument::connect(&conn, Authentication::NoAuth, &ConnectOptions::default()).unwrap();
+        assert_eq!(
+            instrument
+                .switch_language(
+                    instrument::CmdLanguage::Scpi,
+                    Reboot::Command {
+                        command: "reboot".to_string(),
+                        timeout: Duration::from_secs(10),
+                    },
+                )
+                .unwrap(),
+            LanguageSwitch::Switched
+        );
+        assert_eq!(
+            instrument_side.join().unwrap(),
+            ["*LANG?", "*LANG SCPI", "reboot"]
+        );
+    }
+
     #[test]
     fn write_script() {
         let optional_writes: Vec<Vec<u8>> = vec![
@@ -961,6 +1369,7 @@ mod unit {
         ];
         let mut interface = MockInterface::new();
         let mut seq = Sequence::new();
+        test_util::expect_wait_complete!(interface);
 
         //Accept any number of flushes
         interface.expect_flush().times(..).returning(|| Ok(()));
@@ -1009,6 +1418,7 @@ mod unit {
         ];
         let mut interface = MockInterface::new();
         let mut seq = Sequence::new();
+        test_util::expect_wait_complete!(interface);
 
         //Accept any number of flushes
         interface.expect_flush().times(..).returning(|| Ok(()));
@@ -1057,6 +1467,7 @@ mod unit {
         ];
         let mut interface = MockInterface::new();
         let mut seq = Sequence::new();
+        test_util::expect_wait_complete!(interface);
 
         //Accept any number of flushes
         interface.expect_flush().times(..).returning(|| Ok(()));
@@ -1107,6 +1518,7 @@ mod unit {
         ];
         let mut interface = MockInterface::new();
         let mut seq = Sequence::new();
+        test_util::expect_wait_complete!(interface);
 
         //Accept any number of flushes
         interface.expect_flush().times(..).returning(|| Ok(()));
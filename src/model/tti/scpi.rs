@@ -0,0 +1,297 @@
+//! Communicating with TTI instruments that are in SCPI mode.
+//!
+//! The rest of the crate assumes that instruments are using TSP. Once a TTI
+//! instrument has been switched to SCPI with
+//! [`Language::change_language`](crate::instrument::Language::change_language), use
+//! [`Instrument::session`](super::Instrument::session) to get an [`Scpi`] session
+//! instead.
+
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+    time::Duration,
+};
+
+use crate::{
+    error::Result,
+    instrument::{
+        buffer::{decode, ByteOrder, DataFormat},
+        read_until,
+    },
+    protocol::block::BlockReader,
+    InstrumentError,
+};
+
+/// The number of attempts to make when reading a response.
+const READ_ATTEMPTS: usize = 100;
+
+/// The delay between attempts when reading a response.
+const READ_DELAY: Duration = Duration::from_millis(20);
+
+/// The most errors that will be read from the error queue at once. This is larger
+/// than the error queue of any TTI instrument.
+const MAX_ERRORS: usize = 1000;
+
+/// An entry from the SCPI error queue, as read with `:SYSTem:ERRor?`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ScpiError {
+    /// The error code. `0` means there was no error.
+    pub code: i32,
+    /// The description of the error.
+    pub message: String,
+}
+
+impl ScpiError {
+    /// Parse a `<code>,"<message>"` error queue entry.
+    ///
+    /// # Errors
+    /// [`InstrumentError::InformationRetrievalError`] if the entry is malformed.
+    pub fn parse(entry: &str) -> Result<Self> {
+        let entry = entry.trim();
+        let Some((code, message)) = entry.split_once(',') else {
+            return Err(InstrumentError::InformationRetrievalError {
+                details: format!("unable to parse SCPI error '{entry}'"),
+            });
+        };
+        let Ok(code) = code.trim().parse() else {
+            return Err(InstrumentError::InformationRetrievalError {
+                details: format!("unable to parse SCPI error code from '{entry}'"),
+            });
+        };
+        Ok(Self {
+            code,
+            message: message.trim().trim_matches('"').to_string(),
+        })
+    }
+}
+
+impl Display for ScpiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+/// A SCPI session with an instrument.
+#[derive(Debug)]
+pub struct Scpi<'a, T: Read + Write + ?Sized> {
+    rw: &'a mut T,
+}
+
+impl<'a, T: Read + Write + ?Sized> Scpi<'a, T> {
+    /// Start a SCPI session. This doesn't check that the instrument is in SCPI mode.
+    pub const fn new(rw: &'a mut T) -> Self {
+        Self { rw }
+    }
+
+    /// Send a command.
+    ///
+    /// # Errors
+    /// Any IO errors.
+    pub fn write(&mut self, command: &str) -> Result<()> {
+        self.rw.write_all(format!("{command}\n").as_bytes())?;
+        self.rw.flush()?;
+        Ok(())
+    }
+
+    /// Send a query and return the response, without the terminator.
+    ///
+    /// # Errors
+    /// Any IO errors, or [`InstrumentError::InformationRetrievalError`] if no
+    /// response was received.
+    pub fn query(&mut self, query: &str) -> Result<String> {
+        self.write(query)?;
+        match read_until(self.rw, &["\n".to_string()], READ_ATTEMPTS, READ_DELAY) {
+            Ok(response) => Ok(response),
+            Err(InstrumentError::Other(_)) => Err(InstrumentError::InformationRetrievalError {
+                details: format!("no response to '{query}'"),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read and remove every entry in the error queue.
+    ///
+    /// # Errors
+    /// See [`Scpi::query`] and [`ScpiError::parse`].
+    pub fn errors(&mut self) -> Result<Vec<ScpiError>> {
+        let mut errors = Vec::new();
+        for _ in 0..MAX_ERRORS {
+            let error = ScpiError::parse(&self.query(":SYST:ERR?")?)?;
+            if error.code == 0 {
+                break;
+            }
+            errors.push(error);
+        }
+        Ok(errors)
+    }
+
+    /// Read the error queue and return an error with every entry, if there were any.
+    ///
+    /// # Errors
+    /// [`InstrumentError::InstrumentError`] if the error queue wasn't empty, or see
+    /// [`Scpi::errors`].
+    pub fn check_errors(&mut self) -> Result<()> {
+        let errors = self.errors()?;
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(InstrumentError::InstrumentError {
+            error: errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; "),
+        })
+    }
+
+    /// Wait for all pending operations to complete with `*OPC?`.
+    ///
+    /// # Errors
+    /// See [`Scpi::query`].
+    pub fn wait_complete(&mut self) -> Result<()> {
+        let response = self.query("*OPC?")?;
+        if response.trim() == "1" {
+            Ok(())
+        } else {
+            Err(InstrumentError::InformationRetrievalError {
+                details: format!("unexpected response to *OPC?: '{response}'"),
+            })
+        }
+    }
+
+    /// Discard anything in the output queue. This is the SCPI equivalent of
+    /// [`clear_output_queue`](crate::instrument::clear_output_queue).
+    ///
+    /// # Errors
+    /// See [`Scpi::query`].
+    pub fn clear_output_queue(&mut self) -> Result<()> {
+        self.write("*OPC?")?;
+        match read_until(self.rw, &["1\n".to_string()], READ_ATTEMPTS, READ_DELAY) {
+            Ok(_) => Ok(()),
+            Err(InstrumentError::Other(_)) => Err(InstrumentError::Other(
+                "unable to clear instrument output queue".to_string(),
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read readings `start` through `end` (starting at 1) of the given reading
+    /// buffer with `:TRACe:DATA?`, transferred as a binary block.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if the range is empty, any IO errors, or
+    /// [`InstrumentError::BlockError`] if the block couldn't be read.
+    pub fn trace_data(&mut self, buffer: &str, start: usize, end: usize) -> Result<Vec<f64>> {
+        let count = end
+            .checked_sub(start)
+            .and_then(|c| c.checked_add(1))
+            .filter(|_| start > 0)
+            .ok_or_else(|| {
+                InstrumentError::Other(format!("{start} to {end} is not a valid reading range"))
+            })?;
+        let format = DataFormat::Real64;
+        let expected = count.saturating_mul(format.width());
+        self.write(&format!(
+            ":FORM:DATA REAL;:FORM:BORD SWAP;:TRAC:DATA? {start}, {end}, \"{}\"",
+            buffer.replace('"', "\"\"")
+        ))?;
+        let block = BlockReader::new(&mut *self.rw)
+            .with_max_size(expected)
+            .read_to_vec();
+        self.write(":FORM:DATA ASC")?;
+        decode(&block?, format, ByteOrder::Little)
+    }
+
+    /// Send a query that responds with a block, and return the data of the block.
+    ///
+    /// # Errors
+    /// Any IO errors, or [`InstrumentError::BlockError`] if the block couldn't be
+    /// read or is larger than `max_size`.
+    pub fn query_block(&mut self, query: &str, max_size: usize) -> Result<Vec<u8>> {
+        self.write(query)?;
+        BlockReader::new(&mut *self.rw)
+            .with_max_size(max_size)
+            .read_to_vec()
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use crate::{test_util::FakeInstrument, InstrumentError};
+
+    use super::{Scpi, ScpiError};
+
+    #[test]
+    fn query_and_opc() {
+        let mut fake = FakeInstrument::new()
+            .with_replies(&[b"KEITHLEY INSTRUMENTS,MODEL DMM6500,1,1.7\n", b"1\n"]);
+        let mut scpi = Scpi::new(&mut fake);
+
+        assert_eq!(
+            scpi.query("*IDN?").unwrap(),
+            "KEITHLEY INSTRUMENTS,MODEL DMM6500,1,1.7"
+        );
+        scpi.wait_complete().unwrap();
+        scpi.write(":SENS:FUNC \"VOLT:DC\"").unwrap();
+
+        assert_eq!(
+            fake.commands(),
+            ["*IDN?", "*OPC?", ":SENS:FUNC \"VOLT:DC\""]
+        );
+    }
+
+    #[test]
+    fn drain_error_queue() {
+        let mut fake = FakeInstrument::new().with_replies(&[
+            b"-113,\"Undefined header\"\n",
+            b"-222,\"Data out of range\"\n",
+            b"0,\"No error\"\n",
+            b"0,\"No error\"\n",
+        ]);
+        let mut scpi = Scpi::new(&mut fake);
+
+        let errors = scpi.errors().unwrap();
+        assert_eq!(
+            errors,
+            [
+                ScpiError {
+                    code: -113,
+                    message: "Undefined header".to_string()
+                },
+                ScpiError {
+                    code: -222,
+                    message: "Data out of range".to_string()
+                },
+            ]
+        );
+        assert!(scpi.check_errors().is_ok());
+        assert!(ScpiError::parse("No error").is_err());
+    }
+
+    #[test]
+    fn trace_data_block() {
+        let mut block = b"#224".to_vec();
+        for v in [1.0f64, -0.5, 2.5e-3] {
+            block.extend(v.to_le_bytes());
+        }
+        block.push(b'\n');
+        let mut fake = FakeInstrument::new().with_replies(&[&block]);
+        let mut scpi = Scpi::new(&mut fake);
+
+        assert_eq!(
+            scpi.trace_data("defbuffer1", 1, 3).unwrap(),
+            [1.0, -0.5, 2.5e-3]
+        );
+        assert!(matches!(
+            scpi.trace_data("defbuffer1", 3, 1),
+            Err(InstrumentError::Other(_))
+        ));
+        assert_eq!(
+            fake.commands(),
+            [
+                ":FORM:DATA REAL;:FORM:BORD SWAP;:TRAC:DATA? 1, 3, \"defbuffer1\"",
+                ":FORM:DATA ASC"
+            ]
+        );
+    }
+}
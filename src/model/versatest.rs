@@ -1,20 +1,25 @@
 use std::{
-    io::{ErrorKind, Read, Write},
+    io::{Read, Write},
     time::Duration,
 };
 
 use crate::{
     instrument::{
         self, authenticate::Authentication, clear_output_queue, info::InstrumentInfo,
-        language::Language, read_until, Abort, Info, Login, Reset, Script,
+        language::Language, password::PasswordDialect, read_until, status::StatusByte,
+        status::StatusModel, Abort, AuthProvider, Buffers, Info, Login, PasswordAdmin, Reset,
+        Script, ServiceRequest, Status, TspLink,
     },
-    interface::{connection_addr::ConnectionInfo, NonBlock},
-    model::Model,
-    protocol::Protocol,
+    interface::{connect_options::ConnectOptions, connection_addr::ConnectionInfo, NonBlock},
+    model::{
+        capabilities::{Capabilities, TspLinkDialect},
+        driver, Family, Model,
+    },
+    protocol::{self, Clear, DeviceClear, Protocol},
     Flash, InstrumentError,
 };
 use indicatif::{ProgressBar, ProgressStyle};
-use tracing::{error, trace};
+use tracing::trace;
 
 pub struct Instrument {
     info: Option<InstrumentInfo>,
@@ -25,7 +30,7 @@ pub struct Instrument {
 
 impl Instrument {
     #[must_use]
-    pub const fn is(info: &InstrumentInfo) -> bool {
+    pub fn is(info: &InstrumentInfo) -> bool {
         info.model.is_mp()
     }
 
@@ -37,15 +42,23 @@ impl Instrument {
         model.is_mp()
     }
 
-    /// Connect to an instrument with the given connection information.
+    /// Connect to an instrument with the given connection information and
+    /// [`ConnectOptions`].
     ///
     /// # Errors
     /// There can be issues in creating the protocol from the given [`ConnectionInfo`].
     /// There can also be issues in getting the instrument information using
     /// [`ConnectionInfo::get_info()`].
-    #[tracing::instrument(skip(conn, auth))]
-    pub fn connect(conn: &ConnectionInfo, auth: Authentication) -> Result<Self, InstrumentError> {
-        let protocol = Protocol::connect(conn)?;
+    #[tracing::instrument(skip(conn, auth, options))]
+    pub fn connect(
+        conn: &ConnectionInfo,
+        auth: Authentication,
+        options: &ConnectOptions,
+    ) -> Result<Self, InstrumentError> {
+        let mut protocol = Protocol::connect(conn, options)?;
+        protocol.set_write_chunk_size(
+            Capabilities::for_family(Some(&Family::ModularPlatform)).usb_write_chunk,
+        );
 
         Ok(Self {
             info: None,
@@ -66,9 +79,58 @@ impl Instrument {
     }
 
     pub fn add_info(&mut self, info: InstrumentInfo) -> &Self {
+        self.protocol
+            .set_write_chunk_size(info.model.capabilities().usb_write_chunk);
         self.info = Some(info);
         self
     }
+
+    /// The [`Capabilities`] of this instrument. If the exact model isn't known yet,
+    /// the capabilities common to the family are used.
+    #[must_use]
+    pub fn capabilities(&self) -> Capabilities {
+        self.info.as_ref().map_or_else(
+            || Capabilities::for_family(Some(&Family::ModularPlatform)),
+            |info| info.model.capabilities(),
+        )
+    }
+}
+
+/// The [`driver::Driver`] for this family.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Driver;
+
+impl driver::Driver for Driver {
+    fn name(&self) -> &'static str {
+        "versatest"
+    }
+
+    fn matches(&self, info: &InstrumentInfo) -> bool {
+        Instrument::is(info)
+    }
+
+    fn connect(
+        &self,
+        conn: &ConnectionInfo,
+        auth: Authentication,
+        options: &ConnectOptions,
+        info: InstrumentInfo,
+    ) -> crate::error::Result<Box<dyn instrument::Instrument>> {
+        let mut instrument = Instrument::connect(conn, auth, options)?;
+        instrument.add_info(info);
+        Ok(Box::new(instrument))
+    }
+
+    fn create(
+        &self,
+        protocol: Protocol,
+        auth: Authentication,
+        info: InstrumentInfo,
+    ) -> Box<dyn instrument::Instrument> {
+        let mut instrument = Instrument::new(protocol, auth);
+        instrument.add_info(info);
+        Box::new(instrument)
+    }
 }
 
 //Implement device_interface::Interface since it is a subset of instrument::Instrument trait.
@@ -81,32 +143,18 @@ impl Language for Instrument {}
 impl Login for Instrument {
     fn check_login(&mut self) -> crate::error::Result<instrument::State> {
         self.write_all(b"print('unlocked')\n")?;
-        for _i in 0..5 {
-            std::thread::sleep(Duration::from_millis(100));
-            let mut resp: Vec<u8> = vec![0; 256];
-            let read_size = match self.read(&mut resp) {
-                Ok(read_size) => read_size,
-                Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                    continue;
-                }
-                Err(e) => {
-                    error!("{e:?}: {e}");
-                    return Err(e.into());
-                }
-            };
-            let resp = &resp[..read_size];
-
-            let resp = std::str::from_utf8(resp).unwrap_or("").trim();
-
+        let state = instrument::read_response(self, instrument::login::CHECK_TIMEOUT, |resp| {
             if resp.contains("unlocked") {
-                return Ok(instrument::State::NotNeeded);
+                Some(instrument::State::NotNeeded)
+            } else if resp.contains("Port in use") {
+                Some(instrument::State::LogoutNeeded)
+            } else if resp.contains("FAILURE") {
+                Some(instrument::State::Needed)
+            } else {
+                None
             }
-            if resp.contains("Port in use") {
-                return Ok(instrument::State::LogoutNeeded);
-            }
-        }
-
-        Ok(instrument::State::Needed)
+        })?;
+        Ok(state.unwrap_or(instrument::State::Needed))
     }
 
     fn login(&mut self) -> crate::error::Result<()> {
@@ -117,23 +165,26 @@ impl Login for Instrument {
             return Err(InstrumentError::InterfaceLoginErr);
         }
 
+        if self.auth.needs_identity() {
+            let info = match self.info.clone() {
+                Some(info) => info,
+                None => self.info()?,
+            };
+            self.auth.on_identified(&info)?;
+        }
+
         let username = self.auth.read_username()?.unwrap_or_default();
         if let Some(password) = self.auth.read_password()? {
-            self.write_all(
-                format!(
-                    "login {username}{}{password}\n",
-                    if username.is_empty() { "" } else { " " }
-                )
-                .as_bytes(),
-            )?;
+            let command = self.capabilities().login.command(&username, &password);
+            self.write_all(format!("{command}\n").as_bytes())?;
         }
 
         inst_login_state = self.check_login()?;
         if instrument::State::NotNeeded == inst_login_state {
             let info = self.info()?;
-            self.auth
-                .save_credential(&info.model, &info.serial_number)?;
+            self.auth.on_success(&info)?;
         } else if instrument::State::Needed == inst_login_state {
+            self.auth.on_rejected()?;
             return Err(InstrumentError::LoginRejected);
         }
 
@@ -141,7 +192,58 @@ impl Login for Instrument {
     }
 }
 
-impl Script for Instrument {}
+impl Script for Instrument {
+    fn max_script_name_len(&self) -> usize {
+        self.capabilities().max_script_name_len
+    }
+}
+
+impl Buffers for Instrument {}
+
+impl Status for Instrument {
+    fn status_model(&self) -> StatusModel {
+        self.capabilities().status
+    }
+}
+
+impl ServiceRequest for Instrument {
+    fn wait_for_srq(&mut self, timeout: Duration) -> crate::error::Result<StatusByte> {
+        self.protocol.wait_for_srq(timeout)
+    }
+}
+
+impl Clear for Instrument {
+    type Error = InstrumentError;
+
+    /// Clear the instrument, logging in again if the session was reconnected.
+    fn device_clear(&mut self) -> crate::error::Result<DeviceClear> {
+        let cleared = self.protocol.device_clear()?;
+        if cleared == DeviceClear::Reconnected {
+            self.login()?;
+        }
+        Ok(cleared)
+    }
+}
+
+impl PasswordAdmin for Instrument {
+    fn password_dialect(&self) -> Option<PasswordDialect> {
+        self.capabilities().password
+    }
+}
+
+impl TspLink for Instrument {
+    fn tsplink_initialize_command(&self) -> Option<&'static str> {
+        self.capabilities()
+            .tsplink
+            .map(TspLinkDialect::initialize_command)
+    }
+
+    fn node_revision_attribute(&self) -> &'static str {
+        self.capabilities()
+            .tsplink
+            .map_or("version", TspLinkDialect::revision_attribute)
+    }
+}
 
 impl Read for Instrument {
     #[tracing::instrument(skip(self, buf))]
@@ -159,7 +261,10 @@ impl Read for Instrument {
 impl Write for Instrument {
     #[tracing::instrument(skip(self, buf))]
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        trace!("writing to instrument: '{}'", String::from_utf8_lossy(buf));
+        trace!(
+            "writing to instrument: '{}'",
+            protocol::redact(&String::from_utf8_lossy(buf))
+        );
         self.protocol.write(buf)
     }
 
@@ -206,7 +311,8 @@ impl Flash for Instrument {
         self.write_all(b"localnode.prompts=0\n")?;
         //let image = image.reader();
         //let start_time = Instant::now();
-        self.write_all(b"flash\n")?;
+        let begin = self.capabilities().firmware.begin_command();
+        self.write_all(format!("{begin}\n").as_bytes())?;
 
         self.write_all(image)?;
 
@@ -358,7 +464,9 @@ impl Drop for Instrument {
             trace!("FW flash in progress. Skipping instrument reset.");
             return;
         }
-        let _ = self.reset();
+        // Don't wait for the reset to complete since the connection may be gone
+        let _ = self.write_all(b"*RST\n");
+        let _ = self.write_all(b"abort\n");
     }
 }
 
@@ -366,11 +474,9 @@ impl Reset for Instrument {
     #[tracing::instrument(skip(self))]
     fn reset(&mut self) -> crate::error::Result<()> {
         trace!("calling versatest reset...");
-        let _ = self.write_all(b"*RST\n");
-        std::thread::sleep(Duration::from_millis(100));
-        let _ = self.write_all(b"abort\n");
-        std::thread::sleep(Duration::from_millis(100));
-        Ok(())
+        self.write_all(b"*RST\n")?;
+        self.write_all(b"abort\n")?;
+        crate::instrument::wait_complete(self, crate::instrument::COMPLETE_TIMEOUT)
     }
 }
 
@@ -378,9 +484,8 @@ impl Abort for Instrument {
     #[tracing::instrument(skip(self))]
     fn abort(&mut self) -> crate::error::Result<()> {
         trace!("Calling MPS abort...");
-        let _ = self.write_all(b"abort\n");
-        std::thread::sleep(Duration::from_millis(100));
-        Ok(())
+        self.write_all(b"abort\n")?;
+        crate::instrument::wait_complete(self, crate::instrument::COMPLETE_TIMEOUT)
     }
 }
 #[cfg(test)]
@@ -400,7 +505,7 @@ mod unit {
     use crate::{
         instrument::{self, info::Info, Login, Script},
         interface::{self, NonBlock},
-        InstrumentError,
+        test_util, InstrumentError,
     };
 
     use super::Instrument;
@@ -498,7 +603,7 @@ mod unit {
 
         interface
             .expect_read()
-            .times(5)
+            .times(1)
             .in_sequence(&mut seq)
             .withf(|buf: &[u8]| buf.len() >= 8)
             .returning(|buf: &mut [u8]| {
@@ -523,7 +628,7 @@ mod unit {
 
         interface
             .expect_read()
-            .times(5)
+            .times(1)
             .in_sequence(&mut seq)
             .withf(|buf: &[u8]| buf.len() >= 8)
             .returning(|buf: &mut [u8]| {
@@ -667,7 +772,7 @@ mod unit {
 
         interface
             .expect_read()
-            .times(5)
+            .times(1)
             .in_sequence(&mut seq)
             .withf(|buf: &[u8]| buf.len() >= 8)
             .returning(|buf: &mut [u8]| {
@@ -692,7 +797,7 @@ mod unit {
 
         interface
             .expect_read()
-            .times(5)
+            .times(1)
             .in_sequence(&mut seq)
             .withf(|buf: &[u8]| buf.len() >= 8)
             .returning(|buf: &mut [u8]| {
@@ -724,7 +829,7 @@ mod unit {
 
         interface
             .expect_read()
-            .times(5)
+            .times(1)
             .in_sequence(&mut seq)
             .withf(|buf: &[u8]| buf.len() >= 8)
             .returning(|buf: &mut [u8]| {
@@ -749,7 +854,7 @@ mod unit {
 
         interface
             .expect_read()
-            .times(5)
+            .times(1)
             .in_sequence(&mut seq)
             .withf(|buf: &[u8]| buf.len() >= 8)
             .returning(|buf: &mut [u8]| {
@@ -851,6 +956,7 @@ mod unit {
     fn write_script() {
         let mut interface = MockInterface::new();
         let mut seq = Sequence::new();
+        test_util::expect_wait_complete!(interface);
 
         interface
             .expect_write()
@@ -919,6 +1025,7 @@ mod unit {
     fn write_script_run() {
         let mut interface = MockInterface::new();
         let mut seq = Sequence::new();
+        test_util::expect_wait_complete!(interface);
 
         interface
             .expect_write()
@@ -994,6 +1101,7 @@ mod unit {
     fn write_script_save() {
         let mut interface = MockInterface::new();
         let mut seq = Sequence::new();
+        test_util::expect_wait_complete!(interface);
 
         interface
             .expect_write()
@@ -1069,6 +1177,7 @@ mod unit {
     fn write_script_save_run() {
         let mut interface = MockInterface::new();
         let mut seq = Sequence::new();
+        test_util::expect_wait_complete!(interface);
 
         interface
             .expect_write()
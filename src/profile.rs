@@ -0,0 +1,334 @@
+//! Named connection profiles for instruments.
+//!
+//! A [`ProfileStore`] maps friendly names to a [`Profile`] that holds everything
+//! needed to connect to an instrument: the preferred [`ConnectionInfo`], any fallback
+//! connections to try if that fails, the [`ConnectOptions`] to use, and how to
+//! authenticate. Profiles can also be tagged so that groups of instruments can be
+//! found together.
+//!
+//! The store is saved as JSON in the user configuration directory (see
+//! [`ProfileStore::default_path`]):
+//!
+//! ```json
+//! {
+//!   "profiles": {
+//!     "bench-smu": {
+//!       "connection": "192.168.0.10:5025",
+//!       "fallbacks": ["USB0::0x05E6::0x2461::04331961::INSTR"],
+//!       "tags": ["bench", "smu"],
+//!       "options": { "connect_timeout": 2000 },
+//!       "auth": { "keyring": { "id": "2461#04331961" } }
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! Plaintext credentials are never stored in a profile. See
+//! [`Authentication`](crate::instrument::authenticate::Authentication) for the
+//! supported forms.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use tracing::{instrument, trace, warn};
+
+use crate::{
+    error::Result,
+    instrument::{authenticate::Authentication, Instrument},
+    interface::{connect_options::ConnectOptions, connection_addr::ConnectionInfo},
+    model::connect_to,
+    InstrumentError,
+};
+
+/// The name of the directory, inside the user configuration directory, in which the
+/// profile store is kept.
+pub(crate) const CONFIG_DIR_NAME: &str = "tsp-toolkit";
+
+/// The name of the profile store file.
+const PROFILE_FILE_NAME: &str = "profiles.json";
+
+/// Everything needed to connect to a single instrument.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Profile {
+    /// The preferred connection to the instrument.
+    pub connection: ConnectionInfo,
+    /// Other connections to the same instrument that should be tried, in order, if
+    /// the preferred connection fails.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallbacks: Vec<ConnectionInfo>,
+    /// Free-form tags used to group instruments.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// The options to use when connecting to the instrument.
+    #[serde(default)]
+    pub options: ConnectOptions,
+    /// How to authenticate with the instrument.
+    #[serde(default = "no_auth")]
+    pub auth: Authentication,
+}
+
+const fn no_auth() -> Authentication {
+    Authentication::NoAuth
+}
+
+impl Profile {
+    /// Create a new profile for the given connection with default options, no
+    /// fallbacks, no tags and no authentication.
+    #[must_use]
+    pub fn new(connection: ConnectionInfo) -> Self {
+        Self {
+            connection,
+            fallbacks: Vec::new(),
+            tags: Vec::new(),
+            options: ConnectOptions::default(),
+            auth: Authentication::NoAuth,
+        }
+    }
+
+    /// All the connections of this profile, in the order they should be tried.
+    pub fn connections(&self) -> impl Iterator<Item = &ConnectionInfo> {
+        std::iter::once(&self.connection).chain(self.fallbacks.iter())
+    }
+
+    /// Check whether this profile has the given tag.
+    #[must_use]
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// Connect to the instrument described by this profile, trying each of the
+    /// [`Profile::connections`] in order.
+    ///
+    /// # Errors
+    /// If no connection succeeds, the error from the last connection attempt is
+    /// returned.
+    #[instrument(skip(self))]
+    pub fn connect(&self) -> Result<Box<dyn Instrument>> {
+        let mut last_error = None;
+        for conn in self.connections() {
+            trace!("Trying connection {conn}");
+            match connect_to(conn, self.auth.clone(), &self.options) {
+                Ok(instrument) => return Ok(instrument),
+                Err(e) => {
+                    warn!("Unable to connect to {conn}: {e}");
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(
+            last_error.unwrap_or_else(|| InstrumentError::ConnectionError {
+                details: "profile did not contain any connections".to_string(),
+            }),
+        )
+    }
+}
+
+/// A collection of named [`Profile`]s.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ProfileStore {
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl ProfileStore {
+    /// The location of the profile store in the user configuration directory.
+    ///
+    /// # Errors
+    /// An error is returned if the user configuration directory cannot be determined.
+    pub fn default_path() -> Result<PathBuf> {
+        let Some(dir) = dirs::config_dir() else {
+            return Err(InstrumentError::Other(
+                "unable to determine the user configuration directory".to_string(),
+            ));
+        };
+        Ok(dir.join(CONFIG_DIR_NAME).join(PROFILE_FILE_NAME))
+    }
+
+    /// Load the profile store from [`ProfileStore::default_path`].
+    ///
+    /// # Errors
+    /// See [`ProfileStore::load`].
+    pub fn load_default() -> Result<Self> {
+        Self::load(Self::default_path()?)
+    }
+
+    /// Load the profile store from the given path. A missing file is treated as an
+    /// empty store.
+    ///
+    /// # Errors
+    /// An error is returned if the file exists but cannot be read or parsed.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let contents = match std::fs::read_to_string(path.as_ref()) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Save the profile store to [`ProfileStore::default_path`].
+    ///
+    /// # Errors
+    /// See [`ProfileStore::save`].
+    pub fn save_default(&self) -> Result<()> {
+        self.save(Self::default_path()?)
+    }
+
+    /// Save the profile store to the given path, creating any missing parent
+    /// directories.
+    ///
+    /// # Errors
+    /// An error is returned if the store cannot be serialized or written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Get the profile with the given name.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+
+    /// Add a profile with the given name, returning the profile it replaced, if any.
+    pub fn insert(&mut self, name: impl Into<String>, profile: Profile) -> Option<Profile> {
+        self.profiles.insert(name.into(), profile)
+    }
+
+    /// Remove the profile with the given name, returning it if it existed.
+    pub fn remove(&mut self, name: &str) -> Option<Profile> {
+        self.profiles.remove(name)
+    }
+
+    /// Iterate over all the profiles in the store, ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Profile)> {
+        self.profiles.iter().map(|(n, p)| (n.as_str(), p))
+    }
+
+    /// Iterate over all the profiles that have the given tag, ordered by name.
+    pub fn tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = (&'a str, &'a Profile)> {
+        self.iter().filter(move |(_, p)| p.has_tag(tag))
+    }
+
+    /// Connect to the instrument with the given profile name.
+    ///
+    /// # Errors
+    /// [`InstrumentError::UnknownProfile`] if there is no profile with the given name,
+    /// otherwise see [`Profile::connect`].
+    pub fn connect(&self, name: &str) -> Result<Box<dyn Instrument>> {
+        let Some(profile) = self.get(name) else {
+            return Err(InstrumentError::UnknownProfile {
+                name: name.to_string(),
+            });
+        };
+        profile.connect()
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::time::Duration;
+
+    use crate::{
+        instrument::authenticate::Authentication, interface::connect_options::ConnectOptions,
+    };
+
+    use super::{Profile, ProfileStore};
+
+    fn store() -> ProfileStore {
+        let mut store = ProfileStore::default();
+        store.insert(
+            "bench-smu",
+            Profile {
+                connection: "192.168.0.10".parse().unwrap(),
+                fallbacks: vec!["TCPIP0::192.168.0.10::inst0::INSTR".parse().unwrap()],
+                tags: vec!["bench".to_string(), "smu".to_string()],
+                options: ConnectOptions {
+                    connect_timeout: Some(Duration::from_secs(2)),
+                    ..ConnectOptions::default()
+                },
+                auth: Authentication::Keyring {
+                    id: "2461#04331961".to_string(),
+                },
+            },
+        );
+        store.insert(
+            "rack-dmm",
+            Profile {
+                tags: vec!["rack".to_string()],
+                ..Profile::new("192.168.0.20:5025".parse().unwrap())
+            },
+        );
+        store
+    }
+
+    #[test]
+    fn store_round_trip() {
+        let expected = store();
+
+        let json = serde_json::to_string(&expected).expect("store should serialize");
+        let actual: ProfileStore = serde_json::from_str(&json).expect("store should deserialize");
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn store_save_load() {
+        let path = std::env::temp_dir()
+            .join(format!("tsp-toolkit-profiles-{}", std::process::id()))
+            .join("profiles.json");
+        let expected = store();
+
+        expected.save(&path).expect("store should be saved");
+        let actual = ProfileStore::load(&path).expect("store should be loaded");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn load_missing_store_is_empty() {
+        let path = std::env::temp_dir().join("tsp-toolkit-profiles-missing/profiles.json");
+
+        let actual = ProfileStore::load(path).expect("missing store should load");
+
+        assert_eq!(actual, ProfileStore::default());
+    }
+
+    #[test]
+    fn profile_defaults() {
+        let actual: Profile = serde_json::from_str(r#"{"connection": "192.168.0.1"}"#)
+            .expect("minimal profile should deserialize");
+
+        assert_eq!(actual, Profile::new("192.168.0.1:5025".parse().unwrap()));
+    }
+
+    #[test]
+    fn tagged_profiles() {
+        let store = store();
+
+        let actual: Vec<&str> = store.tagged("smu").map(|(n, _)| n).collect();
+
+        assert_eq!(actual, vec!["bench-smu"]);
+    }
+
+    #[test]
+    fn plaintext_credentials_are_not_serialized() {
+        let profile = Profile {
+            auth: Authentication::Credential {
+                username: "admin".to_string(),
+                password: "secret".to_string(),
+            },
+            ..Profile::new("192.168.0.1".parse().unwrap())
+        };
+
+        assert!(serde_json::to_string(&profile).is_err());
+    }
+}
@@ -0,0 +1,396 @@
+//! Reading and writing IEEE 488.2 arbitrary blocks.
+//!
+//! A definite-length block is `#<n><len><data>`, where `<n>` is the number of digits
+//! in `<len>`. An indefinite-length block is `#0<data>` and is ended by the message
+//! terminator. Both are usually followed by a newline.
+//!
+//! [`BlockReader`] reads a block from any [`Read`] (including non-blocking
+//! [`Interface`](crate::Interface)s that return [`ErrorKind::WouldBlock`]) without
+//! holding the whole block in memory, so large transfers can be streamed to a file.
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    time::Duration,
+};
+
+use crate::{error::Result, InstrumentError};
+
+/// The default largest block that will be read, in bytes.
+pub const DEFAULT_MAX_BLOCK_SIZE: usize = 256 * 1024 * 1024;
+
+/// The largest block that can be written with a definite-length header, which only
+/// allows up to 9 digits for the length.
+pub const MAX_DEFINITE_LEN: usize = 999_999_999;
+
+/// The number of attempts to make when reading without receiving any data.
+const READ_ATTEMPTS: usize = 500;
+
+/// The number of attempts to make when reading the terminator after a block.
+const TERMINATOR_ATTEMPTS: usize = 10;
+
+/// The delay between attempts when reading.
+const READ_DELAY: Duration = Duration::from_millis(10);
+
+/// The length of a block, as given by its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockLength {
+    /// A `#<n><len>` header with the given length in bytes.
+    Definite(usize),
+    /// A `#0` header.
+    Indefinite,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Header,
+    Data { remaining: usize },
+    Indefinite { read: usize },
+    Terminator,
+    Done,
+}
+
+/// Reads a single block from `inner`, returning only the data of the block from
+/// [`Read::read`].
+///
+/// Any bytes before the `#` of the header are skipped. The newline after the block,
+/// if any, is consumed.
+#[derive(Debug)]
+#[allow(clippy::module_name_repetitions)]
+pub struct BlockReader<R> {
+    inner: R,
+    max_size: usize,
+    indefinite_len: Option<usize>,
+    length: Option<BlockLength>,
+    state: State,
+}
+
+impl<R: Read> BlockReader<R> {
+    /// Create a reader for the next block from `inner`.
+    pub const fn new(inner: R) -> Self {
+        Self {
+            inner,
+            max_size: DEFAULT_MAX_BLOCK_SIZE,
+            indefinite_len: None,
+            length: None,
+            state: State::Header,
+        }
+    }
+
+    /// Set the largest block that will be read. Reading a larger block is an error.
+    #[must_use]
+    pub const fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Set the length of the data if the block turns out to be indefinite-length.
+    ///
+    /// Without this, an indefinite-length block ends at the first newline, which is
+    /// only correct for data that can't contain a newline (e.g. text).
+    #[must_use]
+    pub const fn with_indefinite_len(mut self, len: usize) -> Self {
+        self.indefinite_len = Some(len);
+        self
+    }
+
+    /// Read the header of the block, if it hasn't been read yet, and return the
+    /// length it gives.
+    ///
+    /// # Errors
+    /// Any IO errors, or [`InstrumentError::BlockError`] if the header is malformed,
+    /// the block is larger than the maximum size or nothing was received in time.
+    pub fn length(&mut self) -> Result<BlockLength> {
+        if let Some(length) = self.length {
+            return Ok(length);
+        }
+        let mut byte = [0u8];
+        loop {
+            self.read_inner(&mut byte, READ_ATTEMPTS)?;
+            if byte[0] == b'#' {
+                break;
+            }
+        }
+        self.read_inner(&mut byte, READ_ATTEMPTS)?;
+        if !byte[0].is_ascii_digit() {
+            return Err(block_error(format!(
+                "invalid header digit '{}'",
+                char::from(byte[0])
+            )));
+        }
+        let digits = usize::from(byte[0].saturating_sub(b'0'));
+        let (length, state) = if digits == 0 {
+            let state = self
+                .indefinite_len
+                .map_or(State::Indefinite { read: 0 }, |remaining| State::Data {
+                    remaining,
+                });
+            (BlockLength::Indefinite, state)
+        } else {
+            let mut len = vec![0u8; digits];
+            for b in &mut len {
+                self.read_inner(std::slice::from_mut(b), READ_ATTEMPTS)?;
+            }
+            let len = std::str::from_utf8(&len)
+                .ok()
+                .and_then(|l| l.parse::<usize>().ok())
+                .ok_or_else(|| {
+                    block_error(format!(
+                        "invalid length '{}'",
+                        String::from_utf8_lossy(&len)
+                    ))
+                })?;
+            (BlockLength::Definite(len), State::Data { remaining: len })
+        };
+        if let State::Data { remaining } = state {
+            self.check_size(remaining)?;
+        }
+        self.length = Some(length);
+        self.state = state;
+        Ok(length)
+    }
+
+    /// Read the whole block into memory.
+    ///
+    /// # Errors
+    /// See [`BlockReader::length`].
+    pub fn read_to_vec(mut self) -> Result<Vec<u8>> {
+        let mut data = match self.length()? {
+            BlockLength::Definite(len) => Vec::with_capacity(len),
+            BlockLength::Indefinite => Vec::with_capacity(self.indefinite_len.unwrap_or(0)),
+        };
+        let mut chunk = vec![0u8; 64 * 1024];
+        loop {
+            let n = self.read_data(&mut chunk)?;
+            if n == 0 {
+                return Ok(data);
+            }
+            data.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    /// Get back the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn read_data(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.state {
+                State::Header => {
+                    self.length()?;
+                }
+                State::Data { remaining: 0 } => self.state = State::Terminator,
+                State::Data { remaining } => {
+                    let n = buf.len().min(remaining);
+                    let n = self.read_inner(&mut buf[..n], READ_ATTEMPTS)?;
+                    self.state = State::Data {
+                        remaining: remaining.saturating_sub(n),
+                    };
+                    return Ok(n);
+                }
+                State::Indefinite { read } => {
+                    // Read one byte at a time so nothing after the terminating
+                    // newline is taken from `inner`.
+                    let mut n = 0usize;
+                    let mut ended = false;
+                    for b in buf.iter_mut() {
+                        self.read_inner(std::slice::from_mut(b), READ_ATTEMPTS)?;
+                        if *b == b'\n' {
+                            ended = true;
+                            break;
+                        }
+                        n = n.saturating_add(1);
+                        self.check_size(read.saturating_add(n))?;
+                    }
+                    let read = read.saturating_add(n);
+                    self.state = if ended {
+                        State::Done
+                    } else {
+                        State::Indefinite { read }
+                    };
+                    return Ok(n);
+                }
+                State::Terminator => {
+                    let mut byte = [0u8];
+                    while self.read_inner(&mut byte, TERMINATOR_ATTEMPTS).is_ok() {
+                        if byte[0] == b'\n' {
+                            break;
+                        }
+                    }
+                    self.state = State::Done;
+                }
+                State::Done => return Ok(0),
+            }
+        }
+    }
+
+    /// Read at least one byte, retrying up to `attempts` times while no data is
+    /// available.
+    fn read_inner(&mut self, buf: &mut [u8], attempts: usize) -> Result<usize> {
+        for _ in 0..attempts {
+            match self.inner.read(buf) {
+                Ok(n) if n > 0 => return Ok(n),
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+            std::thread::sleep(READ_DELAY);
+        }
+        Err(block_error("timed out waiting for data".to_string()))
+    }
+
+    fn check_size(&self, len: usize) -> Result<()> {
+        if len > self.max_size {
+            return Err(block_error(format!(
+                "a block of {len} bytes is larger than the maximum of {} bytes",
+                self.max_size
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for BlockReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.read_data(buf).map_err(|e| match e {
+            InstrumentError::IoError { source } => source,
+            e => std::io::Error::new(ErrorKind::InvalidData, e),
+        })
+    }
+}
+
+const fn block_error(details: String) -> InstrumentError {
+    InstrumentError::BlockError { details }
+}
+
+/// Read a single block from `rw` into memory.
+///
+/// # Errors
+/// See [`BlockReader::length`].
+pub fn read_block<R: Read + ?Sized>(rw: &mut R, max_size: usize) -> Result<Vec<u8>> {
+    BlockReader::new(rw).with_max_size(max_size).read_to_vec()
+}
+
+/// The definite-length header for a block of `len` bytes.
+///
+/// # Errors
+/// [`InstrumentError::BlockError`] if `len` is larger than [`MAX_DEFINITE_LEN`].
+pub fn header(len: usize) -> Result<String> {
+    if len > MAX_DEFINITE_LEN {
+        return Err(block_error(format!(
+            "a block of {len} bytes is too large for a definite-length header"
+        )));
+    }
+    let len = len.to_string();
+    Ok(format!("#{}{len}", len.len()))
+}
+
+/// Encode `data` as a definite-length block.
+///
+/// # Errors
+/// See [`header`].
+pub fn encode(data: &[u8]) -> Result<Vec<u8>> {
+    let mut block = header(data.len())?.into_bytes();
+    block.extend_from_slice(data);
+    Ok(block)
+}
+
+/// Write `data` to `rw` as a definite-length block.
+///
+/// # Errors
+/// See [`header`], or any IO errors.
+pub fn write_block<W: Write + ?Sized>(rw: &mut W, data: &[u8]) -> Result<()> {
+    rw.write_all(&encode(data)?)?;
+    Ok(())
+}
+
+/// Write `data` to `rw` as an indefinite-length block followed by a newline.
+///
+/// # Errors
+/// Any IO errors.
+pub fn write_indefinite_block<W: Write + ?Sized>(rw: &mut W, data: &[u8]) -> Result<()> {
+    rw.write_all(b"#0")?;
+    rw.write_all(data)?;
+    rw.write_all(b"\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit {
+    use std::io::Read;
+
+    use crate::{test_util::FakeInstrument, InstrumentError};
+
+    use super::{encode, header, read_block, BlockLength, BlockReader};
+
+    /// Returns at most `step` bytes of `data` per read, alternating with
+    /// `WouldBlock`.
+    fn trickle(data: &[u8], step: usize) -> FakeInstrument {
+        let fake = FakeInstrument::new().with_read_size(step).with_stalls();
+        fake.push_output(data);
+        fake
+    }
+
+    #[test]
+    fn definite_blocks() {
+        assert_eq!(header(0).unwrap(), "#10");
+        assert_eq!(header(1234).unwrap(), "#41234");
+        assert!(header(1_000_000_000).is_err());
+
+        let data: Vec<u8> = (0..=255).cycle().take(1_000).collect();
+        let mut wire = b"TSP>".to_vec();
+        wire.extend(encode(&data).unwrap());
+        wire.extend(b"\nnext\n");
+        let mut rw = trickle(&wire, 37);
+
+        let mut reader = BlockReader::new(&mut rw);
+        assert_eq!(reader.length().unwrap(), BlockLength::Definite(1_000));
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+        assert_eq!(rw.pending_output(), b"next\n");
+    }
+
+    #[test]
+    fn indefinite_blocks() {
+        let mut rw = trickle(b"#0hello\n", 3);
+        assert_eq!(read_block(&mut rw, 100).unwrap(), b"hello");
+
+        // The block and the next line can arrive in a single read.
+        let mut rw = FakeInstrument::new();
+        rw.push_output(b"#0hello\nnext\n");
+        assert_eq!(read_block(&mut rw, 100).unwrap(), b"hello");
+        assert_eq!(rw.pending_output(), b"next\n");
+
+        // Binary data can contain newlines, so the length must be known.
+        let mut rw = trickle(b"#0a\nb\n", 3);
+        let read = BlockReader::new(&mut rw)
+            .with_indefinite_len(3)
+            .read_to_vec()
+            .unwrap();
+        assert_eq!(read, b"a\nb");
+    }
+
+    #[test]
+    fn malformed_and_oversized_blocks() {
+        assert!(matches!(
+            read_block(&mut trickle(b"#x", 1), 100),
+            Err(InstrumentError::BlockError { .. })
+        ));
+        assert!(matches!(
+            read_block(&mut trickle(b"#2ab", 1), 100),
+            Err(InstrumentError::BlockError { .. })
+        ));
+        assert!(matches!(
+            read_block(&mut trickle(b"#3101", 1), 100),
+            Err(InstrumentError::BlockError { .. })
+        ));
+        assert!(matches!(
+            read_block(&mut trickle(b"#0aaaaaaaaaa", 4), 5),
+            Err(InstrumentError::BlockError { .. })
+        ));
+    }
+}
@@ -1,10 +1,13 @@
-use crate::interface::connection_addr::ConnectionInfo;
-use crate::protocol::raw::Raw;
+use crate::interface::{connect_options::ConnectOptions, connection_addr::ConnectionInfo};
+use crate::{
+    instrument::status::StatusByte,
+    protocol::{block::BlockReader, raw::Raw},
+};
 use std::{
+    borrow::Cow,
     error::Error,
     fmt::Display,
     io::{Read, Write},
-    net::TcpStream,
     time::Duration,
 };
 
@@ -88,6 +91,7 @@ pub mod visa;
 #[cfg(feature = "visa")]
 use crate::protocol::visa::Visa;
 
+pub mod block;
 pub mod raw;
 
 pub enum Protocol {
@@ -104,31 +108,92 @@ impl Protocol {
         Self::Raw(Raw::new(interface))
     }
 
-    /// Connects to the appropriate interface given a connection
+    /// Set the largest message that will be written to the instrument at once. This
+    /// only applies to VISA connections since raw sockets don't need to be chunked.
+    #[allow(unused_variables)]
+    pub const fn set_write_chunk_size(&mut self, size: usize) {
+        match self {
+            Self::Raw(_) => {}
+
+            #[cfg(feature = "visa")]
+            Self::Visa(v) => v.set_write_chunk_size(size),
+        }
+    }
+
+    /// Read an IEEE 488.2 block that is no larger than `max_size` bytes.
+    ///
+    /// # Errors
+    /// See [`BlockReader::length`].
+    pub fn read_block(&mut self, max_size: usize) -> Result<Vec<u8>, InstrumentError> {
+        block::read_block(self, max_size)
+    }
+
+    /// A [`BlockReader`] to stream the next IEEE 488.2 block from the instrument.
+    pub const fn block_reader(&mut self) -> BlockReader<&mut Self> {
+        BlockReader::new(self)
+    }
+
+    /// Write `data` to the instrument as an IEEE 488.2 definite-length block.
     ///
     /// # Errors
-    /// The errors that can occur are from each of the connection types: [`TcpStream`]
+    /// See [`block::header`], or any IO errors.
+    pub fn write_block(&mut self, data: &[u8]) -> Result<(), InstrumentError> {
+        let block = block::encode(data)?;
+        match self {
+            Self::Raw(r) => r.write_all(&block)?,
+
+            #[cfg(feature = "visa")]
+            Self::Visa(v) => {
+                for chunk in block.chunks(v.write_chunk_size().max(1)) {
+                    v.write_all(chunk)?;
+                }
+            }
+        }
+        self.flush()?;
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for the instrument to request service and return the
+    /// status byte that was read when it did. VISA connections wait for a service
+    /// request event; raw socket connections poll the status byte on their status
+    /// connection ([`ConnectOptions::status_port`]).
+    ///
+    /// # Errors
+    /// [`InstrumentError::Timeout`] if the instrument didn't request service in
+    /// time, or any errors from reading the status byte.
+    pub fn wait_for_srq(&mut self, timeout: Duration) -> Result<StatusByte, InstrumentError> {
+        match self {
+            Self::Raw(r) => r.wait_for_srq(timeout),
+
+            #[cfg(feature = "visa")]
+            Self::Visa(v) => v.wait_for_srq(timeout),
+        }
+    }
+
+    /// Connects to the appropriate interface given a connection and the options to
+    /// use while connecting.
+    ///
+    /// # Errors
+    /// The errors that can occur are from each of the connection types: [`std::net::TcpStream`]
     /// and [`Visa`]
-    pub fn connect(info: &ConnectionInfo) -> Result<Self, InstrumentError> {
+    pub fn connect(
+        info: &ConnectionInfo,
+        options: &ConnectOptions,
+    ) -> Result<Self, InstrumentError> {
         #[allow(unused_variables)]
         match info {
-            ConnectionInfo::Lan { addr } => {
-                let stream = TcpStream::connect(addr)?;
-                stream.set_nonblocking(true)?;
-                stream.set_write_timeout(Some(Duration::from_millis(1000)))?;
-                stream.set_read_timeout(Some(Duration::from_millis(1000)))?;
-                Ok(Self::Raw(Raw::new(stream)))
-            }
+            ConnectionInfo::Lan { addr } => Ok(Self::Raw(Raw::connect(addr, options)?)),
             ConnectionInfo::Vxi11 { string, .. }
             | ConnectionInfo::HiSlip { string, .. }
             | ConnectionInfo::Usb { string, .. }
             | ConnectionInfo::Gpib { string, .. }
+            | ConnectionInfo::Asrl { string, .. }
             | ConnectionInfo::VisaSocket { string, .. } => {
                 #[cfg(feature = "visa")]
                 {
                     use crate::interface::NonBlock;
 
-                    let mut visa = Visa::new(string)?;
+                    let mut visa = Visa::new(string, options)?;
                     visa.set_nonblocking(true)?;
                     Ok(Self::Visa(visa))
                 }
@@ -152,11 +217,48 @@ pub trait ReadStb {
     }
 }
 
-pub trait Clear {
-    type Error: Display + Error;
+pub trait Clear: Write {
+    type Error: Display + Error + From<InstrumentError>;
+
+    /// Clear the status registers and the error queue with `*CLS`. This doesn't
+    /// affect the input or output of the instrument.
+    ///
+    /// # Errors
+    /// The errors returned must be of, or convertible to the type `Self::Error`.
+    fn clear_status(&mut self) -> core::result::Result<(), Self::Error> {
+        self.write_all(b"*CLS\n").map_err(InstrumentError::from)?;
+        self.flush().map_err(InstrumentError::from)?;
+        Ok(())
+    }
+
+    /// Clear the input and output of the instrument and abort any pending I/O, so an
+    /// instrument that is blocked (e.g. on output that is never read) responds
+    /// again.
+    ///
+    /// VISA connections keep their session. Raw socket connections have to close
+    /// every LAN session of the instrument (including those of other clients) and
+    /// reconnect, which loses the login, so [`DeviceClear::Reconnected`] is
+    /// returned and the instrument must be logged into again (see
+    /// [`Raw::device_clear`]).
+    ///
+    /// The default returns [`InstrumentError::Other`], since a device clear depends
+    /// on the connection.
+    ///
     /// # Errors
     /// The errors returned must be of, or convertible to the type `Self::Error`.
-    fn clear(&mut self) -> core::result::Result<(), Self::Error>;
+    fn device_clear(&mut self) -> core::result::Result<DeviceClear, Self::Error> {
+        Err(InstrumentError::Other("device clear is not supported".to_string()).into())
+    }
+}
+
+/// What a [`Clear::device_clear`] did to the session with the instrument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceClear {
+    /// The session, and its login, was kept.
+    SessionKept,
+    /// The session was closed and a new one opened, so the instrument must be
+    /// logged into again.
+    Reconnected,
 }
 
 pub trait Trigger {
@@ -178,9 +280,31 @@ impl Read for Protocol {
     }
 }
 
+/// Replace `text` with a placeholder if it sends credentials to the instrument
+/// (`login`, `password` or an assignment to `localnode.password`), so they aren't
+/// logged.
+pub(crate) fn redact(text: &str) -> Cow<'_, str> {
+    let login = text
+        .lines()
+        .map(str::trim_start)
+        .any(|l| l.starts_with("login") || l.starts_with("password"));
+    let assignment = text
+        .split("localnode.password")
+        .skip(1)
+        .any(|rest| !rest.starts_with("mode"));
+    if login || assignment {
+        Cow::Borrowed("<credentials redacted>")
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
 impl Write for Protocol {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        trace!("writing to instrument: '{}'", String::from_utf8_lossy(buf));
+        trace!(
+            "writing to instrument: '{}'",
+            redact(&String::from_utf8_lossy(buf))
+        );
         match self {
             Self::Raw(r) => r.write(buf),
 
@@ -205,7 +329,7 @@ impl Write for Protocol {
     }
 
     fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
-        // fit as much into a 1000-byte message as possible (For USBTMC)
+        // fit as much into a single message as possible (For USBTMC)
 
         let mut start: usize = 0;
 
@@ -213,8 +337,7 @@ impl Write for Protocol {
             Self::Raw(_) => buf.len(),
 
             #[cfg(feature = "visa")]
-            Self::Visa(_) => 1000, //TODO Need a way to make this 4500 for Treb and 1000 for
-                                   //everything else.
+            Self::Visa(v) => v.write_chunk_size(),
         };
         let mut end: usize = if start.saturating_add(step) < buf.len() {
             start.saturating_add(step)
@@ -239,7 +362,7 @@ impl Write for Protocol {
         };
 
         while end < buf.len().saturating_sub(1) {
-            //Here we are trusting that a single line will not be more than 1000-bytes long
+            //Here we are trusting that a single line will not be longer than a chunk
             let mut last_newline = end;
             // if the file is NOT a ZIP file, look for lines, otherwise, just obey chunking
             if buf[0..4] != [0x50, 0x4B, 0x03, 0x04] {
@@ -285,15 +408,20 @@ impl Write for Protocol {
 
 impl Clear for Protocol {
     type Error = InstrumentError;
-    fn clear(&mut self) -> core::result::Result<(), Self::Error> {
+
+    fn device_clear(&mut self) -> core::result::Result<DeviceClear, Self::Error> {
         match self {
-            Self::Raw(r) => r.write_all(b"*CLS\n")?,
+            Self::Raw(r) => {
+                r.device_clear()?;
+                Ok(DeviceClear::Reconnected)
+            }
 
             #[cfg(feature = "visa")]
-            Self::Visa(v) => v.clear()?,
+            Self::Visa(v) => {
+                v.clear()?;
+                Ok(DeviceClear::SessionKept)
+            }
         }
-
-        Ok(())
     }
 }
 
@@ -301,7 +429,7 @@ impl ReadStb for Protocol {
     type Error = InstrumentError;
     fn read_stb(&mut self) -> core::result::Result<stb::Stb, Self::Error> {
         match self {
-            Self::Raw(_) => Ok(stb::Stb::NotSupported),
+            Self::Raw(r) => r.read_stb(),
 
             #[cfg(feature = "visa")]
             Self::Visa(v) => Ok(stb::Stb::Stb(v.read_stb()?)),
@@ -328,9 +456,40 @@ impl Trigger for Protocol {
 
 #[cfg(test)]
 mod unit {
-    use std::assert_matches::assert_matches;
+    use std::{assert_matches::assert_matches, io::Write};
+
+    use crate::{
+        protocol::{stb::Stb, Clear},
+        test_util::FakeInstrument,
+        InstrumentError,
+    };
+
+    /// Only uses the defaults of [`Clear`].
+    struct Cleared(FakeInstrument);
+
+    impl Write for Cleared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.write(buf)
+        }
 
-    use crate::protocol::stb::Stb;
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl Clear for Cleared {
+        type Error = InstrumentError;
+    }
+
+    #[test]
+    fn clear_defaults() {
+        let fake = FakeInstrument::new();
+        let mut cleared = Cleared(fake.clone());
+
+        cleared.clear_status().unwrap();
+        assert_eq!(fake.written(), "*CLS\n");
+        assert_matches!(cleared.device_clear(), Err(InstrumentError::Other(_)));
+    }
 
     #[test]
     fn stb_test_mav() {
@@ -1,12 +1,313 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    collections::VecDeque,
+    io::{ErrorKind, Read, Write},
+    ops::{Deref, DerefMut},
+    time::{Duration, Instant},
+};
 
-use crate::Interface;
+use crate::{
+    error::Result,
+    instrument::{read_response, status::StatusByte},
+    interface::{connect_options::ConnectOptions, host::HostAddr},
+    protocol::stb::Stb,
+    InstrumentError, Interface,
+};
 
-pub struct Raw(Box<dyn Interface>);
+/// How long to wait for the response to `*STB?`.
+const STB_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The longest start of a written line that is kept to recognize script loading.
+const LINE_START_LEN: usize = 32;
+
+/// The delay between reads of the status byte while waiting for a service request.
+const SRQ_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long to wait after connecting to the dead socket termination port, so the
+/// instrument has closed its sessions before reconnecting.
+const DEVICE_CLEAR_SETTLE: Duration = Duration::from_millis(100);
+
+/// The number of attempts to reconnect after a device clear.
+const RECONNECT_ATTEMPTS: usize = 5;
+
+/// The delay between attempts to reconnect after a device clear.
+const RECONNECT_DELAY: Duration = Duration::from_millis(200);
+
+/// The summary bits of the status byte, and the query that reads (and so clears)
+/// the event register each one summarizes.
+const SUMMARIZED_EVENTS: [(StatusByte, &str); 5] = [
+    (
+        StatusByte::MEASUREMENT_SUMMARY,
+        "print(status.measurement.event)",
+    ),
+    (StatusByte::SYSTEM_SUMMARY, "print(status.system.event)"),
+    (
+        StatusByte::QUESTIONABLE_SUMMARY,
+        "print(status.questionable.event)",
+    ),
+    (StatusByte::EVENT_SUMMARY, "*ESR?"),
+    (
+        StatusByte::OPERATION_SUMMARY,
+        "print(status.operation.event)",
+    ),
+];
+
+pub struct Raw {
+    interface: Box<dyn Interface>,
+    control: Option<Box<dyn Interface>>,
+    /// The address and options the connection was opened with, which are needed to
+    /// reconnect after a device clear.
+    lan: Option<Box<(HostAddr, ConnectOptions)>>,
+    /// Output that was waiting when the status byte was read in-band, which is
+    /// returned by the next reads.
+    held: VecDeque<u8>,
+    /// The start of the line that is being written.
+    line: Vec<u8>,
+    /// Whether a script is being loaded, so anything written becomes part of it.
+    loading_script: bool,
+}
 
 impl Raw {
     pub fn new(interface: impl Interface + 'static) -> Self {
-        Self(Box::new(interface))
+        Self {
+            interface: Box::new(interface),
+            control: None,
+            lan: None,
+            held: VecDeque::new(),
+            line: Vec::new(),
+            loading_script: false,
+        }
+    }
+
+    /// Open a raw socket connection to `addr`, and a control connection if
+    /// [`ConnectOptions::status_port`] is set.
+    ///
+    /// # Errors
+    /// See [`ConnectOptions::connect_host`].
+    pub fn connect(addr: &HostAddr, options: &ConnectOptions) -> Result<Self> {
+        let stream = options.connect_host(addr)?;
+        stream.set_nonblocking(true)?;
+        let mut raw = Self::new(stream);
+        if let Some(port) = options.status_port {
+            let control = options.connect_host(&HostAddr::new(addr.host.clone(), port))?;
+            control.set_nonblocking(true)?;
+            raw = raw.with_control(control);
+        }
+        raw.lan = Some(Box::new((addr.clone(), options.clone())));
+        Ok(raw)
+    }
+
+    /// Use `control` to read the status byte with `*STB?`. This is a separate
+    /// connection to the instrument (see [`ConnectOptions::status_port`]).
+    #[must_use]
+    pub fn with_control(mut self, control: impl Interface + 'static) -> Self {
+        self.control = Some(Box::new(control));
+        self
+    }
+
+    /// Read the status byte with `*STB?`, which TSP instruments accept in both TSP
+    /// and SCPI mode.
+    ///
+    /// The query is sent on the control connection, if there is one (see
+    /// [`Raw::with_control`]). Otherwise it is sent in-band, on this connection,
+    /// which is only done while the session is idle:
+    ///
+    /// - While a script is being loaded the query would become part of the script,
+    ///   so [`InstrumentError::Other`] is returned instead.
+    /// - Output that is already waiting is held back and returned by the next
+    ///   reads, so it isn't mixed up with the response.
+    /// - While a script or measurement runs the response is held back by the
+    ///   instrument, so the query times out and its response is read as output
+    ///   later. Only read the status byte in-band between commands.
+    ///
+    /// # Errors
+    /// Any IO errors, [`InstrumentError::Other`] while a script is being loaded, or
+    /// [`InstrumentError::InformationRetrievalError`] if the status byte wasn't read
+    /// in time or couldn't be parsed.
+    pub fn read_stb(&mut self) -> Result<Stb> {
+        if self.control.is_none() {
+            if self.loading_script {
+                return Err(InstrumentError::Other(
+                    "the status byte can't be read in-band while a script is being loaded"
+                        .to_string(),
+                ));
+            }
+            self.hold_output()?;
+        }
+        let control = self
+            .control
+            .as_mut()
+            .map_or_else(|| self.interface.as_mut(), Box::as_mut);
+        let Some(response) = query(control, "*STB?", STB_TIMEOUT)? else {
+            return Err(InstrumentError::InformationRetrievalError {
+                details: "timed out reading the status byte".to_string(),
+            });
+        };
+        Ok(Stb::Stb(parse_stb(&response)?))
+    }
+
+    /// Keep the output that is waiting on this connection for the next reads.
+    fn hold_output(&mut self) -> Result<()> {
+        let mut buf = [0u8; 512];
+        loop {
+            match self.interface.read(&mut buf) {
+                Ok(n) if n > 0 => self.held.extend(&buf[..n]),
+                Ok(_) => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Follow the lines that are written, to know whether a script is being loaded.
+    fn track_written(&mut self, buf: &[u8]) {
+        for &b in buf {
+            if b == b'\n' {
+                let line = String::from_utf8_lossy(&self.line);
+                let line = line.trim();
+                if line.starts_with("loadscript") || line.starts_with("loadandrunscript") {
+                    self.loading_script = true;
+                } else if line == "endscript" {
+                    self.loading_script = false;
+                }
+                self.line.clear();
+            } else if self.line.len() < LINE_START_LEN {
+                self.line.push(b);
+            }
+        }
+    }
+
+    /// Clear the instrument so it responds again, even if it is blocked on output
+    /// that is never read.
+    ///
+    /// Raw sockets have no device clear message, so this connects to the dead socket
+    /// termination port ([`ConnectOptions::device_clear_port`]) and then reconnects,
+    /// retrying while the instrument is still closing its sessions. This has
+    /// effects beyond this connection:
+    ///
+    /// - Every LAN session of the instrument is closed, including those of other
+    ///   clients and of other connections in this process.
+    /// - The new session isn't logged in, so an instrument with a password must be
+    ///   logged into again.
+    /// - Any output that was waiting is discarded.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if this connection wasn't opened with
+    /// [`Raw::connect`], or any errors from connecting.
+    pub fn device_clear(&mut self) -> Result<()> {
+        let Some((addr, options)) = self.lan.as_deref().cloned() else {
+            return Err(InstrumentError::Other(
+                "device clear requires a connection opened with Raw::connect".to_string(),
+            ));
+        };
+        let terminate = HostAddr::new(addr.host.clone(), options.device_clear_port);
+        drop(options.connect_host(&terminate)?);
+        std::thread::sleep(DEVICE_CLEAR_SETTLE);
+        let mut result = Self::connect(&addr, &options);
+        for _ in 1..RECONNECT_ATTEMPTS {
+            if result.is_ok() {
+                break;
+            }
+            std::thread::sleep(RECONNECT_DELAY);
+            result = Self::connect(&addr, &options);
+        }
+        *self = result?;
+        Ok(())
+    }
+
+    /// Wait for the instrument to request service by polling the status byte on the
+    /// control connection until MSS is set. A status byte that isn't read in time
+    /// (e.g. while the instrument is busy) counts as no service request yet.
+    ///
+    /// The status byte keeps requesting service until the event registers of its
+    /// set summary bits are read, so they are read (and so cleared) before
+    /// returning. EAV and MAV stay set until the error and output queues are read.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Other`] if there is no control connection to read the
+    /// status byte on, [`InstrumentError::Timeout`] if the instrument didn't request
+    /// service in time, or any IO errors.
+    pub fn wait_for_srq(&mut self, timeout: Duration) -> Result<StatusByte> {
+        let Some(control) = self.control.as_mut() else {
+            return Err(InstrumentError::Other(
+                "service requests on raw socket connections need a status connection \
+                 (ConnectOptions::status_port)"
+                    .to_string(),
+            ));
+        };
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            let remaining = deadline.map_or(STB_TIMEOUT, |d| {
+                d.saturating_duration_since(Instant::now()).min(STB_TIMEOUT)
+            });
+            if let Some(response) = query(control.as_mut(), "*STB?", remaining)? {
+                let stb = StatusByte(parse_stb(&response)?.to_le_bytes()[0]);
+                if stb.contains(StatusByte::MASTER_SUMMARY) {
+                    for (bit, event) in SUMMARIZED_EVENTS {
+                        if stb.contains(bit) {
+                            query(control.as_mut(), event, STB_TIMEOUT)?;
+                        }
+                    }
+                    return Ok(stb);
+                }
+            }
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Err(InstrumentError::Timeout {
+                    details: "a service request".to_string(),
+                });
+            }
+            std::thread::sleep(SRQ_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Send `command` on `control` and read the first line of the response, or [`None`]
+/// if it didn't arrive within `timeout`. Any late response to an earlier query is
+/// discarded first.
+fn query(control: &mut dyn Interface, command: &str, timeout: Duration) -> Result<Option<String>> {
+    let mut stale = [0u8; 512];
+    loop {
+        match control.read(&mut stale) {
+            Ok(n) if n > 0 => {}
+            Ok(_) => break,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    control.write_all(format!("{command}\n").as_bytes())?;
+    control.flush()?;
+    read_response(control, timeout, |r| {
+        r.split_once('\n').map(|(line, _)| line.to_string())
+    })
+}
+
+fn parse_stb(s: &str) -> Result<u16> {
+    let s = s.trim();
+    s.parse::<f64>()
+        .ok()
+        .and_then(|v| format!("{v:.0}").parse::<u16>().ok())
+        .ok_or_else(|| InstrumentError::InformationRetrievalError {
+            details: format!("unable to parse the status byte from '{s}'"),
+        })
+}
+
+impl Read for Raw {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.held.is_empty() {
+            return self.interface.read(buf);
+        }
+        self.held.read(buf)
+    }
+}
+
+impl Write for Raw {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.interface.write(buf)?;
+        self.track_written(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.interface.flush()
     }
 }
 
@@ -14,12 +315,141 @@ impl Deref for Raw {
     type Target = Box<dyn Interface>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.interface
     }
 }
 
 impl DerefMut for Raw {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.interface
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::{
+        io::{Read, Write},
+        net::{IpAddr, TcpListener},
+        time::Duration,
+    };
+
+    use crate::{
+        instrument::status::StatusByte,
+        interface::{connect_options::ConnectOptions, host::HostAddr},
+        protocol::{stb::Stb, Clear, DeviceClear, Protocol},
+        test_util::FakeInstrument,
+        InstrumentError,
+    };
+
+    use super::Raw;
+
+    /// Responds to `*STB?` with `condition`, a few bytes at a time.
+    fn fake(condition: &'static str) -> FakeInstrument {
+        FakeInstrument::new()
+            .with_read_size(4)
+            .with_responder(move |command| {
+                (command == "*STB?").then(|| format!("{condition}\n").into_bytes())
+            })
+    }
+
+    #[test]
+    fn in_band_stb() {
+        let main = fake("64");
+        main.push_output(b"output\n");
+        let mut raw = Raw::new(main.clone());
+
+        assert_eq!(raw.read_stb().unwrap(), Stb::Stb(0x40));
+        assert_eq!(main.written(), "*STB?\n");
+        let mut output = [0u8; 7];
+        raw.read_exact(&mut output).unwrap();
+        assert_eq!(&output, b"output\n");
+
+        // The query would become part of the script.
+        raw.write_all(b"loadscript test\nprint(1)\n").unwrap();
+        assert!(matches!(raw.read_stb(), Err(InstrumentError::Other(_))));
+        raw.write_all(b"endscript\n").unwrap();
+        assert_eq!(raw.read_stb().unwrap(), Stb::Stb(0x40));
+        assert_eq!(main.commands().iter().filter(|c| *c == "*STB?").count(), 2);
+
+        assert!(matches!(
+            raw.wait_for_srq(Duration::from_millis(10)),
+            Err(InstrumentError::Other(_))
+        ));
+    }
+
+    #[test]
+    fn control_connection_stb() {
+        let main = FakeInstrument::new();
+        main.push_output(b"output\n");
+        let mut raw = Raw::new(main.clone()).with_control(fake("6.40000e+01"));
+
+        assert_eq!(raw.read_stb().unwrap(), Stb::Stb(0x40));
+        assert_eq!(main.pending_output(), b"output\n");
+        assert_eq!(main.written(), "");
+    }
+
+    #[test]
+    fn device_clear_reconnects() {
+        let main = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dead_socket = TcpListener::bind("127.0.0.1:0").unwrap();
+        let options = ConnectOptions {
+            device_clear_port: dead_socket.local_addr().unwrap().port(),
+            ..ConnectOptions::default()
+        };
+        let addr = HostAddr::new(
+            IpAddr::from([127, 0, 0, 1]),
+            main.local_addr().unwrap().port(),
+        );
+        let mut protocol = Protocol::Raw(Raw::connect(&addr, &options).unwrap());
+        let _first = main.accept().unwrap();
+
+        assert_eq!(protocol.device_clear().unwrap(), DeviceClear::Reconnected);
+        let _terminate = dead_socket.accept().unwrap();
+        let (mut second, _) = main.accept().unwrap();
+        protocol.write_all(b"*IDN?\n").unwrap();
+        let mut buf = [0u8; 6];
+        second.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"*IDN?\n");
+
+        assert!(Raw::new(FakeInstrument::new()).device_clear().is_err());
+    }
+
+    #[test]
+    fn wait_for_srq_polls_stb() {
+        // Requests service (MSS) for a standard event (ESB) until `*ESR?` is read.
+        let mut stb = 0x60;
+        let requesting = FakeInstrument::new().with_responder(move |command| match command {
+            "*STB?" => Some(format!("{stb}\n").into_bytes()),
+            "*ESR?" => {
+                stb = 0;
+                Some(b"1\n".to_vec())
+            }
+            _ => None,
+        });
+        let mut raw = Raw::new(FakeInstrument::new()).with_control(requesting.clone());
+        assert_eq!(
+            raw.wait_for_srq(Duration::from_millis(50)).unwrap(),
+            StatusByte(0x60)
+        );
+        assert!(requesting.commands().contains(&"*ESR?".to_string()));
+        assert!(matches!(
+            raw.wait_for_srq(Duration::from_millis(50)),
+            Err(InstrumentError::Timeout { .. })
+        ));
+
+        let idle = fake("0");
+        let mut raw = Raw::new(FakeInstrument::new()).with_control(idle);
+        assert!(matches!(
+            raw.wait_for_srq(Duration::from_millis(50)),
+            Err(InstrumentError::Timeout { .. })
+        ));
+
+        // An instrument that is too busy to answer hasn't requested service yet.
+        let busy = FakeInstrument::new();
+        let mut raw = Raw::new(FakeInstrument::new()).with_control(busy);
+        assert!(matches!(
+            raw.wait_for_srq(Duration::from_millis(50)),
+            Err(InstrumentError::Timeout { .. })
+        ));
     }
 }
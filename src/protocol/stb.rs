@@ -1,6 +1,6 @@
 use crate::InstrumentError;
 
-use crate::error::Result;
+use crate::{error::Result, instrument::status::StatusByte};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Stb {
@@ -111,4 +111,30 @@ impl Stb {
             )),
         }
     }
+
+    /// Check to see if the OSB bit is set
+    ///
+    /// # Errors
+    /// An error is returned if `read_stb` is not supported.
+    pub fn operation_summary(&self) -> Result<bool> {
+        match self {
+            Self::Stb(s) => Ok(Self::is_bit_set(*s, 7)),
+            Self::NotSupported => Err(InstrumentError::Other(
+                "read_stb() not supported".to_string(),
+            )),
+        }
+    }
+
+    /// The status byte, with each bit named.
+    ///
+    /// # Errors
+    /// An error is returned if `read_stb` is not supported.
+    pub fn status_byte(&self) -> Result<StatusByte> {
+        match self {
+            Self::Stb(s) => Ok(StatusByte(s.to_le_bytes()[0])),
+            Self::NotSupported => Err(InstrumentError::Other(
+                "read_stb() not supported".to_string(),
+            )),
+        }
+    }
 }
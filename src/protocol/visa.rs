@@ -1,16 +1,36 @@
 use std::{
     io::{Read, Write},
     ops::{Deref, DerefMut},
+    time::Duration,
 };
 
-use visa_rs::{flags::AccessMode, AsResourceManager, VisaString, TIMEOUT_INFINITE};
+use visa_rs::{
+    enums::{
+        attribute::{AttrTmoValue, HasAttribute},
+        event::{EventKind, Mechanism},
+        status::ErrorCode,
+    },
+    flags::AccessMode,
+    AsResourceManager, VisaString, TIMEOUT_INFINITE,
+};
 
-use crate::{interface::NonBlock, protocol::stb::Stb, InstrumentError, Interface};
+use crate::{
+    instrument::status::StatusByte,
+    interface::{
+        connect_options::{ConnectOptions, LockMode},
+        NonBlock,
+    },
+    model::capabilities::Capabilities,
+    protocol::stb::Stb,
+    InstrumentError, Interface,
+};
 
 pub struct Visa {
     _rm: visa_rs::DefaultRM,
     inst: visa_rs::Instrument,
     nonblocking: bool,
+    write_chunk_size: usize,
+    srq_enabled: bool,
 }
 
 impl Visa {
@@ -18,22 +38,85 @@ impl Visa {
     ///
     /// # Errors
     /// Errors can occur when creating the [`DefaultRM`], creating the [`VisaString`],
-    /// and opening the [`visa_rs::Instrument`]
-    pub fn new(resource_string: &str) -> Result<Self, InstrumentError> {
+    /// opening the [`visa_rs::Instrument`] and applying the [`ConnectOptions`]
+    pub fn new(resource_string: &str, options: &ConnectOptions) -> Result<Self, InstrumentError> {
         let rm = visa_rs::DefaultRM::new()?;
         let Some(resource_string) = VisaString::from_string(resource_string.to_string()) else {
             return Err(InstrumentError::VisaParseError(format!(
                 "VISA unable to parse '{resource_string}' as resource string"
             )));
         };
-        let inst: visa_rs::Instrument =
-            rm.open(&resource_string, AccessMode::NO_LOCK, TIMEOUT_INFINITE)?;
+        let inst: visa_rs::Instrument = rm.open(
+            &resource_string,
+            access_mode(options.lock_mode),
+            options.visa_open_timeout.unwrap_or(TIMEOUT_INFINITE),
+        )?;
+        if let Some(timeout) = options.visa_timeout {
+            let Some(timeout) = timeout
+                .as_millis()
+                .try_into()
+                .ok()
+                .and_then(AttrTmoValue::new_checked)
+            else {
+                return Err(InstrumentError::VisaParseError(format!(
+                    "{timeout:?} is not a valid VISA timeout"
+                )));
+            };
+            inst.set_attr(timeout)?;
+        }
         Ok(Self {
             _rm: rm,
             inst,
             nonblocking: true,
+            write_chunk_size: Capabilities::DEFAULT.usb_write_chunk,
+            srq_enabled: false,
         })
     }
+
+    /// The largest message that will be written to the instrument at once.
+    #[must_use]
+    pub const fn write_chunk_size(&self) -> usize {
+        self.write_chunk_size
+    }
+
+    /// Set the largest message that will be written to the instrument at once.
+    pub const fn set_write_chunk_size(&mut self, size: usize) {
+        self.write_chunk_size = size;
+    }
+
+    /// Wait for a VISA service request event, then read the status byte so VISA
+    /// keeps delivering them. Service request events are queued from the first call
+    /// on, and this also covers HiSLIP asynchronous service requests.
+    ///
+    /// # Errors
+    /// [`InstrumentError::Timeout`] if the instrument didn't request service in
+    /// time, or any VISA errors.
+    pub fn wait_for_srq(&mut self, timeout: Duration) -> Result<StatusByte, InstrumentError> {
+        if !self.srq_enabled {
+            self.inst
+                .enable_event(EventKind::EventServiceReq, Mechanism::Queue)?;
+            self.srq_enabled = true;
+        }
+        match self.inst.wait_on_event(EventKind::EventServiceReq, timeout) {
+            Ok(_) => {}
+            Err(e) if ErrorCode::from(e) == ErrorCode::ErrorTmo => {
+                return Err(InstrumentError::Timeout {
+                    details: "a service request".to_string(),
+                });
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(StatusByte(self.inst.read_stb()?.to_le_bytes()[0]))
+    }
+}
+
+/// Convert a [`LockMode`] into the equivalent VISA [`AccessMode`].
+pub(crate) const fn access_mode(lock_mode: LockMode) -> AccessMode {
+    match lock_mode {
+        LockMode::None => AccessMode::NO_LOCK,
+        LockMode::Exclusive => AccessMode::EXCLUSIVE_LOCK,
+        LockMode::Shared => AccessMode::SHARED_LOCK,
+    }
 }
 
 impl NonBlock for Visa {
@@ -0,0 +1,209 @@
+//! A fake instrument for unit tests that records what is written to it and answers
+//! the way an instrument would.
+
+use std::{
+    collections::VecDeque,
+    io::{ErrorKind, Read, Write},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::interface::{Interface, NonBlock};
+
+type Responder = Box<dyn FnMut(&str) -> Option<Vec<u8>> + Send>;
+
+#[derive(Default)]
+struct State {
+    written: String,
+    output: VecDeque<u8>,
+    lines: Vec<String>,
+    replies: VecDeque<Vec<u8>>,
+    responder: Option<Responder>,
+    read_size: Option<usize>,
+    stalls: bool,
+    stalled: bool,
+    log: Option<(String, Arc<Mutex<Vec<String>>>)>,
+}
+
+/// A fake instrument that records everything written to it. Each write is answered
+/// with what the instrument would print:
+///
+/// 1. whatever the responder (see [`FakeInstrument::with_responder`]) returns for
+///    the command,
+/// 2. otherwise, for a query (TSP ending with `print("<marker>")`, or SCPI ending
+///    with `?`), the next queued reply (see [`FakeInstrument::with_replies`]), or
+///    the lines given to [`FakeInstrument::with_lines`] for TSP queries,
+///
+/// followed by the marker itself, which is how `query_tsp` and `wait_complete` know
+/// the output is complete. The command is never echoed back.
+///
+/// Clones share their state, so a clone can be given to a
+/// [`Protocol`](crate::protocol::Protocol) and the original inspected afterwards.
+#[derive(Clone, Default)]
+pub struct FakeInstrument(Arc<Mutex<State>>);
+
+impl FakeInstrument {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.0.lock().expect("fake instrument should lock")
+    }
+
+    /// Answer every TSP query (other than `waitcomplete()`) with these lines.
+    pub fn with_lines(self, lines: &[&str]) -> Self {
+        self.state().lines = lines.iter().map(ToString::to_string).collect();
+        self
+    }
+
+    /// Answer the next queries with these replies, in order.
+    pub fn with_replies(self, replies: &[&[u8]]) -> Self {
+        self.state()
+            .replies
+            .extend(replies.iter().map(|r| r.to_vec()));
+        self
+    }
+
+    /// Answer commands (without the `print("<marker>")` of a TSP query) for which
+    /// `respond` returns [`Some`] with its output.
+    pub fn with_responder(
+        self,
+        respond: impl FnMut(&str) -> Option<Vec<u8>> + Send + 'static,
+    ) -> Self {
+        self.state().responder = Some(Box::new(respond));
+        self
+    }
+
+    /// Return at most `size` bytes from each read to exercise partial reads.
+    pub fn with_read_size(self, size: usize) -> Self {
+        self.state().read_size = Some(size);
+        self
+    }
+
+    /// Fail every other read with [`ErrorKind::WouldBlock`], as a slow connection
+    /// would.
+    pub fn with_stalls(self) -> Self {
+        self.state().stalls = true;
+        self
+    }
+
+    /// Also add each command (other than `waitcomplete()`) to `log`, prefixed with
+    /// `name`, to check the order of commands across instruments.
+    pub fn with_log(self, name: &str, log: &Arc<Mutex<Vec<String>>>) -> Self {
+        self.state().log = Some((name.to_string(), Arc::clone(log)));
+        self
+    }
+
+    /// Queue output as if the instrument had printed it.
+    pub fn push_output(&self, output: &[u8]) {
+        self.state().output.extend(output);
+    }
+
+    /// The output that hasn't been read yet.
+    pub fn pending_output(&self) -> Vec<u8> {
+        self.state().output.iter().copied().collect()
+    }
+
+    /// Everything that was written.
+    pub fn written(&self) -> String {
+        self.state().written.clone()
+    }
+
+    /// The commands that were written, one per line, without `waitcomplete()`.
+    pub fn commands(&self) -> Vec<String> {
+        self.state()
+            .written
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with("waitcomplete()"))
+            .map(ToString::to_string)
+            .collect()
+    }
+}
+
+fn is_scpi_query(command: &str) -> bool {
+    command
+        .split(';')
+        .any(|c| c.split_whitespace().next().unwrap_or("").ends_with('?'))
+}
+
+impl Read for FakeInstrument {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut state = self.state();
+        state.stalled = state.stalls && !state.stalled;
+        if state.stalled || state.output.is_empty() {
+            drop(state);
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        let n = buf
+            .len()
+            .min(state.output.len())
+            .min(state.read_size.unwrap_or(usize::MAX));
+        for (b, o) in buf.iter_mut().zip(state.output.drain(..n)) {
+            *b = o;
+        }
+        drop(state);
+        Ok(n)
+    }
+}
+
+impl Write for FakeInstrument {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = String::from_utf8_lossy(buf).into_owned();
+        let mut state = self.state();
+        state.written.push_str(&written);
+        if let Some((name, log)) = &state.log {
+            let mut log = log.lock().expect("log should lock");
+            log.extend(
+                written
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty() && !l.starts_with("waitcomplete()"))
+                    .map(|l| format!("{name}: {l}")),
+            );
+        }
+
+        let command = written.trim();
+        let (command, marker) = command
+            .rsplit_once(" print(\"")
+            .map_or((command, None), |(command, marker)| {
+                (command, Some(marker.trim_end_matches("\")")))
+            });
+
+        let mut reply = state.responder.as_mut().and_then(|r| r(command));
+        if reply.is_none() && command != "waitcomplete()" {
+            if marker.is_some() || is_scpi_query(command) {
+                reply = state.replies.pop_front();
+            }
+            if reply.is_none() && marker.is_some() {
+                reply = Some(
+                    state
+                        .lines
+                        .iter()
+                        .flat_map(|l| format!("{l}\n").into_bytes())
+                        .collect(),
+                );
+            }
+        }
+        if let Some(reply) = reply {
+            state.output.extend(reply);
+        }
+        if let Some(marker) = marker {
+            state.output.extend(format!("{marker}\n").as_bytes());
+        }
+        drop(state);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl NonBlock for FakeInstrument {
+    fn set_nonblocking(&mut self, _: bool) -> crate::error::Result<()> {
+        Ok(())
+    }
+}
+
+impl Interface for FakeInstrument {}
@@ -0,0 +1,63 @@
+//! Capture the tracing output of a test.
+
+use std::{
+    fmt::{Debug, Write},
+    sync::{Arc, Mutex},
+};
+
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Metadata, Subscriber,
+};
+
+/// Records the fields of every span and event.
+struct Capture(Arc<Mutex<String>>);
+
+struct Fields<'a>(&'a mut String);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        let _ = write!(self.0, "{}={value:?} ", field.name());
+    }
+}
+
+impl Capture {
+    fn record(&self, record: impl FnOnce(&mut Fields<'_>)) {
+        let mut logs = self.0.lock().expect("logs should lock");
+        record(&mut Fields(&mut logs));
+        logs.push('\n');
+    }
+}
+
+impl Subscriber for Capture {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+        self.record(|fields| span.record(fields));
+        span::Id::from_u64(1)
+    }
+
+    fn record(&self, _: &span::Id, values: &span::Record<'_>) {
+        self.record(|fields| values.record(fields));
+    }
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        self.record(|fields| event.record(fields));
+    }
+
+    fn enter(&self, _: &span::Id) {}
+
+    fn exit(&self, _: &span::Id) {}
+}
+
+/// Run `f` and return everything it logged on this thread.
+pub fn capture_logs(f: impl FnOnce()) -> String {
+    let logs = Arc::new(Mutex::new(String::new()));
+    tracing::subscriber::with_default(Capture(Arc::clone(&logs)), f);
+    let logs = logs.lock().expect("logs should lock").clone();
+    logs
+}
@@ -4,3 +4,28 @@ pub const _SIMPLE_FAKE_BINARY_CHUNK1: &[u8] = include_bytes!("./simple_fake_bina
 pub const _SIMPLE_FAKE_BINARY_CHUNK2: &[u8] = include_bytes!("./simple_fake_binary_fw.chunk2");
 pub const _SIMPLE_FAKE_BINARY_CHUNK3: &[u8] = include_bytes!("./simple_fake_binary_fw.chunk3");
 pub const SIMPLE_FAKE_TEXTUAL_FW: &[u8] = include_bytes!("./simple_fake_textual_fw.test");
+
+mod fake;
+mod logs;
+pub use fake::FakeInstrument;
+pub use logs::capture_logs;
+
+/// Set up a [`mockall`] interface mock to answer
+/// [`wait_complete`](crate::instrument::wait_complete) the way an instrument would,
+/// by printing only the marker of each `waitcomplete() print("<marker>")` it is sent.
+macro_rules! expect_wait_complete {
+    ($interface:expr) => {{
+        let mut input = $crate::test_util::FakeInstrument::new();
+        let mut output = input.clone();
+        $interface
+            .expect_write()
+            .times(..)
+            .withf(|buf: &[u8]| buf.starts_with(b"waitcomplete()"))
+            .returning(move |buf: &[u8]| ::std::io::Write::write(&mut input, buf));
+        $interface
+            .expect_read()
+            .times(..)
+            .returning(move |buf: &mut [u8]| ::std::io::Read::read(&mut output, buf));
+    }};
+}
+pub(crate) use expect_wait_complete;